@@ -1,23 +1,53 @@
 
 use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures_util::SinkExt;
 use axum::{
-    extract::State,
-    http::{header, HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path as UrlPath, State,
+    },
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     routing::{get, post},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::StreamExt;
 use tower::ServiceBuilder;
 use tower_http::cors::CorsLayer;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use crate::helper::{load_text_to_speech, load_voice_style, timer};
+use crate::engine_pool::{CheckoutError, EngineHandle};
+use crate::scheduler::{PriorityClass, PriorityWeights, Ticket};
+use crate::inference::InferenceWorkers;
+use crate::helper::{
+    blend_voice_styles, drain_complete_sentences, encode_audio, input_segments, load_text_to_speech,
+    load_voice_style, normalize_mix_weights, save_voice_style, timer, voice_style_stem, AudioFormat, AudioStreamEncoder,
+    ChunkSettings, EncoderSettings, SpeechChunk, SplitStrategy, Style, StyleShapes, TextSegment, TextToSpeech,
+    UnicodeProcessor, UnknownCharPolicy, UnsupportedChar, VoiceStyleData, VoiceStyles, WavSampleFormat,
+};
+use crate::lexicon::{Lexicon, LexiconEntries, LexiconEntry};
+use crate::markup::InputFormat;
+use crate::normalizer::{normalizer_for, DEFAULT_LANGUAGE};
+use crate::segmenter::{is_valid_language, Segmenter, Segmenters};
+use crate::voices::{default_voices, is_valid_voice_name, VoiceEntry, VoiceRegistry};
+
+/// Number of sentences a WebSocket session may queue ahead of synthesis
+const WS_JOB_QUEUE_CAPACITY: usize = 32;
+
+/// Most voices a voice mix may combine
+const MAX_VOICE_MIX: usize = 8;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
@@ -39,21 +69,141 @@ pub struct TtsSettings {
     pub use_gpu: bool,
     pub total_step: usize,
     pub speed: f32,
+    /// Voice used when a request does not name one: a style file or a voice
+    /// ID or alias
     pub default_voice_style: String,
+    /// Directory of voice style files; registry paths are relative to it
+    #[serde(default = "default_voices_dir")]
+    pub voices_dir: String,
+    /// Voice registry: IDs, aliases, style files and metadata
+    #[serde(default = "default_voices")]
+    pub voices: Vec<VoiceEntry>,
+    /// JSON list of the voices uploaded through the API, created on the
+    /// first upload
+    #[serde(default)]
+    pub uploaded_voices_path: Option<String>,
     #[serde(default = "default_engine_pool_size")]
     pub engine_pool_size: usize,
     #[serde(default = "default_warmup_on_startup")]
     pub warmup_on_startup: bool,
+    /// Longest a request waits for a pooled engine before failing
     #[serde(default = "default_engine_checkout_timeout_ms")]
     pub engine_checkout_timeout_ms: u64,
+    /// Most requests that may wait for a pooled engine; further requests
+    /// get 429
+    #[serde(default = "default_max_queue_depth")]
+    pub max_queue_depth: usize,
+    /// Share of the pooled engines each priority class gets while both are
+    /// waiting
+    #[serde(default)]
+    pub priority_weights: PriorityWeights,
+    /// Consecutive failures after which a pooled engine is replaced
+    #[serde(default = "default_engine_failure_threshold")]
+    pub engine_failure_threshold: u32,
+    /// Seconds between probes that synthesize a short text on every pooled
+    /// engine; no probes when unset
+    #[serde(default)]
+    pub engine_probe_interval_secs: Option<u64>,
     #[serde(default = "default_voice_style_cache_size")]
     pub voice_style_cache_size: usize,
+    /// Threads that run inference, apart from the async runtime's; defaults
+    /// to one per pool engine
+    #[serde(default)]
+    pub inference_workers: Option<usize>,
+    #[serde(default = "default_mp3_bitrate")]
+    pub mp3_bitrate: u32,
+    #[serde(default = "default_opus_bitrate")]
+    pub opus_bitrate: u32,
+    /// JSON pronunciation lexicon, created on the first edit through the API
+    #[serde(default)]
+    pub lexicon_path: Option<String>,
+    /// Handling of characters the model cannot read
+    #[serde(default)]
+    pub unknown_char_policy: UnknownCharPolicy,
+    /// Text substituted for unreadable characters by the `replace` policy,
+    /// and by `transliterate` when no transliteration exists
+    #[serde(default = "default_unknown_char_replacement")]
+    pub unknown_char_replacement: String,
+    /// Language of requests that do not set one, e.g. "en" or "de"
+    #[serde(default = "default_language")]
+    pub language: String,
+    /// JSON file of extra abbreviations per language for sentence splitting
+    #[serde(default)]
+    pub abbreviations_path: Option<String>,
+    /// How input is written when the request does not say
+    #[serde(default)]
+    pub input_format: InputFormat,
+    /// Spoken in place of code blocks in Markdown and HTML input; code blocks
+    /// are skipped when unset
+    #[serde(default)]
+    pub code_block_phrase: Option<String>,
+    /// Longest text chunk synthesized in one pass, in characters
+    #[serde(default = "default_max_chunk_chars")]
+    pub max_chunk_chars: usize,
+    /// Shorter chunks are merged with a neighbour when they fit
+    #[serde(default)]
+    pub min_chunk_chars: usize,
+    #[serde(default)]
+    pub split_strategy: SplitStrategy,
+    /// Silence between chunks of a paragraph, in seconds
+    #[serde(default = "default_sentence_silence")]
+    pub sentence_silence: f32,
+    /// Silence between paragraphs, in seconds
+    #[serde(default = "default_paragraph_silence")]
+    pub paragraph_silence: f32,
+}
+
+impl TtsSettings {
+    pub fn encoder_settings(&self) -> EncoderSettings {
+        EncoderSettings {
+            mp3_bitrate: self.mp3_bitrate,
+            opus_bitrate: self.opus_bitrate,
+            sample_rate: None,
+        }
+    }
+
+    pub fn inference_worker_count(&self) -> usize {
+        self.inference_workers.unwrap_or(self.engine_pool_size.max(1))
+    }
+
+    /// The configured voices plus those uploaded through the API
+    pub fn voice_registry(&self) -> Result<VoiceRegistry> {
+        let mut registry = VoiceRegistry::new(&self.voices_dir, self.voices.clone())?;
+        if let Some(path) = &self.uploaded_voices_path {
+            registry.load_uploaded(path)?;
+        }
+        Ok(registry)
+    }
+
+    pub fn chunk_settings(&self, segmenter: Arc<Segmenter>) -> ChunkSettings {
+        ChunkSettings {
+            max_chunk_chars: self.max_chunk_chars,
+            min_chunk_chars: self.min_chunk_chars,
+            strategy: self.split_strategy,
+            sentence_silence: self.sentence_silence,
+            paragraph_silence: self.paragraph_silence,
+            segmenter,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthSettings {
     pub require_api_key: bool,
     pub api_key: Option<String>,
+    /// Further API keys, each scheduled as a client of its own
+    #[serde(default)]
+    pub keys: Vec<ApiKey>,
+}
+
+/// A named API key; pooled engines are shared fairly between keys
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    pub key: String,
+    /// Class of the key's requests that send no X-Priority header
+    #[serde(default)]
+    pub priority: Option<PriorityClass>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,7 +216,17 @@ pub struct LoggingSettings {
 fn default_engine_pool_size() -> usize { 1 }
 fn default_warmup_on_startup() -> bool { false }
 fn default_engine_checkout_timeout_ms() -> u64 { 5000 }
+fn default_max_queue_depth() -> usize { 32 }
+fn default_engine_failure_threshold() -> u32 { 3 }
 fn default_voice_style_cache_size() -> usize { 10 }
+fn default_mp3_bitrate() -> u32 { 128 }
+fn default_opus_bitrate() -> u32 { 64 }
+fn default_unknown_char_replacement() -> String { " ".to_string() }
+fn default_language() -> String { DEFAULT_LANGUAGE.to_string() }
+fn default_max_chunk_chars() -> usize { 300 }
+fn default_voices_dir() -> String { "assets/voice_styles".to_string() }
+fn default_sentence_silence() -> f32 { 0.3 }
+fn default_paragraph_silence() -> f32 { 0.3 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
@@ -81,14 +241,37 @@ impl Default for ServerConfig {
                 total_step: 5,
                 speed: 1.05,
                 default_voice_style: "assets/voice_styles/M1.json".to_string(),
+                voices_dir: default_voices_dir(),
+                voices: default_voices(),
+                uploaded_voices_path: None,
                 engine_pool_size: 1,
                 warmup_on_startup: false,
                 engine_checkout_timeout_ms: 5000,
+                max_queue_depth: default_max_queue_depth(),
+                priority_weights: PriorityWeights::default(),
+                engine_failure_threshold: default_engine_failure_threshold(),
+                engine_probe_interval_secs: None,
                 voice_style_cache_size: 10,
+                inference_workers: None,
+                mp3_bitrate: 128,
+                opus_bitrate: 64,
+                lexicon_path: None,
+                unknown_char_policy: UnknownCharPolicy::default(),
+                unknown_char_replacement: default_unknown_char_replacement(),
+                language: default_language(),
+                abbreviations_path: None,
+                input_format: InputFormat::default(),
+                code_block_phrase: None,
+                max_chunk_chars: default_max_chunk_chars(),
+                min_chunk_chars: 0,
+                split_strategy: SplitStrategy::default(),
+                sentence_silence: default_sentence_silence(),
+                paragraph_silence: default_paragraph_silence(),
             },
             auth: AuthSettings {
                 require_api_key: false,
                 api_key: None,
+                keys: Vec::new(),
             },
             logging: LoggingSettings {
                 level: "info".to_string(),
@@ -121,16 +304,51 @@ impl ServerConfig {
 
 #[derive(Debug, Deserialize)]
 pub struct TtsRequest {
-    /// Text to synthesize
+    /// Text to synthesize, or an SSML document starting with `<speak>`
     pub input: String,
+    /// How `input` is written: "text" (plain text or SSML), "markdown" or
+    /// "html" (default: server setting)
+    pub input_format: Option<String>,
+    /// Language of `input`, e.g. "en" or "de-AT"; selects the sentence
+    /// splitting rules and the normalizer (default: server setting)
+    pub language: Option<String>,
     /// Voice model to use (default: "supertts")
     pub model: Option<String>,
-    /// Voice style (OpenAI uses "voice" parameter)
-    pub voice: Option<String>,
+    /// Voice style (OpenAI uses "voice" parameter): a voice name or style
+    /// file, or a weighted mix such as `{"mix": [["M1", 0.7], ["F1", 0.3]]}`
+    pub voice: Option<VoiceParam>,
     /// Speech speed (0.25 to 4.0)
     pub speed: Option<f32>,
-    /// Response format (default: "wav", only "wav" supported)
+    /// Response format: "wav" (default), "wav_s24", "wav_f32", "wav_ulaw", "wav_alaw",
+    /// "pcm", "ulaw", "alaw", "flac", "mp3" or "opus"
     pub response_format: Option<String>,
+    /// Output sample rate in Hz: 8000, 16000, 22050, 24000, 44100 or 48000
+    /// (default: the model's rate; the telephony formats are always 8000)
+    pub sample_rate: Option<u32>,
+    /// Spell out numbers, currency, dates, times, percentages and units
+    /// before synthesis (default: true)
+    pub normalize: Option<bool>,
+    /// Pronunciation overrides for this request, applied before the server
+    /// lexicon: pattern -> respelling, or pattern -> entry with options
+    pub lexicon: Option<LexiconEntries>,
+    /// Characters the model cannot read: "strict" (reject with 400),
+    /// "transliterate", "strip" or "replace" (default: server setting)
+    pub unknown_char_policy: Option<String>,
+    /// Longest text chunk synthesized in one pass, in characters (10 to 1000)
+    pub max_chunk_chars: Option<usize>,
+    /// Merge chunks shorter than this many characters with a neighbour
+    pub min_chunk_chars: Option<usize>,
+    /// Where chunks start: "paragraph" (default), "sentence" or "clause"
+    pub split_strategy: Option<String>,
+    /// Silence between chunks of a paragraph, in seconds (0 to 5)
+    pub sentence_silence: Option<f32>,
+    /// Silence between paragraphs, in seconds (0 to 5)
+    pub paragraph_silence: Option<f32>,
+    /// Stream audio chunk by chunk as it is synthesized (default: false)
+    pub stream: Option<bool>,
+    /// Streaming delivery: "audio" (chunked audio body) or "sse" (Server-Sent Events).
+    /// Setting this implies `stream: true`.
+    pub stream_format: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -151,6 +369,8 @@ pub struct HealthResponse {
     pub timestamp: String,
     pub version: String,
     pub model_loaded: bool,
+    /// Threads that run inference
+    pub inference_workers: usize,
     pub pool_stats: Option<crate::engine_pool::PoolStatsResponse>,
 }
 
@@ -160,25 +380,68 @@ pub struct AppState {
     pub text_to_speech: Arc<Mutex<Option<crate::helper::TextToSpeech>>>, // Kept for backward compatibility
     pub default_voice_style: String,
     pub engine_pool: Option<Arc<crate::engine_pool::TTSEnginePool>>,
+    pub lexicon: Arc<RwLock<Lexicon>>,
+    /// The model's character set, used to check input before synthesis
+    pub text_processor: Option<Arc<UnicodeProcessor>>,
+    /// Sentence splitting rules per language
+    pub segmenters: Arc<Segmenters>,
+    /// Named voices; uploads and deletions through the API update it
+    pub voices: Arc<RwLock<VoiceRegistry>>,
+    /// Threads that synthesize, so that inference never blocks the runtime
+    pub inference: Arc<InferenceWorkers>,
+}
+
+/// The `voice` request parameter
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum VoiceParam {
+    /// A voice name or a path to a voice style file
+    Name(String),
+    /// Voices blended by weight; weights are scaled to sum to 1
+    Mix { mix: Vec<(String, f32)> },
+}
+
+impl VoiceParam {
+    /// Name for logs and the X-Voice-Used header, e.g. "M1" or "M1:0.7+F1:0.3"
+    pub fn label(&self) -> String {
+        match self {
+            VoiceParam::Name(name) => name.clone(),
+            VoiceParam::Mix { mix } => mix
+                .iter()
+                .map(|(name, weight)| format!("{}:{}", name, weight))
+                .collect::<Vec<_>>()
+                .join("+"),
+        }
+    }
 }
 
 // Voice Style Resolution Helper
-fn resolve_voice_style_path(voice_name: Option<&str>, default_path: &str) -> Result<String> {
-    // If no voice name provided, use default
+pub fn resolve_voice_style_path(
+    voices: &VoiceRegistry,
+    voice_name: Option<&str>,
+    default_path: &str,
+) -> Result<String> {
+    // If no voice name provided, use default (a style file or a voice name)
     let voice_name = match voice_name {
         Some(name) => name,
         None => {
             if Path::new(default_path).exists() {
                 return Ok(default_path.to_string());
-            } else {
-                return Err(anyhow!("Default voice style file not found: {}", default_path));
             }
+            if let Some(voice) = voices.find(default_path) {
+                let path = voices.path_of(voice);
+                if Path::new(&path).exists() {
+                    return Ok(path);
+                }
+            }
+            return Err(anyhow!("Default voice style file not found: {}", default_path));
         }
     };
 
-    // Direct file path support (if voice name contains .json or /)
-    if voice_name.contains(".json") || voice_name.contains("/") || voice_name.contains("\\") {
-        let direct_path = if voice_name.ends_with(".json") {
+    // Direct file path support (if voice name has a style file extension or /)
+    let has_extension = voice_style_stem(voice_name).is_some();
+    if has_extension || voice_name.contains("/") || voice_name.contains("\\") {
+        let direct_path = if has_extension {
             voice_name.to_string()
         } else {
             format!("{}.json", voice_name)
@@ -191,120 +454,157 @@ fn resolve_voice_style_path(voice_name: Option<&str>, default_path: &str) -> Res
         }
     }
 
-    // Standard voice name mappings
-    let voice_mappings = [
-        ("m1", "assets/voice_styles/M1.json"),
-        ("male1", "assets/voice_styles/M1.json"),
-        ("f1", "assets/voice_styles/F1.json"),
-        ("female1", "assets/voice_styles/F1.json"),
-        ("m2", "assets/voice_styles/M1.json"),
-        ("male2", "assets/voice_styles/M2.json"),
-        ("f2", "assets/voice_styles/F1.json"),
-        ("female2", "assets/voice_styles/F2.json"),
-    ];
-
-    let normalized_name = voice_name.to_lowercase();
-
-    // Try exact matches first
-    for (name, path) in &voice_mappings {
-        if normalized_name == *name {
-            if Path::new(path).exists() {
-                return Ok(path.to_string());
-            } else {
-                return Err(anyhow!("Voice style file not found for voice '{}': {}", name, path));
-            }
-        }
-    }
-
-    // Try partial matches
-    for (name, path) in &voice_mappings {
-        if normalized_name.contains(name) {
-            if Path::new(path).exists() {
-                return Ok(path.to_string());
-            } else {
-                return Err(anyhow!("Voice style file not found for voice '{}': {}", name, path));
-            }
-        }
-    }
-
-    // Try to find a file that matches the voice name in voice_styles directory
-    let voice_styles_dir = "assets/voice_styles";
-    if Path::new(voice_styles_dir).exists() {
-        if let Ok(entries) = std::fs::read_dir(voice_styles_dir) {
-            for entry in entries.flatten() {
-                if let Some(file_name) = entry.file_name().to_str() {
-                    if file_name.ends_with(".json") {
-                        let file_stem = file_name.trim_end_matches(".json").to_lowercase();
-                        if file_stem.contains(&normalized_name) || normalized_name.contains(&file_stem) {
-                            let full_path = format!("{}/{}", voice_styles_dir, file_name);
-                            return Ok(full_path);
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    // Default fallback - try M1.json first, then F1.json
-    let fallback_options = ["assets/voice_styles/F1.json", "assets/voice_styles/M1.json"];
-    for fallback in &fallback_options {
-        if Path::new(fallback).exists() {
-            warn!("Unknown voice '{}', falling back to: {}", voice_name, fallback);
-            return Ok(fallback.to_string());
+    // Registry IDs and aliases: the whole name, ignoring case
+    if let Some(voice) = voices.find(voice_name) {
+        let path = voices.path_of(voice);
+        if Path::new(&path).exists() {
+            return Ok(path);
+        } else {
+            return Err(anyhow!("Voice style file not found for voice '{}': {}", voice.id, path));
         }
     }
 
-    // No voice style files found
-    let available_voices = if Path::new(voice_styles_dir).exists() {
-        if let Ok(entries) = std::fs::read_dir(voice_styles_dir) {
-            let voices: Vec<String> = entries
+    // Other style files in the voice directory, by their whole name without
+    // extension, ignoring case
+    let voice_styles_dir = voices.dir();
+    let style_files: Vec<(String, String)> = std::fs::read_dir(voice_styles_dir)
+        .map(|entries| {
+            entries
                 .flatten()
                 .filter_map(|entry| {
-                    entry.file_name().to_str().and_then(|name| {
-                        if name.ends_with(".json") {
-                            Some(name.trim_end_matches(".json").to_string())
-                        } else {
-                            None
-                        }
-                    })
+                    let file_name = entry.file_name().to_str()?.to_string();
+                    let stem = voice_style_stem(&file_name)?.to_string();
+                    Some((stem, file_name))
                 })
-                .collect();
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some((_, file_name)) = style_files.iter().find(|(stem, _)| stem.eq_ignore_ascii_case(voice_name)) {
+        return Ok(Path::new(voice_styles_dir).join(file_name).to_string_lossy().into_owned());
+    }
 
-            if !voices.is_empty() {
-                format!("Available voice styles: {}", voices.join(", "))
-            } else {
-                "No voice style files found in assets/voice_styles/ directory".to_string()
-            }
-        } else {
-            "Unable to read voice styles directory".to_string()
+    // An unknown name never falls back to some other voice
+    let mut names: Vec<&str> = voices.voices().flat_map(VoiceEntry::names).collect();
+    for (stem, _) in &style_files {
+        if voices.find(stem).is_none() {
+            names.push(stem);
         }
-    } else {
-        "Voice styles directory 'assets/voice_styles/' does not exist".to_string()
-    };
-
-    Err(anyhow!(
-        "Unknown voice '{}' and no fallback available. {}",
-        voice_name,
-        available_voices
-    ))
+    }
+    if names.is_empty() {
+        return Err(anyhow!(
+            "Unknown voice '{}': no voices are configured and '{}' holds no voice style files",
+            voice_name,
+            voice_styles_dir
+        ));
+    }
+    Err(anyhow!("Unknown voice '{}'. Valid voice IDs and aliases: {}", voice_name, names.join(", ")))
 }
 // Authentication Middleware
-fn check_api_key(headers: &HeaderMap, config: &AuthSettings) -> Result<(), StatusCode> {
-    if !config.require_api_key {
-        return Ok(());
+/// The client a request was made by, as told by its API key
+#[derive(Debug, Clone)]
+struct Caller {
+    name: String,
+    priority: Option<PriorityClass>,
+}
+
+impl Caller {
+    fn anonymous() -> Self {
+        Caller {
+            name: "anonymous".to_string(),
+            priority: None,
+        }
     }
+}
 
-    let auth_header = headers
+/// The caller named by a `Bearer` token in the Authorization header, if it
+/// carries a configured key
+fn key_caller(headers: &HeaderMap, config: &AuthSettings) -> Option<Caller> {
+    let token = headers
         .get(header::AUTHORIZATION)
         .and_then(|h| h.to_str().ok())
-        .and_then(|h| h.strip_prefix("Bearer "));
+        .and_then(|h| h.strip_prefix("Bearer "))?;
+    if let Some(key) = config.keys.iter().find(|key| key.key == token) {
+        return Some(Caller {
+            name: key.name.clone(),
+            priority: key.priority,
+        });
+    }
+    if config.api_key.as_deref() == Some(token) {
+        return Some(Caller {
+            name: "default".to_string(),
+            priority: None,
+        });
+    }
+    None
+}
+
+fn check_api_key(headers: &HeaderMap, config: &AuthSettings) -> Result<Caller, StatusCode> {
+    // Keys also tell clients apart when they are not required
+    if let Some(caller) = key_caller(headers, config) {
+        return Ok(caller);
+    }
+
+    if config.require_api_key && (config.api_key.is_some() || !config.keys.is_empty()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(Caller::anonymous())
+}
 
-    match (auth_header, &config.api_key) {
-        (Some(token), Some(expected_token)) if token == expected_token => Ok(()),
-        (Some(_), Some(_)) => Err(StatusCode::UNAUTHORIZED),
-        (None, Some(_)) => Err(StatusCode::UNAUTHORIZED),
-        _ => Ok(()),
+/// Authenticate a request that changes files on the server. Unlike
+/// `check_api_key` it always needs a configured key, whatever
+/// `require_api_key` says.
+fn check_required_api_key(headers: &HeaderMap, config: &AuthSettings) -> Result<Caller, RequestError> {
+    if config.api_key.is_none() && config.keys.is_empty() {
+        return Err(RequestError {
+            status: StatusCode::FORBIDDEN,
+            message: "This endpoint changes files on the server and needs an API key: configure auth.api_key or auth.keys"
+                .to_string(),
+            type_: "permission_error",
+            code: "api_key_not_configured",
+        });
     }
+    key_caller(headers, config).ok_or_else(|| RequestError {
+        status: StatusCode::UNAUTHORIZED,
+        message: "Invalid or missing API key".to_string(),
+        type_: "authentication_error",
+        code: "invalid_api_key",
+    })
+}
+
+/// Schedule `cost` input characters for `caller`, in the class named by the
+/// X-Priority header, else by the caller's key, else interactive. The
+/// X-Queue-Timeout-Ms header shortens how long the request may queue.
+fn request_ticket(caller: &Caller, headers: &HeaderMap, cost: usize) -> Result<Ticket, RequestError> {
+    let priority = match headers.get("X-Priority") {
+        Some(value) => value.to_str().ok().and_then(PriorityClass::parse).ok_or_else(|| RequestError {
+            status: StatusCode::BAD_REQUEST,
+            message: format!(
+                "Invalid X-Priority header. Supported priorities: {}",
+                PriorityClass::SUPPORTED.join(", ")
+            ),
+            type_: "invalid_request_error",
+            code: "invalid_priority",
+        })?,
+        None => caller.priority.unwrap_or(PriorityClass::Interactive),
+    };
+    let max_wait = match headers.get("X-Queue-Timeout-Ms") {
+        Some(value) => {
+            let millis = value.to_str().ok().and_then(|value| value.trim().parse::<u64>().ok());
+            Some(Duration::from_millis(millis.ok_or_else(|| RequestError {
+                status: StatusCode::BAD_REQUEST,
+                message: "Invalid X-Queue-Timeout-Ms header: expected a whole number of milliseconds".to_string(),
+                type_: "invalid_request_error",
+                code: "invalid_queue_timeout",
+            })?))
+        }
+        None => None,
+    };
+    Ok(Ticket {
+        client: caller.name.clone(),
+        priority,
+        cost,
+        max_wait,
+    })
 }
 pub async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
     // Get pool stats if pool is available
@@ -319,6 +619,7 @@ pub async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
         timestamp: chrono::Utc::now().to_rfc3339(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         model_loaded: true, // We'll assume model is loaded if server is running
+        inference_workers: state.inference.workers(),
         pool_stats,
     };
 
@@ -333,63 +634,73 @@ pub struct VoicesResponse {
 
 #[derive(Debug, Serialize)]
 pub struct VoiceInfo {
+    /// Voice ID, or the file name for style files outside the registry
     pub name: String,
+    pub aliases: Vec<String>,
     pub path: String,
     pub exists: bool,
+    pub language: Option<String>,
+    pub gender: Option<String>,
+    pub description: Option<String>,
+    /// Speed used when a request does not set one
+    pub speed: Option<f32>,
+    /// Whether the voice was uploaded through the API (and can be deleted)
+    pub uploaded: bool,
+}
+
+fn voice_info(voices: &VoiceRegistry, voice: &VoiceEntry) -> VoiceInfo {
+    let path = voices.path_of(voice);
+    VoiceInfo {
+        name: voice.id.clone(),
+        aliases: voice.aliases.clone(),
+        exists: Path::new(&path).exists(),
+        path,
+        language: voice.language.clone(),
+        gender: voice.gender.clone(),
+        description: voice.description.clone(),
+        speed: voice.speed,
+        uploaded: voices.is_uploaded(&voice.id),
+    }
 }
 
-pub async fn list_voices() -> impl IntoResponse {
-    let voice_styles_dir = "assets/voice_styles";
+pub async fn list_voices(State(state): State<AppState>) -> impl IntoResponse {
+    let registry = state.voices.read().unwrap();
+    let voice_styles_dir = registry.dir();
     let mut voices = Vec::new();
+    let mut seen_files = std::collections::HashSet::new();
 
-    // Add standard voice mappings
-    let standard_voices = [
-        ("m1", "assets/voice_styles/M1.json"),
-        ("male1", "assets/voice_styles/M1.json"),
-        ("f1", "assets/voice_styles/F1.json"),
-        ("female1", "assets/voice_styles/F1.json"),
-        ("m2", "assets/voice_styles/M1.json"),
-        ("male2", "assets/voice_styles/M2.json"),
-        ("f2", "assets/voice_styles/F1.json"),
-        ("female2", "assets/voice_styles/F2.json"),
-    ];
-
-    for (name, path) in &standard_voices {
-        voices.push(VoiceInfo {
-            name: name.to_string(),
-            path: path.to_string(),
-            exists: Path::new(path).exists(),
-        });
+    // Registered voices first
+    for voice in registry.voices() {
+        let path = registry.path_of(voice);
+        seen_files.insert(Path::new(&path).canonicalize().unwrap_or_else(|_| PathBuf::from(&path)));
+        voices.push(voice_info(&registry, voice));
     }
 
     // Add any additional voice files found in the directory
-    if Path::new(voice_styles_dir).exists() {
-        if let Ok(entries) = std::fs::read_dir(voice_styles_dir) {
-            let mut seen_files = std::collections::HashSet::new();
-
-            // Mark standard files as seen
-            for (_, path) in &standard_voices {
-                if let Some(file_name) = Path::new(path).file_name() {
-                    if let Some(name_str) = file_name.to_str() {
-                        seen_files.insert(name_str.to_lowercase());
-                    }
-                }
-            }
-
-            for entry in entries.flatten() {
-                if let Some(file_name) = entry.file_name().to_str() {
-                    if file_name.ends_with(".json") && !seen_files.contains(&file_name.to_lowercase()) {
-                        let name = file_name.trim_end_matches(".json").to_string();
-                        let path = format!("{}/{}", voice_styles_dir, file_name);
-                        voices.push(VoiceInfo {
-                            name,
-                            path: path.clone(),
-                            exists: Path::new(&path).exists(),
-                        });
-                        seen_files.insert(file_name.to_lowercase());
-                    }
-                }
+    if let Ok(entries) = std::fs::read_dir(voice_styles_dir) {
+        let mut files: Vec<String> = entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+            .filter(|file_name| voice_style_stem(file_name).is_some())
+            .collect();
+        files.sort();
+
+        for file_name in files {
+            let path = Path::new(voice_styles_dir).join(&file_name);
+            if !seen_files.insert(path.canonicalize().unwrap_or_else(|_| path.clone())) {
+                continue;
             }
+            voices.push(VoiceInfo {
+                name: voice_style_stem(&file_name).unwrap_or_default().to_string(),
+                aliases: Vec::new(),
+                path: path.to_string_lossy().into_owned(),
+                exists: true,
+                language: None,
+                gender: None,
+                description: None,
+                speed: None,
+                uploaded: false,
+            });
         }
     }
 
@@ -401,6 +712,350 @@ pub async fn list_voices() -> impl IntoResponse {
     Json(response)
 }
 
+/// Body of `POST /v1/voices/mix`
+#[derive(Debug, Deserialize)]
+pub struct VoiceMixRequest {
+    /// Name of the new voice: letters, digits, '-' and '_'
+    pub name: String,
+    /// Voices and their weights, as in the `voice` request parameter
+    pub mix: Vec<(String, f32)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VoiceMixResponse {
+    pub name: String,
+    pub path: String,
+    /// The mixed voices with their weights scaled to sum to 1
+    pub mix: Vec<(String, f32)>,
+}
+
+/// Blend voices and save the result as a new voice style file that can then
+/// be requested by name
+pub async fn create_voice_mix(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<VoiceMixRequest>,
+) -> Result<Response, StatusCode> {
+    if let Err(e) = check_required_api_key(&headers, &state.config.auth) {
+        return Ok(e.into_response());
+    }
+    Ok(match save_voice_mix(&state, body).await {
+        Ok(response) => {
+            info!("Voice mix '{}' saved to {}", response.name, response.path);
+            (StatusCode::CREATED, Json(response)).into_response()
+        }
+        Err(e) => e.into_response(),
+    })
+}
+
+async fn save_voice_mix(state: &AppState, body: VoiceMixRequest) -> Result<VoiceMixResponse, RequestError> {
+    check_voice_name(&body.name)?;
+    check_voice_available(&state.voices.read().unwrap(), &body.name, &[])?;
+
+    let param = VoiceParam::Mix { mix: body.mix.clone() };
+    let voice = resolve_voice(state, Some(&param))?;
+    let style = load_voice(None, &voice, "voice-mix").await?;
+    let entry = VoiceEntry {
+        id: body.name.clone(),
+        aliases: Vec::new(),
+        path: format!("{}.json", body.name),
+        language: None,
+        gender: None,
+        description: Some(format!("Mix of {}", param.label())),
+        speed: None,
+    };
+    let path = register_voice(state, entry, |path| save_voice_style(&style, path))?;
+
+    let mix = body
+        .mix
+        .into_iter()
+        .zip(voice.components)
+        .map(|((name, _), (_, weight))| (name, weight))
+        .collect();
+    Ok(VoiceMixResponse { name: body.name, path, mix })
+}
+
+/// Body of `POST /v1/voices`: a voice style file with its registry entry
+#[derive(Debug, Deserialize)]
+pub struct VoiceUploadRequest {
+    /// ID of the new voice: letters, digits, '-' and '_'
+    pub id: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub language: Option<String>,
+    pub gender: Option<String>,
+    pub description: Option<String>,
+    pub speed: Option<f32>,
+    /// Contents of a voice style file
+    pub style: VoiceStyleData,
+}
+
+fn check_voice_name(name: &str) -> Result<(), RequestError> {
+    if is_valid_voice_name(name) {
+        return Ok(());
+    }
+    Err(RequestError {
+        status: StatusCode::BAD_REQUEST,
+        message: format!("Invalid voice name '{}': use 1 to 64 letters, digits, '-' or '_'", name),
+        type_: "invalid_request_error",
+        code: "invalid_voice_name",
+    })
+}
+
+/// Check that a new voice's names are free and that its style file would not
+/// replace an existing one
+fn check_voice_available(voices: &VoiceRegistry, id: &str, aliases: &[String]) -> Result<(), RequestError> {
+    let conflict = |message: String| RequestError {
+        status: StatusCode::CONFLICT,
+        message,
+        type_: "invalid_request_error",
+        code: "voice_exists",
+    };
+    for name in std::iter::once(id).chain(aliases.iter().map(String::as_str)) {
+        if let Some(voice) = voices.find(name) {
+            return Err(conflict(format!("Voice name '{}' is already used by voice '{}'", name, voice.id)));
+        }
+    }
+    let path = Path::new(voices.dir()).join(format!("{}.json", id));
+    if path.exists() {
+        return Err(conflict(format!("Voice '{}' already exists: {}", id, path.display())));
+    }
+    Ok(())
+}
+
+fn voice_save_error(id: &str, e: anyhow::Error) -> RequestError {
+    error!("Failed to save voice '{}': {:#}", id, e);
+    RequestError {
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+        message: format!("Failed to save voice: {:#}", e),
+        type_: "internal_server_error",
+        code: "voice_save_failed",
+    }
+}
+
+/// Save the uploaded voices list, when a file for it is configured
+fn save_uploaded_voices(state: &AppState, voices: &VoiceRegistry) -> Result<()> {
+    match &state.config.tts.uploaded_voices_path {
+        Some(path) => voices.save_uploaded(path),
+        None => Ok(()),
+    }
+}
+
+/// Write a new voice's style file with `write_style` and register the voice,
+/// undoing both if either fails. Returns the style file path.
+fn register_voice<F>(state: &AppState, voice: VoiceEntry, write_style: F) -> Result<String, RequestError>
+where
+    F: FnOnce(&str) -> Result<()>,
+{
+    let mut voices = state.voices.write().unwrap();
+    check_voice_available(&voices, &voice.id, &voice.aliases)?;
+
+    let id = voice.id.clone();
+    let path = voices.path_of(&voice);
+    std::fs::create_dir_all(voices.dir())
+        .map_err(anyhow::Error::from)
+        .and_then(|()| write_style(&path))
+        .map_err(|e| voice_save_error(&id, e))?;
+
+    if let Err(e) = voices.add_uploaded(voice) {
+        let _ = std::fs::remove_file(&path);
+        return Err(RequestError {
+            status: StatusCode::BAD_REQUEST,
+            message: format!("Invalid voice: {}", e),
+            type_: "invalid_request_error",
+            code: "invalid_voice",
+        });
+    }
+    if let Err(e) = save_uploaded_voices(state, &voices) {
+        voices.remove_uploaded(&id);
+        let _ = std::fs::remove_file(&path);
+        return Err(voice_save_error(&id, e));
+    }
+    Ok(path)
+}
+
+/// Style tensor shapes of the model, read from a pooled engine or the single
+/// engine (which is loaded if needed)
+async fn model_style_shapes(state: &AppState, ticket: &Ticket) -> Result<StyleShapes, AcquireError> {
+    let engine_error = |e: anyhow::Error| {
+        error!("Failed to read the model's style shapes: {}", e);
+        RequestError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: format!("Failed to get engine: {}", e),
+            type_: "internal_server_error",
+            code: "engine_access_failed",
+        }
+    };
+
+    let engine = match &state.engine_pool {
+        Some(pool) => {
+            let engine_handle = pool.checkout(ticket).await.map_err(|e| checkout_error(e, "voice-upload"))?;
+            SynthesisEngine::Pooled(engine_handle)
+        }
+        None => SynthesisEngine::Single(Arc::clone(&state.text_to_speech)),
+    };
+
+    let settings = state.config.tts.clone();
+    state
+        .inference
+        .run(move || engine.run(&settings, "voice-upload", |tts| tts.style_shapes()))
+        .await
+        .and_then(|result| result)
+        .map_err(|e| engine_error(e).into())
+}
+
+/// Add a voice from an uploaded style file. The style must fit the loaded
+/// model; the voice can be used right away.
+pub async fn upload_voice(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<VoiceUploadRequest>,
+) -> Result<Response, StatusCode> {
+    let caller = match check_required_api_key(&headers, &state.config.auth) {
+        Ok(caller) => caller,
+        Err(e) => return Ok(e.into_response()),
+    };
+    let ticket = match request_ticket(&caller, &headers, 1) {
+        Ok(ticket) => ticket,
+        Err(e) => return Ok(e.into_response()),
+    };
+    Ok(match add_voice(&state, body, &ticket).await {
+        Ok(info) => {
+            info!("Voice '{}' uploaded to {}", info.name, info.path);
+            (StatusCode::CREATED, Json(info)).into_response()
+        }
+        Err(e) => e.into_response(),
+    })
+}
+
+async fn add_voice(state: &AppState, body: VoiceUploadRequest, ticket: &Ticket) -> Result<VoiceInfo, AcquireError> {
+    check_voice_name(&body.id)?;
+    for alias in &body.aliases {
+        check_voice_name(alias)?;
+    }
+    check_voice_available(&state.voices.read().unwrap(), &body.id, &body.aliases)?;
+
+    let shapes = model_style_shapes(state, ticket).await?;
+    body.style.validate(&shapes).map_err(|e| RequestError {
+        status: StatusCode::BAD_REQUEST,
+        message: format!("Invalid voice style: {}", e),
+        type_: "invalid_request_error",
+        code: "invalid_voice_style",
+    })?;
+
+    let entry = VoiceEntry {
+        path: format!("{}.json", body.id),
+        id: body.id,
+        aliases: body.aliases,
+        language: body.language,
+        gender: body.gender,
+        description: body.description,
+        speed: body.speed,
+    };
+    let style = body.style;
+    let path = register_voice(state, entry.clone(), |path| style.save(path))?;
+
+    // A file of this name may have been cached before it was replaced
+    if let Some(pool) = &state.engine_pool {
+        pool.invalidate_voice_style(&path).await;
+    }
+    Ok(voice_info(&state.voices.read().unwrap(), &entry))
+}
+
+fn voice_not_found(name: &str) -> RequestError {
+    RequestError {
+        status: StatusCode::NOT_FOUND,
+        message: format!("Voice '{}' not found", name),
+        type_: "invalid_request_error",
+        code: "voice_not_found",
+    }
+}
+
+/// A registered voice by ID or alias
+pub async fn get_voice(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    UrlPath(name): UrlPath<String>,
+) -> Result<Response, StatusCode> {
+    check_api_key(&headers, &state.config.auth)?;
+    let voices = state.voices.read().unwrap();
+    Ok(match voices.find(&name) {
+        Some(voice) => Json(voice_info(&voices, voice)).into_response(),
+        None => voice_not_found(&name).into_response(),
+    })
+}
+
+/// Delete an uploaded voice and its style file. Configured voices cannot be
+/// deleted.
+pub async fn delete_voice(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    UrlPath(name): UrlPath<String>,
+) -> Result<Response, StatusCode> {
+    if let Err(e) = check_required_api_key(&headers, &state.config.auth) {
+        return Ok(e.into_response());
+    }
+    Ok(match remove_voice(&state, &name).await {
+        Ok(id) => {
+            info!("Voice '{}' deleted", id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => e.into_response(),
+    })
+}
+
+async fn remove_voice(state: &AppState, name: &str) -> Result<String, RequestError> {
+    let (id, path) = {
+        let voices = state.voices.read().unwrap();
+        let voice = voices.find(name).ok_or_else(|| voice_not_found(name))?;
+        if !voices.is_uploaded(&voice.id) {
+            return Err(RequestError {
+                status: StatusCode::CONFLICT,
+                message: format!("Voice '{}' is configured in the server config and cannot be deleted", voice.id),
+                type_: "invalid_request_error",
+                code: "voice_not_deletable",
+            });
+        }
+        (voice.id.clone(), voices.path_of(voice))
+    };
+
+    // Cached styles are keyed by the resolved file path, so drop the entry
+    // while the file still exists
+    if let Some(pool) = &state.engine_pool {
+        pool.invalidate_voice_style(&path).await;
+    }
+
+    let mut voices = state.voices.write().unwrap();
+    let voice = voices.remove_uploaded(&id).ok_or_else(|| voice_not_found(name))?;
+    if let Err(e) = save_uploaded_voices(state, &voices) {
+        error!("Failed to save the uploaded voices list: {:#}", e);
+        voices.add_uploaded(voice).ok();
+        return Err(RequestError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: format!("Failed to delete voice: {:#}", e),
+            type_: "internal_server_error",
+            code: "voice_delete_failed",
+        });
+    }
+    if let Err(e) = std::fs::remove_file(&path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to delete style file of voice '{}' ({}): {}", id, path, e);
+        }
+    }
+    Ok(id)
+}
+
+fn error_response(status: StatusCode, message: impl Into<String>, type_: &str, code: &str) -> Response {
+    let error = TtsError {
+        error: TtsErrorDetail {
+            message: message.into(),
+            type_: type_.to_string(),
+            code: Some(code.to_string()),
+        },
+    };
+    (status, Json(error)).into_response()
+}
+
 pub async fn tts_speech(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -412,26 +1067,32 @@ pub async fn tts_speech(
       // Log model and response_format for debugging
     let model = request.model.as_deref().unwrap_or("supertts");
     let response_format = request.response_format.as_deref().unwrap_or("wav");
+    let stream = request.stream.unwrap_or(false) || request.stream_format.is_some();
 
-    info!("[{}] TTS request: model='{}' input='{}' voice={:?} format={:?}",
-          request_id, model, request.input, request.voice, response_format);
+    info!("[{}] TTS request: model='{}' input='{}' voice={:?} format={:?} stream={}",
+          request_id, model, request.input, request.voice.as_ref().map(VoiceParam::label), response_format, stream);
 
     // Check authentication
-    if let Err(status) = check_api_key(&headers, &state.config.auth) {
-        warn!("[{}] Authentication failed", request_id);
-        return Err(status);
-    }
+    let caller = match check_api_key(&headers, &state.config.auth) {
+        Ok(caller) => caller,
+        Err(status) => {
+            warn!("[{}] Authentication failed", request_id);
+            return Err(status);
+        }
+    };
+    let ticket = match request_ticket(&caller, &headers, request.input.chars().count()) {
+        Ok(ticket) => ticket,
+        Err(e) => return Ok(e.into_response()),
+    };
 
     // Validate input
     if request.input.trim().is_empty() {
-        let error = TtsError {
-            error: TtsErrorDetail {
-                message: "Input text cannot be empty".to_string(),
-                type_: "invalid_request_error".to_string(),
-                code: Some("empty_input".to_string()),
-            },
-        };
-        return Ok((StatusCode::BAD_REQUEST, Json(error)).into_response());
+        return Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            "Input text cannot be empty",
+            "invalid_request_error",
+            "empty_input",
+        ));
     }
 
     // Validate model (we accept any model name but log it)
@@ -439,233 +1100,1542 @@ pub async fn tts_speech(
         warn!("[{}] Using unsupported model '{}', will use supertts engine", request_id, model);
     }
 
-    // Validate response format (only wav is supported)
-    if response_format != "wav" {
-        let error = TtsError {
-            error: TtsErrorDetail {
-                message: format!("Response format '{}' is not supported. Only 'wav' is supported.", response_format),
-                type_: "invalid_request_error".to_string(),
-                code: Some("unsupported_format".to_string()),
-            },
-        };
-        return Ok((StatusCode::BAD_REQUEST, Json(error)).into_response());
+    // Validate response format
+    let audio_format = match AudioFormat::parse(response_format) {
+        Some(format) => format,
+        None => {
+            return Ok(error_response(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Response format '{}' is not supported. Supported formats: {}.",
+                    response_format,
+                    AudioFormat::SUPPORTED.join(", ")
+                ),
+                "invalid_request_error",
+                "unsupported_format",
+            ));
+        }
+    };
+
+    // Validate output sample rate
+    let mut encoder_settings = state.config.tts.encoder_settings();
+    match audio_format.output_sample_rate(request.sample_rate) {
+        Ok(sample_rate) => encoder_settings.sample_rate = sample_rate,
+        Err(e) => {
+            return Ok(error_response(
+                StatusCode::BAD_REQUEST,
+                e.to_string(),
+                "invalid_request_error",
+                "unsupported_sample_rate",
+            ));
+        }
     }
 
+    // Validate stream format
+    let stream_format = match request.stream_format.as_deref() {
+        None => StreamFormat::Audio,
+        Some(value) => match StreamFormat::parse(value) {
+            Some(format) => format,
+            None => {
+                return Ok(error_response(
+                    StatusCode::BAD_REQUEST,
+                    format!("Stream format '{}' is not supported. Use 'audio' or 'sse'.", value),
+                    "invalid_request_error",
+                    "unsupported_stream_format",
+                ));
+            }
+        },
+    };
+
     // Validate speed
     if let Some(speed) = request.speed {
-        if speed < 0.25 || speed > 4.0 {
-            let error = TtsError {
-                error: TtsErrorDetail {
-                    message: "Speed must be between 0.25 and 4.0".to_string(),
-                    type_: "invalid_request_error".to_string(),
-                    code: Some("invalid_speed".to_string()),
-                },
-            };
-            return Ok((StatusCode::BAD_REQUEST, Json(error)).into_response());
+        if !(0.25..=4.0).contains(&speed) {
+            return Ok(error_response(
+                StatusCode::BAD_REQUEST,
+                "Speed must be between 0.25 and 4.0",
+                "invalid_request_error",
+                "invalid_speed",
+            ));
         }
     }
 
-    // Map voice parameter to voice style file with validation
-    let voice_style_path = match resolve_voice_style_path(request.voice.as_deref(), &state.default_voice_style) {
-        Ok(path) => path,
+    // Map voice parameter to voice style files with validation
+    let voice = match resolve_voice(&state, request.voice.as_ref()) {
+        Ok(voice) => voice,
         Err(e) => {
-            error!("[{}] Voice style resolution failed: {}", request_id, e);
-            let error = TtsError {
-                error: TtsErrorDetail {
-                    message: format!("Voice style not found: {}", e),
-                    type_: "invalid_request_error".to_string(),
-                    code: Some("voice_not_found".to_string()),
-                },
-            };
-            return Ok((StatusCode::BAD_REQUEST, Json(error)).into_response());
+            error!("[{}] Voice style resolution failed: {}", request_id, e.message);
+            return Ok(e.into_response());
         }
     };
 
-    // Use engine pool if available, otherwise fallback to single engine
-    let (wav_data, sample_rate) = if let Some(pool) = &state.engine_pool {
-        // Use engine pool
-        info!("[{}] Using engine pool for TTS generation", request_id);
-
-        let engine_handle = match pool.checkout().await {
-            Ok(handle) => handle,
-            Err(e) => {
-                error!("[{}] Failed to checkout engine: {}", request_id, e);
-                let error = TtsError {
-                    error: TtsErrorDetail {
-                        message: format!("Engine pool exhausted: {}", e),
-                        type_: "service_unavailable".to_string(),
-                        code: Some("pool_exhausted".to_string()),
-                    },
-                };
-                return Ok((StatusCode::SERVICE_UNAVAILABLE, Json(error)).into_response());
-            }
-        };
-
-        // Load voice style using pool cache
-        let style = match engine_handle.get_voice_style(&voice_style_path).await {
-            Ok(style) => style,
-            Err(e) => {
-                error!("[{}] Failed to load voice style {}: {}", request_id, voice_style_path, e);
-                let error = TtsError {
-                    error: TtsErrorDetail {
-                        message: format!("Failed to load voice style: {}", e),
-                        type_: "invalid_request_error".to_string(),
-                        code: Some("voice_style_load_failed".to_string()),
-                    },
-                };
-                return Ok((StatusCode::BAD_REQUEST, Json(error)).into_response());
-            }
-        };
+    let input = match prepare_input(&state, &request, voice) {
+        Ok(input) => input,
+        Err(e) => {
+            error!("[{}] Invalid input: {}", request_id, e.message);
+            return Ok(e.into_response());
+        }
+    };
 
-        // Get the engine and generate speech
-        let speed = request.speed.unwrap_or(state.config.tts.speed);
-        let total_step = state.config.tts.total_step;
-
-        let result = match engine_handle.engine().await {
-            Ok(text_to_speech_mutex) => {
-                let mut text_to_speech = text_to_speech_mutex.lock().await;
-                let sample_rate = text_to_speech.sample_rate;
-
-                match timer("TTS Generation", || {
-                    text_to_speech.call(&request.input, &style, total_step, speed, 0.3)
-                }) {
-                    Ok(result) => (result.0, sample_rate as f32),
-                    Err(e) => {
-                        error!("[{}] TTS generation failed: {}", request_id, e);
-                        let error = TtsError {
-                            error: TtsErrorDetail {
-                                message: format!("TTS generation failed: {}", e),
-                                type_: "internal_server_error".to_string(),
-                                code: Some("tts_generation_failed".to_string()),
-                            },
-                        };
-                        return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response());
-                    }
-                }
-            }
-            Err(e) => {
-                error!("[{}] Failed to get engine: {}", request_id, e);
-                let error = TtsError {
-                    error: TtsErrorDetail {
-                        message: format!("Failed to get engine: {}", e),
-                        type_: "internal_server_error".to_string(),
-                        code: Some("engine_access_failed".to_string()),
-                    },
-                };
-                return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response());
-            }
-        };
+    if stream {
+        return Ok(stream_speech(
+            state,
+            request,
+            input,
+            ticket,
+            request_id,
+            start_time,
+            stream_format,
+            audio_format,
+            encoder_settings,
+        )
+        .await);
+    }
 
-        // Engine handle is automatically dropped and returned to pool
-        result
-    } else {
-        // Fallback to single engine (backward compatibility)
-        info!("[{}] Using single engine (fallback)", request_id);
+    let (engine, styles) = match acquire_engine(&state, &input.voices, &ticket, &request_id).await {
+        Ok(acquired) => acquired,
+        Err(e) => return Ok(e.into_response()),
+    };
 
-        let mut tts_guard = state.text_to_speech.lock().unwrap();
-        let text_to_speech = match tts_guard.as_mut() {
-            Some(tts) => tts,
-            None => {
-                info!("[{}] Loading TTS engine...", request_id);
-                match load_text_to_speech(&state.config.tts.onnx_dir, state.config.tts.use_gpu) {
-                    Ok(tts) => {
-                        *tts_guard = Some(tts);
-                        tts_guard.as_mut().unwrap()
-                    }
-                    Err(e) => {
-                        error!("[{}] Failed to load TTS engine: {}", request_id, e);
-                        let error = TtsError {
-                            error: TtsErrorDetail {
-                                message: format!("Failed to load TTS engine: {}", e),
-                                type_: "internal_server_error".to_string(),
-                                code: Some("tts_load_failed".to_string()),
-                            },
-                        };
-                        return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response());
+    // Generate and encode speech on an inference worker
+    let speed = request.speed.or(input.voices.default.speed).unwrap_or(state.config.tts.speed);
+    let settings = state.config.tts.clone();
+    let task_request_id = request_id.clone();
+    let SynthesisInput { segments, chunking, .. } = input;
+    let result = state
+        .inference
+        .run(move || {
+            let (wav_data, sample_rate) = engine
+                .run(&settings, &task_request_id, |text_to_speech| {
+                    let (wav, _) = timer("TTS Generation", || {
+                        text_to_speech.call_segments(&segments, &styles, settings.total_step, speed, &chunking)
+                    })?;
+                    Ok((wav, text_to_speech.sample_rate))
+                })
+                .map_err(|e| {
+                    error!("[{}] TTS generation failed: {}", task_request_id, e);
+                    RequestError {
+                        status: StatusCode::INTERNAL_SERVER_ERROR,
+                        message: format!("TTS generation failed: {}", e),
+                        type_: "internal_server_error",
+                        code: "tts_generation_failed",
                     }
+                })?;
+
+            // The engine is released before encoding
+            let audio_buffer = encode_audio(audio_format, &wav_data, sample_rate, &encoder_settings).map_err(|e| {
+                error!("[{}] Failed to encode {}: {}", task_request_id, audio_format.name(), e);
+                RequestError {
+                    status: StatusCode::INTERNAL_SERVER_ERROR,
+                    message: format!("Failed to encode {}: {}", audio_format.name(), e),
+                    type_: "internal_server_error",
+                    code: "audio_encoding_failed",
                 }
-            }
-        };
-
-        // Load voice style (simplified approach - load on demand without caching)
-        let style = match load_voice_style(&[voice_style_path.to_string()], false) {
-            Ok(style) => style,
-            Err(e) => {
-                error!("[{}] Failed to load voice style {}: {}", request_id, voice_style_path, e);
-                let error = TtsError {
-                    error: TtsErrorDetail {
-                        message: format!("Failed to load voice style: {}", e),
-                        type_: "invalid_request_error".to_string(),
-                        code: Some("voice_style_load_failed".to_string()),
-                    },
-                };
-                return Ok((StatusCode::BAD_REQUEST, Json(error)).into_response());
-            }
-        };
-
-        // Generate speech
-        let speed = request.speed.unwrap_or(state.config.tts.speed);
-        let total_step = state.config.tts.total_step;
+            })?;
+            Ok((audio_buffer, sample_rate))
+        })
+        .await
+        .unwrap_or_else(|e| {
+            Err(RequestError {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("TTS generation failed: {}", e),
+                type_: "internal_server_error",
+                code: "tts_generation_failed",
+            })
+        });
 
-        let sample_rate = text_to_speech.sample_rate;
-        match timer("TTS Generation", || {
-            text_to_speech.call(&request.input, &style, total_step, speed, 0.3)
-        }) {
-            Ok(result) => (result.0, sample_rate as f32),
-            Err(e) => {
-                error!("[{}] TTS generation failed: {}", request_id, e);
-                let error = TtsError {
-                    error: TtsErrorDetail {
-                        message: format!("TTS generation failed: {}", e),
-                        type_: "internal_server_error".to_string(),
-                        code: Some("tts_generation_failed".to_string()),
-                    },
-                };
-                return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response());
-            }
-        }
+    let (audio_buffer, sample_rate) = match result {
+        Ok(result) => result,
+        Err(e) => return Ok(e.into_response()),
     };
 
-    // Convert WAV data to bytes
-    let mut wav_buffer = Vec::new();
-    if let Err(e) = crate::helper::write_wav_to_buffer(&mut wav_buffer, &wav_data, sample_rate as i32) {
-        error!("[{}] Failed to encode WAV: {}", request_id, e);
-        let error = TtsError {
-            error: TtsErrorDetail {
-                message: format!("Failed to encode WAV: {}", e),
-                type_: "internal_server_error".to_string(),
-                code: Some("wav_encoding_failed".to_string()),
-            },
-        };
-        return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response());
-    }
-
     let duration = start_time.elapsed();
-    info!("[{}] TTS request completed in {:?} ({} bytes)", request_id, duration, wav_buffer.len());
+    info!("[{}] TTS request completed in {:?} ({} bytes)", request_id, duration, audio_buffer.len());
 
-    // Return WAV audio response with detailed headers
+    // Return audio response with detailed headers
     let response = Response::builder()
         .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "audio/wav")
-        .header(header::CONTENT_LENGTH, wav_buffer.len())
+        .header(header::CONTENT_TYPE, audio_format.content_type())
+        .header(header::CONTENT_LENGTH, audio_buffer.len())
         .header("X-Request-ID", request_id)
         .header("X-Model-Used", model)
-        .header("X-Voice-Used", request.voice.unwrap_or_else(|| "default".to_string()))
+        .header("X-Voice-Used", request.voice.as_ref().map_or_else(|| "default".to_string(), VoiceParam::label))
         .header("X-Response-Format", response_format)
+        .header("X-Sample-Rate", encoder_settings.output_sample_rate(audio_format, sample_rate))
         .header("X-Processing-Time", format!("{:.3}ms", duration.as_millis()))
         .header("Cache-Control", "no-cache")
-        .body(axum::body::Body::from(wav_buffer))
+        .body(axum::body::Body::from(audio_buffer))
         .unwrap();
 
     Ok(response)
 }
 
-pub fn create_router(state: AppState) -> Router {
-    Router::new()
-        .route("/health", get(health_check))
-        .route("/voices", get(list_voices))
-        .route("/v1/audio/speech", post(tts_speech))
-        .layer(
+// Streaming Synthesis
+
+/// How a streaming response is delivered to the client
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StreamFormat {
+    /// Raw audio bytes with chunked transfer encoding
+    Audio,
+    /// Server-Sent Events with per-chunk audio and timing metadata
+    Sse,
+}
+
+impl StreamFormat {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "audio" => Some(StreamFormat::Audio),
+            "sse" => Some(StreamFormat::Sse),
+            _ => None,
+        }
+    }
+}
+
+/// Engine used for synthesis on a blocking thread: either a leased pool
+/// engine or the fallback single engine, which is loaded on first use.
+enum SynthesisEngine {
+    Pooled(EngineHandle),
+    Single(Arc<Mutex<Option<TextToSpeech>>>),
+}
+
+impl SynthesisEngine {
+    /// Run `f` with exclusive access to the engine. Must be called from an
+    /// inference worker; a pooled engine stays checked out until `f` returns.
+    fn run<T>(
+        self,
+        settings: &TtsSettings,
+        request_id: &str,
+        f: impl FnOnce(&mut TextToSpeech) -> Result<T>,
+    ) -> Result<T> {
+        match self {
+            SynthesisEngine::Pooled(handle) => {
+                let result = {
+                    let engine = handle.engine();
+                    let mut text_to_speech = engine.blocking_lock();
+                    f(&mut text_to_speech)
+                };
+                handle.record(&result);
+                result
+            }
+            SynthesisEngine::Single(engine) => {
+                let mut tts_guard = engine.lock().unwrap();
+                if tts_guard.is_none() {
+                    info!("[{}] Loading TTS engine...", request_id);
+                    let tts = load_text_to_speech(&settings.onnx_dir, settings.use_gpu)
+                        .map_err(|e| anyhow!("Failed to load TTS engine: {}", e))?;
+                    *tts_guard = Some(tts);
+                }
+                f(tts_guard.as_mut().unwrap())
+            }
+        }
+    }
+}
+
+/// Request failure with the status and error code to report to the client
+struct RequestError {
+    status: StatusCode,
+    message: String,
+    type_: &'static str,
+    code: &'static str,
+}
+
+impl IntoResponse for RequestError {
+    fn into_response(self) -> Response {
+        error_response(self.status, self.message, self.type_, self.code)
+    }
+}
+
+/// Failure to get an engine for a request. When the pool is overloaded the
+/// client is told how long to wait before retrying.
+enum AcquireError {
+    Request(RequestError),
+    Overloaded { error: RequestError, retry_after: Duration },
+}
+
+impl AcquireError {
+    fn message(self) -> String {
+        match self {
+            AcquireError::Request(error) | AcquireError::Overloaded { error, .. } => error.message,
+        }
+    }
+}
+
+impl From<RequestError> for AcquireError {
+    fn from(error: RequestError) -> Self {
+        AcquireError::Request(error)
+    }
+}
+
+impl IntoResponse for AcquireError {
+    fn into_response(self) -> Response {
+        match self {
+            AcquireError::Request(error) => error.into_response(),
+            AcquireError::Overloaded { error, retry_after } => {
+                // Retry-After is in whole seconds; never tell clients to retry at once
+                let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                let mut response = error.into_response();
+                response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+                response
+            }
+        }
+    }
+}
+
+/// Report a failed engine checkout: 429 when the queue is full, 503 when the
+/// request waited too long, both with a retry estimate
+fn checkout_error(e: CheckoutError, request_id: &str) -> AcquireError {
+    match e {
+        CheckoutError::QueueFull { retry_after } => {
+            warn!("[{}] Rejected, the engine queue is full", request_id);
+            AcquireError::Overloaded {
+                error: RequestError {
+                    status: StatusCode::TOO_MANY_REQUESTS,
+                    message: "Server is busy, too many requests are waiting for an engine".to_string(),
+                    type_: "rate_limit_error",
+                    code: "queue_full",
+                },
+                retry_after,
+            }
+        }
+        CheckoutError::Timeout { retry_after, .. } => {
+            error!("[{}] Failed to checkout engine: {}", request_id, e);
+            AcquireError::Overloaded {
+                error: RequestError {
+                    status: StatusCode::SERVICE_UNAVAILABLE,
+                    message: format!("Engine pool exhausted: {}", e),
+                    type_: "service_unavailable",
+                    code: "pool_exhausted",
+                },
+                retry_after,
+            }
+        }
+        CheckoutError::Engine(_) => {
+            error!("[{}] Failed to checkout engine: {}", request_id, e);
+            AcquireError::Request(RequestError {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("Failed to get engine: {}", e),
+                type_: "internal_server_error",
+                code: "engine_access_failed",
+            })
+        }
+    }
+}
+
+/// Voice style files a voice is made of, with their mixing weights; a
+/// named voice is a single file
+#[derive(Debug, Clone)]
+struct VoiceMix {
+    components: Vec<(String, f32)>,
+    /// Speed of the registered voice, used when the request sets none
+    speed: Option<f32>,
+}
+
+impl VoiceMix {
+    fn single(path: String) -> Self {
+        VoiceMix {
+            components: vec![(path, 1.0)],
+            speed: None,
+        }
+    }
+
+    /// A single voice with the speed its registry entry gives
+    fn registered(voices: &VoiceRegistry, name: &str, path: String) -> Self {
+        VoiceMix {
+            speed: voices.find(name).and_then(|voice| voice.speed),
+            ..Self::single(path)
+        }
+    }
+}
+
+/// Resolve the request voice to its style files, checking a mix's size and
+/// weights
+fn resolve_voice(state: &AppState, voice: Option<&VoiceParam>) -> Result<VoiceMix, RequestError> {
+    let default_voice_style = &state.default_voice_style;
+    let voices = &*state.voices.read().unwrap();
+    let not_found = |e: anyhow::Error| RequestError {
+        status: StatusCode::BAD_REQUEST,
+        message: format!("Voice style not found: {}", e),
+        type_: "invalid_request_error",
+        code: "voice_not_found",
+    };
+    let invalid = |message: String| RequestError {
+        status: StatusCode::BAD_REQUEST,
+        message,
+        type_: "invalid_request_error",
+        code: "invalid_voice_mix",
+    };
+
+    let mix = match voice {
+        None => {
+            return resolve_voice_style_path(voices, None, default_voice_style)
+                .map(|path| VoiceMix::registered(voices, default_voice_style, path))
+                .map_err(not_found)
+        }
+        Some(VoiceParam::Name(name)) => {
+            return resolve_voice_style_path(voices, Some(name), default_voice_style)
+                .map(|path| VoiceMix::registered(voices, name, path))
+                .map_err(not_found)
+        }
+        Some(VoiceParam::Mix { mix }) => mix,
+    };
+
+    if mix.len() > MAX_VOICE_MIX {
+        return Err(invalid(format!("A voice mix can combine at most {} voices", MAX_VOICE_MIX)));
+    }
+    let weights: Vec<f32> = mix.iter().map(|(_, weight)| *weight).collect();
+    let weights = normalize_mix_weights(&weights).map_err(|e| invalid(e.to_string()))?;
+    let components = mix
+        .iter()
+        .zip(weights)
+        .map(|((name, _), weight)| {
+            let path = resolve_voice_style_path(voices, Some(name), default_voice_style).map_err(not_found)?;
+            Ok((path, weight))
+        })
+        .collect::<Result<_, RequestError>>()?;
+    Ok(VoiceMix { components, speed: None })
+}
+
+/// Voice style files a request needs: the request voice plus any voices
+/// named in its SSML
+struct VoicePaths {
+    default: VoiceMix,
+    named: Vec<(String, String)>,
+}
+
+impl VoicePaths {
+    fn single(voice: VoiceMix) -> Self {
+        VoicePaths {
+            default: voice,
+            named: Vec::new(),
+        }
+    }
+}
+
+/// Request input split into segments, with the voice styles it uses and
+/// how its text is chunked
+struct SynthesisInput {
+    segments: Vec<TextSegment>,
+    voices: VoicePaths,
+    chunking: ChunkSettings,
+}
+
+/// Parse request input (plain text or SSML), rewrite its text for the
+/// model and resolve the voices it names
+fn prepare_input(
+    state: &AppState,
+    request: &TtsRequest,
+    voice: VoiceMix,
+) -> Result<SynthesisInput, RequestError> {
+    let policy = unknown_char_policy(state, request.unknown_char_policy.as_deref())?;
+    let language = request_language(state, request.language.as_deref())?;
+    let chunking = chunk_settings(state, request, &language)?;
+    let format = input_format(state, request.input_format.as_deref())?;
+    let mut segments = rewrite_input(
+        state,
+        &request.input,
+        format,
+        &language,
+        request.lexicon.as_ref(),
+        request.normalize.unwrap_or(true),
+    )?;
+    apply_unknown_char_policy(state, &request.input, &mut segments, policy)?;
+    let default_voice_style = &state.default_voice_style;
+
+    let mut voices = VoicePaths::single(voice);
+    for voice in segments.iter().filter_map(|segment| segment.voice.as_deref()) {
+        if voices.named.iter().any(|(name, _)| name == voice) {
+            continue;
+        }
+        let path = resolve_voice_style_path(&state.voices.read().unwrap(), Some(voice), default_voice_style).map_err(|e| RequestError {
+            status: StatusCode::BAD_REQUEST,
+            message: format!("Voice style not found: {}", e),
+            type_: "invalid_request_error",
+            code: "voice_not_found",
+        })?;
+        voices.named.push((voice.to_string(), path));
+    }
+
+    Ok(SynthesisInput { segments, voices, chunking })
+}
+
+/// The server's chunking settings with the request's overrides applied
+fn chunk_settings(state: &AppState, request: &TtsRequest, language: &str) -> Result<ChunkSettings, RequestError> {
+    let invalid = |message: String| RequestError {
+        status: StatusCode::BAD_REQUEST,
+        message,
+        type_: "invalid_request_error",
+        code: "invalid_chunking",
+    };
+
+    let mut chunking = state.config.tts.chunk_settings(state.segmenters.get(language));
+    if let Some(value) = &request.split_strategy {
+        chunking.strategy = SplitStrategy::parse(value).ok_or_else(|| {
+            invalid(format!(
+                "Split strategy '{}' is not supported. Use one of: {}.",
+                value,
+                SplitStrategy::SUPPORTED.join(", ")
+            ))
+        })?;
+    }
+    chunking.max_chunk_chars = request.max_chunk_chars.unwrap_or(chunking.max_chunk_chars);
+    chunking.min_chunk_chars = request.min_chunk_chars.unwrap_or(chunking.min_chunk_chars);
+    chunking.sentence_silence = request.sentence_silence.unwrap_or(chunking.sentence_silence);
+    chunking.paragraph_silence = request.paragraph_silence.unwrap_or(chunking.paragraph_silence);
+    chunking.validate().map_err(|e| invalid(e.to_string()))?;
+    Ok(chunking)
+}
+
+fn voice_style_error(request_id: &str, path: &str, e: anyhow::Error) -> RequestError {
+    error!("[{}] Failed to load voice style {}: {}", request_id, path, e);
+    RequestError {
+        status: StatusCode::BAD_REQUEST,
+        message: format!("Failed to load voice style: {}", e),
+        type_: "invalid_request_error",
+        code: "voice_style_load_failed",
+    }
+}
+
+/// Load the styles of a voice's files, through the pool's cache when an
+/// engine is checked out, and blend them when there are several
+async fn load_voice(
+    engine_handle: Option<&EngineHandle>,
+    voice: &VoiceMix,
+    request_id: &str,
+) -> Result<Style, RequestError> {
+    let mut components = Vec::with_capacity(voice.components.len());
+    for (path, weight) in &voice.components {
+        let style = match engine_handle {
+            Some(engine_handle) => engine_handle.get_voice_style(path).await,
+            None => load_voice_style(std::slice::from_ref(path), false),
+        }
+        .map_err(|e| voice_style_error(request_id, path, e))?;
+        components.push((style, *weight));
+    }
+
+    if components.len() == 1 {
+        return Ok(components.remove(0).0);
+    }
+    blend_voice_styles(&components).map_err(|e| {
+        error!("[{}] Failed to blend voice styles: {}", request_id, e);
+        RequestError {
+            status: StatusCode::BAD_REQUEST,
+            message: format!("Failed to blend voice styles: {}", e),
+            type_: "invalid_request_error",
+            code: "invalid_voice_mix",
+        }
+    })
+}
+
+/// Check out an engine (or fall back to the single engine) and load the
+/// voice styles for a request that synthesizes off the async runtime.
+async fn acquire_engine(
+    state: &AppState,
+    voices: &VoicePaths,
+    ticket: &Ticket,
+    request_id: &str,
+) -> Result<(SynthesisEngine, VoiceStyles), AcquireError> {
+    if let Some(pool) = &state.engine_pool {
+        debug!("[{}] Using engine pool for TTS generation", request_id);
+
+        let engine_handle = pool.checkout(ticket).await.map_err(|e| checkout_error(e, request_id))?;
+
+        let style = load_voice(Some(&engine_handle), &voices.default, request_id).await?;
+        let mut styles = VoiceStyles::single(style);
+        for (name, path) in &voices.named {
+            let style = engine_handle
+                .get_voice_style(path)
+                .await
+                .map_err(|e| voice_style_error(request_id, path, e))?;
+            styles.named.insert(name.clone(), style);
+        }
+
+        Ok((SynthesisEngine::Pooled(engine_handle), styles))
+    } else {
+        debug!("[{}] Using single engine (fallback)", request_id);
+
+        let style = load_voice(None, &voices.default, request_id).await?;
+        let mut styles = VoiceStyles::single(style);
+        for (name, path) in &voices.named {
+            let style = load_voice_style(std::slice::from_ref(path), false)
+                .map_err(|e| voice_style_error(request_id, path, e))?;
+            styles.named.insert(name.clone(), style);
+        }
+
+        Ok((SynthesisEngine::Single(Arc::clone(&state.text_to_speech)), styles))
+    }
+}
+
+/// Message sent from the synthesis thread to a streaming response
+enum StreamMessage {
+    Chunk { chunk: SpeechChunk, sample_rate: i32 },
+    Done { duration: f32 },
+}
+
+#[derive(Debug, Serialize)]
+struct SseChunkEvent {
+    index: usize,
+    text: String,
+    start_time: f32,
+    duration: f32,
+    format: String,
+    audio: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SseDoneEvent {
+    chunks: usize,
+    total_duration: f32,
+    sample_rate: i32,
+    processing_time_ms: u128,
+}
+
+#[derive(Debug, Serialize)]
+struct SseErrorEvent {
+    message: String,
+}
+
+/// Synthesize the input's segments on an inference worker and send each chunk
+/// to `tx` as it completes, followed by a `Done` message. `tx` is unbounded so
+/// that a slow client never holds the engine and worker; at worst the whole
+/// clip is buffered, as for a non-streaming response.
+fn synthesize_stream(
+    engine: SynthesisEngine,
+    settings: TtsSettings,
+    input: SynthesisInput,
+    styles: VoiceStyles,
+    speed: f32,
+    request_id: String,
+    tx: mpsc::UnboundedSender<Result<StreamMessage>>,
+) {
+    let result = engine.run(&settings, &request_id, |text_to_speech| {
+        let sample_rate = text_to_speech.sample_rate;
+        text_to_speech.call_segments_streaming(&input.segments, &styles, settings.total_step, speed, &input.chunking, |chunk| {
+            debug!("[{}] Streaming chunk {} ({:.2}s)", request_id, chunk.index, chunk.duration);
+            tx.send(Ok(StreamMessage::Chunk { chunk, sample_rate }))
+                .map_err(|_| anyhow!("client disconnected"))
+        })
+    });
+
+    match result {
+        Ok(duration) => {
+            info!("[{}] Streamed {:.2}s of audio", request_id, duration);
+            let _ = tx.send(Ok(StreamMessage::Done { duration }));
+        }
+        Err(e) => {
+            error!("[{}] Streaming TTS generation failed: {}", request_id, e);
+            let _ = tx.send(Err(e));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn stream_speech(
+    state: AppState,
+    request: TtsRequest,
+    input: SynthesisInput,
+    ticket: Ticket,
+    request_id: String,
+    start_time: Instant,
+    stream_format: StreamFormat,
+    audio_format: AudioFormat,
+    encoder_settings: EncoderSettings,
+) -> Response {
+    let speed = request.speed.or(input.voices.default.speed).unwrap_or(state.config.tts.speed);
+
+    let (engine, styles) = match acquire_engine(&state, &input.voices, &ticket, &request_id).await {
+        Ok(acquired) => acquired,
+        Err(e) => return e.into_response(),
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let settings = state.config.tts.clone();
+    let task_request_id = request_id.clone();
+    let spawned = state.inference.spawn(move || {
+        synthesize_stream(engine, settings, input, styles, speed, task_request_id, tx)
+    });
+    if let Err(e) = spawned {
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("TTS generation failed: {}", e),
+            "internal_server_error",
+            "tts_generation_failed",
+        );
+    }
+
+    // Wait for the first chunk before committing to a 200 response
+    let first = match rx.recv().await {
+        Some(Ok(message)) => message,
+        Some(Err(e)) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("TTS generation failed: {}", e),
+                "internal_server_error",
+                "tts_generation_failed",
+            );
+        }
+        None => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "TTS generation ended without producing audio",
+                "internal_server_error",
+                "tts_generation_failed",
+            );
+        }
+    };
+
+    info!("[{}] First audio chunk ready after {:?}", request_id, start_time.elapsed());
+    let output_sample_rate = match &first {
+        StreamMessage::Chunk { sample_rate, .. } => Some(encoder_settings.output_sample_rate(audio_format, *sample_rate)),
+        StreamMessage::Done { .. } => None,
+    };
+
+    let messages = tokio_stream::once(Ok(first)).chain(UnboundedReceiverStream::new(rx));
+
+    let mut response = match stream_format {
+        StreamFormat::Audio => {
+            // Created on the first chunk, once the engine's sample rate is known
+            let mut encoder: Option<AudioStreamEncoder> = None;
+            let body_stream = messages.filter_map(move |message| match message {
+                Ok(StreamMessage::Chunk { chunk, sample_rate }) => {
+                    if encoder.is_none() {
+                        match AudioStreamEncoder::new(audio_format, sample_rate, &encoder_settings) {
+                            Ok(new_encoder) => encoder = Some(new_encoder),
+                            Err(e) => return Some(Err(std::io::Error::other(e.to_string()))),
+                        }
+                    }
+                    encoder.as_mut().map(|encoder| {
+                        encoder.encode(&chunk.audio).map_err(|e| std::io::Error::other(e.to_string()))
+                    })
+                }
+                Ok(StreamMessage::Done { .. }) => encoder
+                    .as_mut()
+                    .map(|encoder| encoder.finish().map_err(|e| std::io::Error::other(e.to_string()))),
+                Err(e) => Some(Err(std::io::Error::other(e.to_string()))),
+            });
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, audio_format.content_type())
+                .body(axum::body::Body::from_stream(body_stream))
+                .unwrap()
+        }
+        StreamFormat::Sse => {
+            let mut chunk_count = 0;
+            let mut chunk_sample_rate = 0;
+            let events = messages.map(move |message| {
+                let event = match message {
+                    Ok(StreamMessage::Chunk { chunk, sample_rate }) => {
+                        chunk_count += 1;
+                        chunk_sample_rate = encoder_settings.output_sample_rate(audio_format, sample_rate);
+                        match encode_audio(audio_format, &chunk.audio, sample_rate, &encoder_settings) {
+                            Ok(audio) => Event::default().event("chunk").json_data(SseChunkEvent {
+                                index: chunk.index,
+                                text: chunk.text,
+                                start_time: chunk.start_time,
+                                duration: chunk.duration,
+                                format: audio_format.name().to_string(),
+                                audio: BASE64.encode(audio),
+                            }),
+                            Err(e) => Event::default().event("error").json_data(SseErrorEvent {
+                                message: format!("Failed to encode audio: {}", e),
+                            }),
+                        }
+                    }
+                    Ok(StreamMessage::Done { duration }) => Event::default().event("done").json_data(SseDoneEvent {
+                        chunks: chunk_count,
+                        total_duration: duration,
+                        sample_rate: chunk_sample_rate,
+                        processing_time_ms: start_time.elapsed().as_millis(),
+                    }),
+                    Err(e) => Event::default().event("error").json_data(SseErrorEvent {
+                        message: format!("TTS generation failed: {}", e),
+                    }),
+                };
+                event.map_err(|e| std::io::Error::other(e.to_string()))
+            });
+
+            Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+        }
+    };
+
+    let headers = response.headers_mut();
+    headers.insert("X-Request-ID", HeaderValue::from_str(&request_id).unwrap());
+    if let Ok(value) = HeaderValue::from_str(request.model.as_deref().unwrap_or("supertts")) {
+        headers.insert("X-Model-Used", value);
+    }
+    let voice = request.voice.as_ref().map_or_else(|| "default".to_string(), VoiceParam::label);
+    if let Ok(value) = HeaderValue::from_str(&voice) {
+        headers.insert("X-Voice-Used", value);
+    }
+    headers.insert("X-Response-Format", HeaderValue::from_static(audio_format.name()));
+    if let Some(sample_rate) = output_sample_rate {
+        headers.insert("X-Sample-Rate", HeaderValue::from(sample_rate));
+    }
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+    response
+}
+
+// WebSocket Streaming
+
+/// Message sent by a WebSocket client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WsClientMessage {
+    /// Set the voice, speed, audio format, text normalization and/or
+    /// language for sentences that follow
+    Config {
+        voice: Option<VoiceParam>,
+        speed: Option<f32>,
+        response_format: Option<String>,
+        normalize: Option<bool>,
+        language: Option<String>,
+    },
+    /// Append a text delta to the sentence buffer
+    Text { text: String },
+    /// Synthesize the buffered partial sentence now
+    Flush,
+    /// Flush, then close the connection once all audio has been sent
+    Close,
+}
+
+/// JSON message sent to a WebSocket client. Each `audio` message is followed
+/// by a binary frame containing the sentence as a standalone audio file.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WsServerMessage {
+    Audio { index: usize, text: String, duration: f32, format: String },
+    Flushed,
+    Done { sentences: usize, total_duration: f32 },
+    Error { message: String },
+}
+
+/// Work queued by the reading half of the socket for the synthesis half, so
+/// text keeps being received while earlier sentences are synthesized.
+enum WsJob {
+    Sentence(WsSentence),
+    Flushed,
+    Error(String),
+    Close,
+}
+
+/// A sentence to synthesize with the session settings in effect when it was
+/// completed
+struct WsSentence {
+    text: String,
+    voice: VoiceMix,
+    speed: f32,
+    audio_format: AudioFormat,
+    normalize: bool,
+    language: String,
+}
+
+pub async fn tts_websocket(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let caller = match check_api_key(&headers, &state.config.auth) {
+        Ok(caller) => caller,
+        Err(status) => {
+            warn!("WebSocket authentication failed");
+            return status.into_response();
+        }
+    };
+    // The session's sentences share its class; each is scheduled by length
+    let ticket = match request_ticket(&caller, &headers, 0) {
+        Ok(ticket) => ticket,
+        Err(e) => return e.into_response(),
+    };
+
+    ws.on_upgrade(move |socket| handle_tts_socket(socket, state, ticket))
+}
+
+async fn handle_tts_socket(socket: WebSocket, state: AppState, ticket: Ticket) {
+    let request_id = Uuid::new_v4().to_string();
+    info!("[{}] WebSocket TTS session opened", request_id);
+
+    let (mut sender, mut receiver) = futures_util::StreamExt::split(socket);
+    let (job_tx, mut job_rx) = mpsc::channel::<WsJob>(WS_JOB_QUEUE_CAPACITY);
+
+    let writer_state = state.clone();
+    let writer_request_id = request_id.clone();
+    let writer = tokio::spawn(async move {
+        let mut index = 0;
+        let mut total_duration = 0.0;
+
+        while let Some(job) = job_rx.recv().await {
+            let messages = match job {
+                WsJob::Sentence(sentence) => {
+                    match synthesize_sentence(&writer_state, &sentence, &ticket, &writer_request_id).await {
+                        Ok((audio, duration)) => {
+                            let meta = WsServerMessage::Audio {
+                                index,
+                                text: sentence.text,
+                                duration,
+                                format: sentence.audio_format.name().to_string(),
+                            };
+                            index += 1;
+                            total_duration += duration;
+                            vec![ws_json(&meta), Message::Binary(audio)]
+                        }
+                        Err(message) => vec![ws_json(&WsServerMessage::Error { message })],
+                    }
+                }
+                WsJob::Flushed => vec![ws_json(&WsServerMessage::Flushed)],
+                WsJob::Error(message) => vec![ws_json(&WsServerMessage::Error { message })],
+                WsJob::Close => {
+                    let done = WsServerMessage::Done {
+                        sentences: index,
+                        total_duration,
+                    };
+                    let _ = sender.send(ws_json(&done)).await;
+                    let _ = sender.send(Message::Close(None)).await;
+                    break;
+                }
+            };
+
+            for message in messages {
+                if sender.send(message).await.is_err() {
+                    debug!("[{}] WebSocket client went away", writer_request_id);
+                    return;
+                }
+            }
+        }
+    });
+
+    let mut buffer = String::new();
+    let mut voice = match resolve_voice(&state, None) {
+        Ok(voice) => voice,
+        Err(e) => {
+            let _ = job_tx.send(WsJob::Error(e.message)).await;
+            VoiceMix::single(state.default_voice_style.clone())
+        }
+    };
+    // Speed set by the client; otherwise the voice's or the server's
+    let mut speed = None;
+    let mut audio_format = AudioFormat::Wav(WavSampleFormat::Int16);
+    let mut normalize = true;
+    let mut language = state.config.tts.language.clone();
+    let mut segmenter = state.segmenters.get(&language);
+
+    while let Some(Ok(message)) = receiver.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let client_message = match serde_json::from_str::<WsClientMessage>(&text) {
+            Ok(client_message) => client_message,
+            Err(e) => {
+                let _ = job_tx.send(WsJob::Error(format!("Invalid message: {}", e))).await;
+                continue;
+            }
+        };
+
+        let mut jobs = Vec::new();
+        match client_message {
+            WsClientMessage::Config {
+                voice: new_voice,
+                speed: new_speed,
+                response_format,
+                normalize: new_normalize,
+                language: new_language,
+            } => {
+                if let Some(new_speed) = new_speed {
+                    if (0.25..=4.0).contains(&new_speed) {
+                        speed = Some(new_speed);
+                    } else {
+                        jobs.push(WsJob::Error("Speed must be between 0.25 and 4.0".to_string()));
+                    }
+                }
+                if let Some(new_voice) = new_voice {
+                    match resolve_voice(&state, Some(&new_voice)) {
+                        Ok(resolved) => voice = resolved,
+                        Err(e) => jobs.push(WsJob::Error(e.message)),
+                    }
+                }
+                if let Some(response_format) = response_format {
+                    match AudioFormat::parse(&response_format) {
+                        Some(format) => audio_format = format,
+                        None => jobs.push(WsJob::Error(format!(
+                            "Response format '{}' is not supported. Supported formats: {}.",
+                            response_format,
+                            AudioFormat::SUPPORTED.join(", ")
+                        ))),
+                    }
+                }
+                if let Some(new_normalize) = new_normalize {
+                    normalize = new_normalize;
+                }
+                if let Some(new_language) = new_language {
+                    if is_valid_language(&new_language) {
+                        segmenter = state.segmenters.get(&new_language);
+                        language = new_language;
+                    } else {
+                        jobs.push(WsJob::Error(format!("Invalid language code '{}'", new_language)));
+                    }
+                }
+            }
+            WsClientMessage::Text { text } => {
+                buffer.push_str(&text);
+                let max_chars = state.config.tts.max_chunk_chars;
+                for sentence in drain_complete_sentences(&mut buffer, &segmenter, max_chars) {
+                    jobs.push(WsJob::Sentence(WsSentence {
+                        text: sentence,
+                        voice: voice.clone(),
+                        speed: speed.or(voice.speed).unwrap_or(state.config.tts.speed),
+                        audio_format,
+                        normalize,
+                        language: language.clone(),
+                    }));
+                }
+            }
+            WsClientMessage::Flush | WsClientMessage::Close => {
+                let remainder = buffer.trim();
+                if !remainder.is_empty() {
+                    jobs.push(WsJob::Sentence(WsSentence {
+                        text: remainder.to_string(),
+                        voice: voice.clone(),
+                        speed: speed.or(voice.speed).unwrap_or(state.config.tts.speed),
+                        audio_format,
+                        normalize,
+                        language: language.clone(),
+                    }));
+                }
+                buffer.clear();
+                jobs.push(match client_message {
+                    WsClientMessage::Close => WsJob::Close,
+                    _ => WsJob::Flushed,
+                });
+            }
+        }
+
+        let closing = jobs.iter().any(|job| matches!(job, WsJob::Close));
+        for job in jobs {
+            if job_tx.send(job).await.is_err() {
+                break;
+            }
+        }
+        if closing {
+            break;
+        }
+    }
+
+    // Let the writer finish any queued sentences before the session ends
+    drop(job_tx);
+    let _ = writer.await;
+    info!("[{}] WebSocket TTS session closed", request_id);
+}
+
+/// Split input into segments and rewrite each segment's text for the model
+fn rewrite_input(
+    state: &AppState,
+    input: &str,
+    format: InputFormat,
+    language: &str,
+    lexicon: Option<&LexiconEntries>,
+    normalize: bool,
+) -> Result<Vec<TextSegment>, RequestError> {
+    let code_block_phrase = state.config.tts.code_block_phrase.as_deref();
+    let mut segments = input_segments(input, format, code_block_phrase).map_err(|e| RequestError {
+        status: StatusCode::BAD_REQUEST,
+        message: e.to_string(),
+        type_: "invalid_request_error",
+        code: "invalid_ssml",
+    })?;
+
+    let inline_lexicon = match lexicon {
+        Some(entries) => Some(Lexicon::new(entries.clone()).map_err(invalid_lexicon)?),
+        None => None,
+    };
+    for segment in &mut segments {
+        segment.text = rewrite_text(state, inline_lexicon.as_ref(), &segment.text, language, normalize);
+    }
+    Ok(segments)
+}
+
+/// The request's language, or the server default
+fn request_language(state: &AppState, value: Option<&str>) -> Result<String, RequestError> {
+    match value {
+        None => Ok(state.config.tts.language.clone()),
+        Some(value) if is_valid_language(value) => Ok(value.to_string()),
+        Some(value) => Err(RequestError {
+            status: StatusCode::BAD_REQUEST,
+            message: format!("Language '{}' is not a valid language code such as \"en\" or \"pt-BR\".", value),
+            type_: "invalid_request_error",
+            code: "invalid_language",
+        }),
+    }
+}
+
+/// The request's input format, or the server default
+fn input_format(state: &AppState, value: Option<&str>) -> Result<InputFormat, RequestError> {
+    match value {
+        None => Ok(state.config.tts.input_format),
+        Some(value) => InputFormat::parse(value).ok_or_else(|| RequestError {
+            status: StatusCode::BAD_REQUEST,
+            message: format!(
+                "Input format '{}' is not supported. Use one of: {}.",
+                value,
+                InputFormat::SUPPORTED.join(", ")
+            ),
+            type_: "invalid_request_error",
+            code: "invalid_input_format",
+        }),
+    }
+}
+
+/// The request's unknown-character policy, or the server default
+fn unknown_char_policy(state: &AppState, value: Option<&str>) -> Result<UnknownCharPolicy, RequestError> {
+    match value {
+        None => Ok(state.config.tts.unknown_char_policy),
+        Some(value) => UnknownCharPolicy::parse(value).ok_or_else(|| RequestError {
+            status: StatusCode::BAD_REQUEST,
+            message: format!(
+                "Unknown character policy '{}' is not supported. Use one of: {}.",
+                value,
+                UnknownCharPolicy::SUPPORTED.join(", ")
+            ),
+            type_: "invalid_request_error",
+            code: "invalid_unknown_char_policy",
+        }),
+    }
+}
+
+/// Make every segment readable by the model. Under the strict policy the
+/// error lists each unreadable character with its offsets in `input`.
+fn apply_unknown_char_policy(
+    state: &AppState,
+    input: &str,
+    segments: &mut [TextSegment],
+    policy: UnknownCharPolicy,
+) -> Result<(), RequestError> {
+    let Some(processor) = &state.text_processor else {
+        return Ok(());
+    };
+
+    let mut rejected = Vec::new();
+    for segment in segments.iter_mut() {
+        match processor.apply_unknown_char_policy(&segment.text, policy, &state.config.tts.unknown_char_replacement) {
+            Ok(text) => segment.text = text,
+            Err(unsupported) => rejected.extend(unsupported.into_iter().map(|u| u.character)),
+        }
+    }
+    if rejected.is_empty() {
+        return Ok(());
+    }
+
+    let described = locate_chars(input, &rejected)
+        .iter()
+        .map(|u| match u.positions.as_slice() {
+            [] => format!("'{}' ({}, introduced by rewriting)", u.character, u.code_point),
+            positions => format!(
+                "'{}' ({}) at {}",
+                u.character,
+                u.code_point,
+                positions.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ")
+            ),
+        })
+        .collect::<Vec<_>>();
+    Err(RequestError {
+        status: StatusCode::BAD_REQUEST,
+        message: format!("Input contains characters the model cannot read: {}", described.join("; ")),
+        type_: "invalid_request_error",
+        code: "unsupported_characters",
+    })
+}
+
+/// Character offsets in `input` of each of `characters`. Characters that
+/// only appear after lexicon or normalization rewriting have no offsets.
+fn locate_chars(input: &str, characters: &[char]) -> Vec<UnsupportedChar> {
+    let mut located: Vec<UnsupportedChar> = Vec::new();
+    for &c in characters {
+        if located.iter().any(|u| u.character == c) {
+            continue;
+        }
+        let positions = input.chars().enumerate().filter(|&(_, d)| d == c).map(|(i, _)| i).collect();
+        located.push(UnsupportedChar::new(c, positions));
+    }
+    located
+}
+
+/// Apply the request lexicon, the server lexicon and (if enabled) text
+/// normalization for `language`, in that order, so respellings are not
+/// normalized away
+fn rewrite_text(
+    state: &AppState,
+    inline_lexicon: Option<&Lexicon>,
+    text: &str,
+    language: &str,
+    normalize: bool,
+) -> String {
+    let mut text = match inline_lexicon {
+        Some(lexicon) => lexicon.apply(text),
+        None => text.to_string(),
+    };
+    {
+        let lexicon = state.lexicon.read().unwrap();
+        if !lexicon.is_empty() {
+            text = lexicon.apply(&text);
+        }
+    }
+    match normalizer_for(language) {
+        Some(normalizer) if normalize => normalizer.normalize(&text),
+        _ => text,
+    }
+}
+
+/// Synthesize one sentence on a pooled engine and encode it in the sentence's
+/// audio format
+async fn synthesize_sentence(
+    state: &AppState,
+    sentence: &WsSentence,
+    ticket: &Ticket,
+    request_id: &str,
+) -> Result<(Vec<u8>, f32), String> {
+    // Rewrite the text first, so that a rejected sentence never takes an engine
+    let rewritten = rewrite_text(state, None, &sentence.text, &sentence.language, sentence.normalize);
+    let mut segments = vec![TextSegment::plain(&rewritten)];
+    apply_unknown_char_policy(state, &sentence.text, &mut segments, state.config.tts.unknown_char_policy)
+        .map_err(|e| e.message)?;
+    let text = std::mem::take(&mut segments[0].text);
+
+    let voices = VoicePaths::single(sentence.voice.clone());
+    let ticket = Ticket {
+        cost: sentence.text.chars().count(),
+        ..ticket.clone()
+    };
+    let (engine, styles) = acquire_engine(state, &voices, &ticket, request_id)
+        .await
+        .map_err(AcquireError::message)?;
+
+    let settings = state.config.tts.clone();
+    let chunking = settings.chunk_settings(state.segmenters.get(&sentence.language));
+    let (speed, audio_format) = (sentence.speed, sentence.audio_format);
+    let task_request_id = request_id.to_string();
+
+    state
+        .inference
+        .run(move || {
+            engine.run(&settings, &task_request_id, |text_to_speech| {
+                let (wav, duration) = text_to_speech.call(&text, &styles.default, settings.total_step, speed, &chunking)?;
+                let buffer = encode_audio(audio_format, &wav, text_to_speech.sample_rate, &settings.encoder_settings())?;
+                Ok((buffer, duration))
+            })
+        })
+        .await
+        .map_err(|e| format!("TTS generation failed: {}", e))?
+    .map_err(|e| {
+        error!("[{}] TTS generation failed: {}", request_id, e);
+        format!("TTS generation failed: {}", e)
+    })
+}
+
+fn ws_json(message: &WsServerMessage) -> Message {
+    Message::Text(serde_json::to_string(message).unwrap_or_default())
+}
+
+// Pronunciation Lexicon
+
+#[derive(Debug, Serialize)]
+pub struct LexiconResponse {
+    pub entries: LexiconEntries,
+    pub path: Option<String>,
+}
+
+/// One lexicon entry together with its pattern
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LexiconEntryBody {
+    pub pattern: String,
+    #[serde(flatten)]
+    pub entry: LexiconEntry,
+}
+
+fn invalid_lexicon(e: anyhow::Error) -> RequestError {
+    RequestError {
+        status: StatusCode::BAD_REQUEST,
+        message: e.to_string(),
+        type_: "invalid_request_error",
+        code: "invalid_lexicon",
+    }
+}
+
+fn lexicon_entry_not_found(pattern: &str) -> RequestError {
+    RequestError {
+        status: StatusCode::NOT_FOUND,
+        message: format!("Lexicon entry '{}' not found", pattern),
+        type_: "invalid_request_error",
+        code: "lexicon_entry_not_found",
+    }
+}
+
+fn lexicon_response(state: &AppState, lexicon: &Lexicon) -> Response {
+    Json(LexiconResponse {
+        entries: lexicon.entries().clone(),
+        path: state.config.tts.lexicon_path.clone(),
+    })
+    .into_response()
+}
+
+/// Apply `change` to a copy of the lexicon entries, then save the result
+/// (when a lexicon file is configured) and make it live
+fn update_lexicon<F>(state: &AppState, change: F) -> Result<(), RequestError>
+where
+    F: FnOnce(&mut LexiconEntries) -> Result<(), RequestError>,
+{
+    let mut lexicon = state.lexicon.write().unwrap();
+    let mut entries = lexicon.entries().clone();
+    change(&mut entries)?;
+
+    let updated = Lexicon::new(entries).map_err(invalid_lexicon)?;
+    if let Some(path) = &state.config.tts.lexicon_path {
+        updated.save(path).map_err(|e| {
+            error!("Failed to save lexicon: {:#}", e);
+            RequestError {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("Failed to save lexicon: {:#}", e),
+                type_: "internal_server_error",
+                code: "lexicon_save_failed",
+            }
+        })?;
+    }
+    *lexicon = updated;
+    Ok(())
+}
+
+pub async fn list_lexicon(State(state): State<AppState>, headers: HeaderMap) -> Result<Response, StatusCode> {
+    check_api_key(&headers, &state.config.auth)?;
+    let lexicon = state.lexicon.read().unwrap();
+    Ok(lexicon_response(&state, &lexicon))
+}
+
+pub async fn get_lexicon_entry(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    UrlPath(pattern): UrlPath<String>,
+) -> Result<Response, StatusCode> {
+    check_api_key(&headers, &state.config.auth)?;
+    let lexicon = state.lexicon.read().unwrap();
+    Ok(match lexicon.entries().get(&pattern) {
+        Some(entry) => Json(LexiconEntryBody { entry: entry.clone(), pattern }).into_response(),
+        None => lexicon_entry_not_found(&pattern).into_response(),
+    })
+}
+
+pub async fn create_lexicon_entry(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<LexiconEntryBody>,
+) -> Result<Response, StatusCode> {
+    if let Err(e) = check_required_api_key(&headers, &state.config.auth) {
+        return Ok(e.into_response());
+    }
+    let result = update_lexicon(&state, |entries| {
+        if entries.contains_key(&body.pattern) {
+            return Err(RequestError {
+                status: StatusCode::CONFLICT,
+                message: format!("Lexicon entry '{}' already exists", body.pattern),
+                type_: "invalid_request_error",
+                code: "lexicon_entry_exists",
+            });
+        }
+        entries.insert(body.pattern.clone(), body.entry.clone());
+        Ok(())
+    });
+
+    Ok(match result {
+        Ok(()) => {
+            info!("Lexicon entry '{}' created", body.pattern);
+            (StatusCode::CREATED, Json(body)).into_response()
+        }
+        Err(e) => e.into_response(),
+    })
+}
+
+pub async fn update_lexicon_entry(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    UrlPath(pattern): UrlPath<String>,
+    Json(entry): Json<LexiconEntry>,
+) -> Result<Response, StatusCode> {
+    if let Err(e) = check_required_api_key(&headers, &state.config.auth) {
+        return Ok(e.into_response());
+    }
+    let result = update_lexicon(&state, |entries| {
+        entries.insert(pattern.clone(), entry.clone());
+        Ok(())
+    });
+
+    Ok(match result {
+        Ok(()) => {
+            info!("Lexicon entry '{}' updated", pattern);
+            Json(LexiconEntryBody { pattern, entry }).into_response()
+        }
+        Err(e) => e.into_response(),
+    })
+}
+
+pub async fn delete_lexicon_entry(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    UrlPath(pattern): UrlPath<String>,
+) -> Result<Response, StatusCode> {
+    if let Err(e) = check_required_api_key(&headers, &state.config.auth) {
+        return Ok(e.into_response());
+    }
+    let result = update_lexicon(&state, |entries| match entries.remove(&pattern) {
+        Some(_) => Ok(()),
+        None => Err(lexicon_entry_not_found(&pattern)),
+    });
+
+    Ok(match result {
+        Ok(()) => {
+            info!("Lexicon entry '{}' deleted", pattern);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => e.into_response(),
+    })
+}
+
+/// Re-read the lexicon file, e.g. after editing it by hand. On failure the
+/// current lexicon stays in use.
+pub async fn reload_lexicon(State(state): State<AppState>, headers: HeaderMap) -> Result<Response, StatusCode> {
+    if let Err(e) = check_required_api_key(&headers, &state.config.auth) {
+        return Ok(e.into_response());
+    }
+    let Some(path) = &state.config.tts.lexicon_path else {
+        return Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            "No lexicon file is configured (tts.lexicon_path)",
+            "invalid_request_error",
+            "lexicon_not_configured",
+        ));
+    };
+
+    match Lexicon::load(path) {
+        Ok(lexicon) => {
+            info!("Reloaded lexicon from {} ({} entries)", path, lexicon.entries().len());
+            let response = lexicon_response(&state, &lexicon);
+            *state.lexicon.write().unwrap() = lexicon;
+            Ok(response)
+        }
+        Err(e) => {
+            error!("Failed to reload lexicon: {:#}", e);
+            Ok(error_response(
+                StatusCode::BAD_REQUEST,
+                format!("{:#}", e),
+                "invalid_request_error",
+                "invalid_lexicon",
+            ))
+        }
+    }
+}
+
+// Character Coverage
+
+/// Body of `POST /v1/text/coverage`: the text options of a speech request
+#[derive(Debug, Deserialize)]
+pub struct CoverageRequest {
+    pub input: String,
+    pub input_format: Option<String>,
+    pub language: Option<String>,
+    pub normalize: Option<bool>,
+    pub lexicon: Option<LexiconEntries>,
+    pub unknown_char_policy: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CoverageResponse {
+    /// Text after SSML or markup conversion, the lexicon and normalization
+    pub text: String,
+    /// Non-whitespace characters in `text`
+    pub characters: usize,
+    pub supported_characters: usize,
+    /// Fraction of `characters` the model can read
+    pub coverage: f32,
+    /// Unreadable characters with their offsets in `input`
+    pub unsupported_characters: Vec<UnsupportedChar>,
+    pub unknown_char_policy: String,
+    /// Text the model would receive under the policy, or null if it rejects the input
+    pub synthesized_text: Option<String>,
+}
+
+/// Dry run of the text pipeline: report which characters of the input the
+/// model cannot read and what the unknown-character policy would do
+pub async fn text_coverage(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<CoverageRequest>,
+) -> Result<Response, StatusCode> {
+    check_api_key(&headers, &state.config.auth)?;
+    let Some(processor) = state.text_processor.clone() else {
+        return Ok(error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The model's unicode indexer is not loaded",
+            "internal_server_error",
+            "indexer_unavailable",
+        ));
+    };
+
+    let result = unknown_char_policy(&state, request.unknown_char_policy.as_deref()).and_then(|policy| {
+        let format = input_format(&state, request.input_format.as_deref())?;
+        let language = request_language(&state, request.language.as_deref())?;
+        let segments = rewrite_input(
+            &state,
+            &request.input,
+            format,
+            &language,
+            request.lexicon.as_ref(),
+            request.normalize.unwrap_or(true),
+        )?;
+        Ok((policy, segments))
+    });
+    let (policy, mut segments) = match result {
+        Ok(result) => result,
+        Err(e) => return Ok(e.into_response()),
+    };
+
+    let text = segments.iter().map(|segment| segment.text.as_str()).collect::<Vec<_>>().join(" ");
+    let unsupported: Vec<char> = processor.unsupported_chars(&text).iter().map(|u| u.character).collect();
+    let characters = text.chars().filter(|c| !c.is_whitespace()).count();
+    let supported_characters = text.chars().filter(|c| !c.is_whitespace() && processor.supports(*c)).count();
+
+    let synthesized_text = match apply_unknown_char_policy(&state, &request.input, &mut segments, policy) {
+        Ok(()) => Some(segments.iter().map(|segment| segment.text.as_str()).collect::<Vec<_>>().join(" ")),
+        Err(_) => None,
+    };
+
+    Ok(Json(CoverageResponse {
+        characters,
+        supported_characters,
+        coverage: if characters == 0 { 1.0 } else { supported_characters as f32 / characters as f32 },
+        unsupported_characters: locate_chars(&request.input, &unsupported),
+        unknown_char_policy: policy.name().to_string(),
+        synthesized_text,
+        text,
+    })
+    .into_response())
+}
+
+pub fn create_router(state: AppState) -> Router {
+    Router::new()
+        .route("/health", get(health_check))
+        .route("/voices", get(list_voices))
+        .route("/v1/voices", post(upload_voice))
+        .route("/v1/voices/:id", get(get_voice).delete(delete_voice))
+        .route("/v1/voices/mix", post(create_voice_mix))
+        .route("/v1/audio/speech", post(tts_speech))
+        .route("/v1/audio/speech/ws", get(tts_websocket))
+        .route("/v1/lexicon", get(list_lexicon))
+        .route("/v1/lexicon/entries", post(create_lexicon_entry))
+        .route(
+            "/v1/lexicon/entries/:pattern",
+            get(get_lexicon_entry).put(update_lexicon_entry).delete(delete_lexicon_entry),
+        )
+        .route("/v1/lexicon/reload", post(reload_lexicon))
+        .route("/v1/text/coverage", post(text_coverage))
+        .layer(
             ServiceBuilder::new()
                 .layer(CorsLayer::permissive())
         )
@@ -673,6 +2643,38 @@ pub fn create_router(state: AppState) -> Router {
 }
 
 pub async fn start_server(config: ServerConfig) -> Result<()> {
+    config
+        .tts
+        .chunk_settings(Arc::new(Segmenter::new(&config.tts.language, Vec::new())))
+        .validate()
+        .map_err(|e| anyhow!("Invalid chunking settings in config: {}", e))?;
+    if !is_valid_language(&config.tts.language) {
+        return Err(anyhow!("Invalid language '{}' in config", config.tts.language));
+    }
+    let voices = config
+        .tts
+        .voice_registry()
+        .map_err(|e| anyhow!("Invalid voice registry in config: {}", e))?;
+    for voice in voices.voices() {
+        let path = voices.path_of(voice);
+        if !Path::new(&path).exists() {
+            warn!("Style file of voice '{}' not found: {}", voice.id, path);
+        }
+    }
+
+    config
+        .tts
+        .priority_weights
+        .validate()
+        .map_err(|e| anyhow!("Invalid priority weights in config: {}", e))?;
+
+    let inference_workers = config.tts.inference_worker_count();
+    if !(1..=64).contains(&inference_workers) {
+        return Err(anyhow!("inference_workers must be between 1 and 64, got {}", inference_workers));
+    }
+    let inference = InferenceWorkers::new(inference_workers)?;
+    info!("Started {} inference worker(s)", inference_workers);
+
     let bind_addr = format!("{}:{}", config.server.host, config.server.port);
     let listener = TcpListener::bind(&bind_addr).await
         .map_err(|e| anyhow!("Failed to bind to {}: {}", bind_addr, e))?;
@@ -685,6 +2687,12 @@ pub async fn start_server(config: ServerConfig) -> Result<()> {
             engine_pool_size: config.tts.engine_pool_size,
             warmup_on_startup: config.tts.warmup_on_startup,
             engine_checkout_timeout_ms: config.tts.engine_checkout_timeout_ms,
+            max_queue_depth: config.tts.max_queue_depth,
+            priority_weights: config.tts.priority_weights.clone(),
+            engine_failure_threshold: config.tts.engine_failure_threshold,
+            engine_probe_interval_secs: config.tts.engine_probe_interval_secs,
+            probe_voice_style: resolve_voice_style_path(&voices, None, &config.tts.default_voice_style)
+                .unwrap_or_else(|_| config.tts.default_voice_style.clone()),
             voice_style_cache_size: config.tts.voice_style_cache_size,
             onnx_dir: config.tts.onnx_dir.clone(),
             use_gpu: config.tts.use_gpu,
@@ -705,12 +2713,44 @@ pub async fn start_server(config: ServerConfig) -> Result<()> {
         None
     };
 
+    let lexicon = match &config.tts.lexicon_path {
+        Some(path) => {
+            let lexicon = Lexicon::load(path)?;
+            info!("Loaded lexicon from {} ({} entries)", path, lexicon.entries().len());
+            lexicon
+        }
+        None => Lexicon::default(),
+    };
+
+    let segmenters = match &config.tts.abbreviations_path {
+        Some(path) => {
+            let segmenters = Segmenters::load(path)?;
+            info!("Loaded abbreviations from {}", path);
+            segmenters
+        }
+        None => Segmenters::default(),
+    };
+
+    let indexer_path = Path::new(&config.tts.onnx_dir).join("unicode_indexer.json");
+    let text_processor = match UnicodeProcessor::new(&indexer_path) {
+        Ok(processor) => Some(Arc::new(processor)),
+        Err(e) => {
+            warn!("Failed to load {}: {}. Input characters will not be checked.", indexer_path.display(), e);
+            None
+        }
+    };
+
     // Initialize application state
     let state = AppState {
         default_voice_style: config.tts.default_voice_style.clone(),
         config: config.clone(),
         text_to_speech: Arc::new(Mutex::new(None)), // Kept for backward compatibility
         engine_pool,
+        lexicon: Arc::new(RwLock::new(lexicon)),
+        text_processor,
+        segmenters: Arc::new(segmenters),
+        voices: Arc::new(RwLock::new(voices)),
+        inference: Arc::new(inference),
     };
 
     let router = create_router(state);
@@ -719,7 +2759,12 @@ pub async fn start_server(config: ServerConfig) -> Result<()> {
     info!("Available endpoints:");
     info!("  GET  /health - Health check (includes pool stats if pool is enabled)");
     info!("  GET  /voices - List available voice styles");
+    info!("  POST /v1/voices - Upload a voice style (GET/DELETE /v1/voices/{{id}} to manage it)");
+    info!("  POST /v1/voices/mix - Save a blend of voices as a new voice");
     info!("  POST /v1/audio/speech - OpenAI compatible TTS endpoint");
+    info!("  GET  /v1/audio/speech/ws - WebSocket TTS for incremental text input");
+    info!("  GET  /v1/lexicon - Pronunciation lexicon (entries under /v1/lexicon/entries)");
+    info!("  POST /v1/text/coverage - Report characters the model cannot read");
 
     axum::serve(listener, router).await
         .map_err(|e| anyhow!("Server error: {}", e))?;
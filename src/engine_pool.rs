@@ -1,16 +1,18 @@
 
 use anyhow::{anyhow, Result};
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
-use tokio::sync::{Mutex, RwLock, Semaphore};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{Mutex, RwLock};
 use tokio::time::timeout;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use crate::helper::{load_text_to_speech, load_voice_style, TextToSpeech, Style};
+use crate::helper::{load_text_to_speech, load_voice_style, ChunkSettings, TextToSpeech, Style};
+use crate::scheduler::{Admission, Lease, PriorityClass, PriorityWeights, Scheduler, Ticket, Waiting};
 
 
 #[derive(Debug, Clone)]
@@ -19,8 +21,19 @@ pub struct EnginePoolConfig {
     pub engine_pool_size: usize,
     /// Whether to preload engines on startup
     pub warmup_on_startup: bool,
-    /// Timeout for engine checkout in milliseconds
+    /// Longest a request waits in the queue for an engine, in milliseconds
     pub engine_checkout_timeout_ms: u64,
+    /// Most requests that may wait for an engine; further requests are
+    /// turned away
+    pub max_queue_depth: usize,
+    /// Share of the engines each priority class gets while both wait
+    pub priority_weights: PriorityWeights,
+    /// Consecutive failures after which an engine is replaced
+    pub engine_failure_threshold: u32,
+    /// Seconds between probes of every engine; no probes when unset
+    pub engine_probe_interval_secs: Option<u64>,
+    /// Voice style file the probes synthesize with
+    pub probe_voice_style: String,
     /// Maximum number of voice styles to cache
     pub voice_style_cache_size: usize,
     /// ONNX model directory
@@ -35,6 +48,11 @@ impl Default for EnginePoolConfig {
             engine_pool_size: 1,
             warmup_on_startup: false,
             engine_checkout_timeout_ms: 5000,
+            max_queue_depth: 32,
+            priority_weights: PriorityWeights::default(),
+            engine_failure_threshold: 3,
+            engine_probe_interval_secs: None,
+            probe_voice_style: "assets/voice_styles/M1.json".to_string(),
             voice_style_cache_size: 10,
             onnx_dir: "assets/onnx".to_string(),
             use_gpu: false,
@@ -91,51 +109,287 @@ impl CacheEntry {
 }
 
 
+/// Voice styles are cached by canonical path, so that different spellings of
+/// a path share one entry
+fn voice_cache_key(voice_path: &str) -> String {
+    Path::new(voice_path)
+        .canonicalize()
+        .unwrap_or_else(|_| PathBuf::from(voice_path))
+        .to_string_lossy()
+        .to_string()
+}
+
+
+/// Retry estimate used until some leases have completed
+const DEFAULT_LEASE_ESTIMATE: Duration = Duration::from_secs(1);
+
+/// Number of recent queue waits and leases the averages are taken over
+const RECENT_SAMPLES: usize = 32;
+
+/// The most recent durations of something, for a moving average
+#[derive(Debug, Default)]
+struct RecentDurations {
+    samples: VecDeque<Duration>,
+}
+
+impl RecentDurations {
+    fn push(&mut self, duration: Duration) {
+        if self.samples.len() == RECENT_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(duration);
+    }
+
+    fn average(&self) -> Option<Duration> {
+        let count = u32::try_from(self.samples.len()).ok().filter(|&count| count > 0)?;
+        Some(self.samples.iter().sum::<Duration>() / count)
+    }
+}
+
+/// How long requests recently waited for an engine, overall and per
+/// priority class, and how long they held it
+#[derive(Debug, Default)]
+struct QueueTimings {
+    waits: RecentDurations,
+    class_waits: BTreeMap<PriorityClass, RecentDurations>,
+    leases: RecentDurations,
+}
+
+/// Checkout counts of one priority class
+#[derive(Debug, Default, Clone, Copy)]
+struct ClassCounts {
+    checkouts: u64,
+    rejected: u64,
+    timed_out: u64,
+}
+
+/// Why `checkout` returned no engine
+#[derive(Debug)]
+pub enum CheckoutError {
+    /// The queue was full; the request was not admitted
+    QueueFull { retry_after: Duration },
+    /// The request waited out the checkout timeout
+    Timeout { waited: Duration, retry_after: Duration },
+    /// A new engine could not be loaded
+    Engine(anyhow::Error),
+}
+
+impl std::fmt::Display for CheckoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckoutError::QueueFull { .. } => write!(f, "All engines are busy and the request queue is full"),
+            CheckoutError::Timeout { waited, .. } => {
+                write!(f, "Engine checkout timeout after {}ms", waited.as_millis())
+            }
+            CheckoutError::Engine(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CheckoutError {}
+
+
+// Engine Health
+
+/// Text synthesized by engine probes
+const PROBE_TEXT: &str = "Health check.";
+
+/// Denoising steps of a probe; enough to run every model
+const PROBE_TOTAL_STEP: usize = 1;
+
+/// Client that probes are scheduled as
+const PROBE_CLIENT: &str = "health-probe";
+
+/// Whether `e` was raised by ONNX Runtime, rather than by the request
+/// (such as a client that went away)
+fn is_engine_error(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| cause.is::<ort::Error>())
+}
+
+/// Outcome of the latest probe of an engine
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub at: String,
+    pub ok: bool,
+}
+
+/// Failures and probe results of one engine
+#[derive(Debug, Clone)]
+struct EngineHealth {
+    loaded_at: String,
+    syntheses: u64,
+    consecutive_failures: u32,
+    total_failures: u64,
+    last_error: Option<String>,
+    last_probe: Option<ProbeResult>,
+    /// Taken out of service and being replaced
+    quarantined: bool,
+}
+
+impl EngineHealth {
+    fn new() -> Self {
+        EngineHealth {
+            loaded_at: chrono::Utc::now().to_rfc3339(),
+            syntheses: 0,
+            consecutive_failures: 0,
+            total_failures: 0,
+            last_error: None,
+            last_probe: None,
+            quarantined: false,
+        }
+    }
+
+    fn record(&mut self, error: Option<String>) {
+        self.syntheses += 1;
+        match error {
+            Some(error) => {
+                self.consecutive_failures += 1;
+                self.total_failures += 1;
+                self.last_error = Some(error);
+            }
+            None => self.consecutive_failures = 0,
+        }
+    }
+}
+
+
+// Pooled Engines
+
+/// What the pool needs of an engine. Implemented by the TTS model, and by
+/// fake engines in tests.
+pub trait PoolEngine: Send + 'static {
+    /// Load a new engine; runs on a blocking thread
+    fn load(config: &EnginePoolConfig) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Synthesize the probe text
+    fn probe(&mut self, style: &Style) -> Result<()>;
+}
+
+impl PoolEngine for TextToSpeech {
+    fn load(config: &EnginePoolConfig) -> Result<Self> {
+        load_text_to_speech(&config.onnx_dir, config.use_gpu)
+    }
+
+    fn probe(&mut self, style: &Style) -> Result<()> {
+        self.call(PROBE_TEXT, style, PROBE_TOTAL_STEP, 1.0, &ChunkSettings::default()).map(|_| ())
+    }
+}
+
+
 // Engine Checkout Handle
-pub struct EngineHandle {
+
+/// Exclusive lease of one pool engine. The engine goes back to the idle
+/// queue, and its lease to the scheduler, when the handle is dropped; an
+/// engine that failed too often is replaced first.
+pub struct EngineHandle<E: PoolEngine = TextToSpeech> {
     engine_id: String,
-    pool: Arc<TTSEnginePool>,
+    engine: Arc<Mutex<E>>,
+    pool: TTSEnginePool<E>,
+    leased_at: Instant,
+    lease: Option<Lease>,
 }
 
-impl EngineHandle {
+impl<E: PoolEngine> EngineHandle<E> {
     /// Get the TTS engine
-    pub async fn engine(&self) -> Result<Arc<Mutex<TextToSpeech>>> {
-        let engines = self.pool.engines.read().await;
-        // Find engine by ID
-        for (id, engine) in engines.iter() {
-            if id == &self.engine_id {
-                return Ok(Arc::clone(engine));
-            }
-        }
-        drop(engines);
-        Err(anyhow!("Engine {} not found", self.engine_id))
+    pub fn engine(&self) -> Arc<Mutex<E>> {
+        Arc::clone(&self.engine)
     }
 
     /// Get cached voice style or load if not cached
     pub async fn get_voice_style(&self, voice_path: &str) -> Result<Style> {
         self.pool.get_voice_style(voice_path).await
     }
+
+    /// Record the outcome of a synthesis on this engine. Only errors raised
+    /// by ONNX Runtime count against the engine.
+    pub fn record<T>(&self, result: &Result<T>) {
+        let error = match result {
+            Ok(_) => None,
+            Err(e) if is_engine_error(e) => Some(e.to_string()),
+            Err(_) => return,
+        };
+        self.pool.record(&self.engine_id, error);
+    }
 }
 
-pub struct TTSEnginePool {
-    config: EnginePoolConfig,
-    engines: Arc<RwLock<HashMap<String, Arc<Mutex<TextToSpeech>>>>>,
-    semaphore: Arc<Semaphore>,
+impl<E: PoolEngine> Drop for EngineHandle<E> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.pool.record(&self.engine_id, Some("Synthesis panicked".to_string()));
+        }
+        self.pool.leased.fetch_sub(1, Ordering::SeqCst);
+        self.pool.timings.lock().unwrap().leases.push(self.leased_at.elapsed());
+
+        if self.pool.should_replace(&self.engine_id) {
+            // The replacement keeps the lease, so the pool does not load
+            // another engine in the meantime
+            if let Some(lease) = self.lease.take() {
+                self.pool.replace_engine(self.engine_id.clone(), lease);
+            }
+            return;
+        }
+
+        // Runs before the lease field is dropped, so the next holder of the
+        // lease finds this engine idle
+        self.pool.idle.lock().unwrap().push_back(self.engine_id.clone());
+        debug!("Returned engine {}", self.engine_id);
+    }
+}
+
+/// Engines are leased exclusively: a checkout takes a lease from the
+/// scheduler (one per engine the pool may hold) and the engine that has been
+/// idle longest, loading a new engine when none is idle. Every engine is
+/// either idle or held by a lease holder, so the pool never grows past its
+/// size.
+///
+/// Requests that find every engine leased wait in a bounded queue, served in
+/// weighted fair order across clients and priority classes; when the queue
+/// is full they are turned away with an estimate of when to retry.
+///
+/// An engine that fails `engine_failure_threshold` times in a row, in
+/// requests or probes, is quarantined when returned: it is dropped and a new
+/// one loaded in its place.
+pub struct TTSEnginePool<E: PoolEngine = TextToSpeech> {
+    config: Arc<EnginePoolConfig>,
+    engines: Arc<RwLock<HashMap<String, Arc<Mutex<E>>>>>,
+    /// IDs of engines not checked out, least recently used first
+    idle: Arc<std::sync::Mutex<VecDeque<String>>>,
+    scheduler: Arc<Scheduler>,
+    leased: Arc<AtomicUsize>,
+    peak_leased: Arc<AtomicUsize>,
+    timings: Arc<std::sync::Mutex<QueueTimings>>,
+    health: Arc<std::sync::Mutex<HashMap<String, EngineHealth>>>,
     voice_cache: Arc<RwLock<HashMap<String, CacheEntry>>>,
     stats: Arc<RwLock<PoolStats>>,
+    /// Runtime that replacements and probes run on, as engines are returned
+    /// from inference threads
+    runtime: tokio::runtime::Handle,
 }
 
 #[derive(Debug, Default)]
 struct PoolStats {
     total_checkouts: u64,
+    rejected_requests: u64,
+    timed_out_requests: u64,
+    classes: BTreeMap<PriorityClass, ClassCounts>,
     cache_hits: u64,
     cache_misses: u64,
     engine_replacements: u64,
+    failed_replacements: u64,
 }
 
 impl TTSEnginePool {
     /// Create a new engine pool
     pub async fn new(config: EnginePoolConfig) -> Result<Self> {
+        Self::build(config).await
+    }
+}
+
+impl<E: PoolEngine> TTSEnginePool<E> {
+    /// Create a pool of engines of type `E`
+    async fn build(config: EnginePoolConfig) -> Result<Self> {
         let pool_size = config.engine_pool_size;
 
         info!("Creating TTS engine pool with size {}", pool_size);
@@ -144,13 +398,25 @@ impl TTSEnginePool {
         if pool_size == 0 || pool_size > 10 {
             return Err(anyhow!("Engine pool size must be between 1 and 10"));
         }
+        if config.engine_failure_threshold == 0 {
+            return Err(anyhow!("Engine failure threshold must be at least 1"));
+        }
+        if config.engine_probe_interval_secs == Some(0) {
+            return Err(anyhow!("Engine probe interval must be at least 1 second"));
+        }
 
         let pool = Self {
             engines: Arc::new(RwLock::new(HashMap::new())),
-            semaphore: Arc::new(Semaphore::new(pool_size)),
+            idle: Arc::new(std::sync::Mutex::new(VecDeque::new())),
+            scheduler: Scheduler::new(pool_size, config.max_queue_depth, config.priority_weights.clone()),
+            leased: Arc::new(AtomicUsize::new(0)),
+            peak_leased: Arc::new(AtomicUsize::new(0)),
+            timings: Arc::new(std::sync::Mutex::new(QueueTimings::default())),
+            health: Arc::new(std::sync::Mutex::new(HashMap::new())),
             voice_cache: Arc::new(RwLock::new(HashMap::new())),
             stats: Arc::new(RwLock::new(PoolStats::default())),
-            config,
+            runtime: tokio::runtime::Handle::current(),
+            config: Arc::new(config),
         };
 
         // Warm up engines if requested
@@ -160,21 +426,24 @@ impl TTSEnginePool {
             info!("Engine pool warmup completed");
         }
 
+        if let Some(interval) = pool.config.engine_probe_interval_secs {
+            info!("Probing engines every {}s", interval);
+            pool.start_probes(Duration::from_secs(interval));
+        }
+
         Ok(pool)
     }
 
     /// Warm up the pool by preloading all engines
     async fn warmup(&self) -> Result<()> {
         let pool_size = self.config.engine_pool_size;
-        let mut engines = self.engines.write().await;
 
         for i in 0..pool_size {
             info!("Loading TTS engine {}/{}", i + 1, pool_size);
 
-            match load_text_to_speech(&self.config.onnx_dir, self.config.use_gpu) {
-                Ok(engine) => {
-                    let engine_id = Uuid::new_v4().to_string();
-                    engines.insert(engine_id.clone(), Arc::new(Mutex::new(engine)));
+            match self.create_engine().await {
+                Ok((engine_id, _)) => {
+                    self.idle.lock().unwrap().push_back(engine_id.clone());
                     debug!("Engine {} loaded successfully", engine_id);
                 }
                 Err(e) => {
@@ -187,64 +456,257 @@ impl TTSEnginePool {
         Ok(())
     }
 
-    /// Check out an engine from the pool
-    pub async fn checkout(&self) -> Result<EngineHandle> {
-        let checkout_timeout = Duration::from_millis(self.config.engine_checkout_timeout_ms);
+    /// Check out an engine from the pool for `ticket`, queueing for up to
+    /// the checkout timeout when all are leased
+    pub async fn checkout(&self, ticket: &Ticket) -> Result<EngineHandle<E>, CheckoutError> {
+        let lease = self.acquire_lease(ticket).await?;
+        let (engine_id, engine) = match self.take_idle_engine().await {
+            Some(idle_engine) => idle_engine,
+            // Every engine is leased, so this permit has room for a new one
+            None => self.create_engine().await.map_err(CheckoutError::Engine)?,
+        };
+        Ok(self.lease_engine(engine_id, engine, lease))
+    }
 
-        // Acquire semaphore permit with timeout
-        let _permit = timeout(
-            checkout_timeout,
-            self.semaphore.acquire()
-        ).await
-            .map_err(|_| anyhow!("Engine checkout timeout after {}ms", self.config.engine_checkout_timeout_ms))?
-            .map_err(|_| anyhow!("Semaphore closed"))?;
+    /// Take a lease from the scheduler, queueing if none is free
+    async fn acquire_lease(&self, ticket: &Ticket) -> Result<Lease, CheckoutError> {
+        let queued_at = Instant::now();
+        let lease = match self.scheduler.admit(ticket) {
+            Admission::Leased(lease) => lease,
+            Admission::Queued(waiting) => self.wait_for_lease(waiting, ticket).await?,
+            Admission::Full { waiting } => {
+                let mut stats = self.stats.write().await;
+                stats.rejected_requests += 1;
+                stats.classes.entry(ticket.priority).or_default().rejected += 1;
+                debug!("Request queue full ({} waiting)", waiting);
+                return Err(CheckoutError::QueueFull {
+                    retry_after: self.estimated_wait(waiting + 1),
+                });
+            }
+        };
+        {
+            let waited = queued_at.elapsed();
+            let mut timings = self.timings.lock().unwrap();
+            timings.waits.push(waited);
+            timings.class_waits.entry(ticket.priority).or_default().push(waited);
+        }
 
         {
             let mut stats = self.stats.write().await;
             stats.total_checkouts += 1;
+            stats.classes.entry(ticket.priority).or_default().checkouts += 1;
         }
+        Ok(lease)
+    }
 
-        {
-            let engines = self.engines.read().await;
-            if engines.is_empty() {
-                drop(engines);
-                self.create_engine().await?;
-            }
+    /// Hand out `engine` under `lease`
+    fn lease_engine(&self, engine_id: String, engine: Arc<Mutex<E>>, lease: Lease) -> EngineHandle<E> {
+        let leased = self.leased.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak_leased.fetch_max(leased, Ordering::SeqCst);
+        debug!("Checked out engine {} ({} leased)", engine_id, leased);
+
+        EngineHandle {
+            engine_id,
+            engine,
+            pool: self.clone(),
+            leased_at: Instant::now(),
+            lease: Some(lease),
         }
+    }
 
-        let engine_id = {
-            let engines = self.engines.read().await;
-            engines.keys().next().unwrap().clone()
-        };
+    /// Take the engine idle longest. The ID leaves the idle queue only
+    /// together with its engine and with no await in between, so a checkout
+    /// dropped midway (e.g. by a client going away) cannot lose the engine.
+    async fn take_idle_engine(&self) -> Option<(String, Arc<Mutex<E>>)> {
+        let engines = self.engines.read().await;
+        let mut idle = self.idle.lock().unwrap();
+        while let Some(engine_id) = idle.pop_front() {
+            match engines.get(&engine_id) {
+                Some(engine) => return Some((engine_id, Arc::clone(engine))),
+                None => warn!("Dropping unknown engine {} from the idle queue", engine_id),
+            }
+        }
+        None
+    }
 
-        debug!("Checked out engine {}", engine_id);
+    /// Wait for a queued request's turn, up to the checkout timeout or the
+    /// ticket's shorter deadline
+    async fn wait_for_lease(&self, waiting: Waiting, ticket: &Ticket) -> Result<Lease, CheckoutError> {
+        let started = Instant::now();
+        let mut checkout_timeout = Duration::from_millis(self.config.engine_checkout_timeout_ms);
+        if let Some(max_wait) = ticket.max_wait {
+            checkout_timeout = checkout_timeout.min(max_wait);
+        }
+        match timeout(checkout_timeout, waiting.lease()).await {
+            Ok(lease) => Ok(lease),
+            Err(_) => {
+                let mut stats = self.stats.write().await;
+                stats.timed_out_requests += 1;
+                stats.classes.entry(ticket.priority).or_default().timed_out += 1;
+                Err(CheckoutError::Timeout {
+                    waited: started.elapsed(),
+                    retry_after: self.estimated_wait(self.scheduler.waiting().values().sum::<usize>() + 1),
+                })
+            }
+        }
+    }
 
-        Ok(EngineHandle {
-            engine_id,
-            pool: Arc::new(self.clone()),
-        })
+    /// Estimated wait for an engine at `position` in the queue: one recent
+    /// average lease for every round of engines ahead of it
+    fn estimated_wait(&self, position: usize) -> Duration {
+        let lease = self.timings.lock().unwrap().leases.average().unwrap_or(DEFAULT_LEASE_ESTIMATE);
+        let rounds = position.div_ceil(self.config.engine_pool_size).max(1);
+        lease * u32::try_from(rounds).unwrap_or(u32::MAX)
     }
 
-    /// Create a new engine (lazy loading)
-    async fn create_engine(&self) -> Result<()> {
+    /// Create a new engine (lazy loading) off the async runtime
+    async fn create_engine(&self) -> Result<(String, Arc<Mutex<E>>)> {
         info!("Creating new TTS engine (lazy load)");
 
-        let engine = load_text_to_speech(&self.config.onnx_dir, self.config.use_gpu)?;
+        let config = Arc::clone(&self.config);
+        let engine = tokio::task::spawn_blocking(move || E::load(&config)).await??;
         let engine_id = Uuid::new_v4().to_string();
+        let engine = Arc::new(Mutex::new(engine));
 
         let mut engines = self.engines.write().await;
-        engines.insert(engine_id.clone(), Arc::new(Mutex::new(engine)));
+        engines.insert(engine_id.clone(), Arc::clone(&engine));
+        self.health.lock().unwrap().insert(engine_id.clone(), EngineHealth::new());
 
-        info!("Created engine {}", engine_id);
-        Ok(())
+        info!("Created engine {} ({}/{})", engine_id, engines.len(), self.config.engine_pool_size);
+        Ok((engine_id, engine))
+    }
+
+    fn record(&self, engine_id: &str, error: Option<String>) {
+        if let Some(error) = &error {
+            warn!("Engine {} failed: {}", engine_id, error);
+        }
+        if let Some(health) = self.health.lock().unwrap().get_mut(engine_id) {
+            health.record(error);
+        }
+    }
+
+    /// Quarantine the engine if it reached the failure threshold
+    fn should_replace(&self, engine_id: &str) -> bool {
+        let mut health = self.health.lock().unwrap();
+        match health.get_mut(engine_id) {
+            Some(health) if health.consecutive_failures >= self.config.engine_failure_threshold => {
+                health.quarantined = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Drop a quarantined engine and load a new one in its place, holding
+    /// the engine's lease until done
+    fn replace_engine(&self, engine_id: String, lease: Lease) {
+        let pool = self.clone();
+        self.runtime.spawn(async move {
+            warn!("Quarantined engine {}, loading a replacement", engine_id);
+            pool.engines.write().await.remove(&engine_id);
+            let replaced = pool.create_engine().await;
+            pool.health.lock().unwrap().remove(&engine_id);
+
+            let mut stats = pool.stats.write().await;
+            match replaced {
+                Ok((new_id, _)) => {
+                    pool.idle.lock().unwrap().push_back(new_id.clone());
+                    stats.engine_replacements += 1;
+                    info!("Replaced engine {} with {}", engine_id, new_id);
+                }
+                Err(e) => {
+                    // The next checkout without an idle engine tries again
+                    stats.failed_replacements += 1;
+                    error!("Failed to replace engine {}: {}", engine_id, e);
+                }
+            }
+            drop(lease);
+        });
+    }
+
+    /// Probe the engines every `interval`
+    fn start_probes(&self, interval: Duration) {
+        let pool = self.clone();
+        self.runtime.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick completes at once
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                pool.probe_engines().await;
+            }
+        });
+    }
+
+    /// Probe the engines with the configured probe voice
+    async fn probe_engines(&self) {
+        match self.get_voice_style(&self.config.probe_voice_style).await {
+            Ok(style) => self.probe_idle_engines(&style).await,
+            Err(e) => warn!("Skipping engine probes, failed to load the probe voice: {}", e),
+        }
+    }
+
+    /// Synthesize a short text on each idle engine in turn. Probes are
+    /// scheduled like batch requests and take the engine idle longest, so
+    /// each probe takes the next engine. Leased engines are skipped, and a
+    /// probe never loads an engine.
+    async fn probe_idle_engines(&self, style: &Style) {
+        let ticket = Ticket {
+            client: PROBE_CLIENT.to_string(),
+            priority: PriorityClass::Batch,
+            cost: PROBE_TEXT.len(),
+            max_wait: None,
+        };
+
+        let engines = self.engines.read().await.len();
+        let mut probed = HashSet::new();
+        for _ in 0..engines {
+            let lease = match self.acquire_lease(&ticket).await {
+                Ok(lease) => lease,
+                Err(e) => {
+                    debug!("Skipping engine probes: {}", e);
+                    return;
+                }
+            };
+            let Some((engine_id, engine)) = self.take_idle_engine().await else {
+                debug!("Skipping engine probes: no engine is idle");
+                return;
+            };
+            if !probed.insert(engine_id.clone()) {
+                // Every idle engine has had its probe this round
+                self.idle.lock().unwrap().push_front(engine_id);
+                return;
+            }
+            let handle = self.lease_engine(engine_id, engine, lease);
+            let style = style.clone();
+            let probe = tokio::task::spawn_blocking(move || {
+                let result = handle.engine().blocking_lock().probe(&style);
+                // Any probe failure counts against the engine
+                let error = result.err().map(|e| e.to_string());
+                handle.pool.record_probe(&handle.engine_id, error);
+            });
+            if let Err(e) = probe.await {
+                error!("Engine probe panicked: {}", e);
+            }
+        }
+    }
+
+    fn record_probe(&self, engine_id: &str, error: Option<String>) {
+        let ok = error.is_none();
+        self.record(engine_id, error);
+        if let Some(health) = self.health.lock().unwrap().get_mut(engine_id) {
+            health.last_probe = Some(ProbeResult {
+                at: chrono::Utc::now().to_rfc3339(),
+                ok,
+            });
+        }
+        debug!("Probed engine {}: {}", engine_id, if ok { "ok" } else { "failed" });
     }
 
     /// Get voice style from cache or load it
     pub async fn get_voice_style(&self, voice_path: &str) -> Result<Style> {
-        let absolute_path = Path::new(voice_path)
-            .canonicalize()
-            .unwrap_or_else(|_| PathBuf::from(voice_path));
-        let cache_key = absolute_path.to_string_lossy().to_string();
+        let cache_key = voice_cache_key(voice_path);
 
         {
             let cache = self.voice_cache.read().await;
@@ -275,6 +737,15 @@ impl TTSEnginePool {
         Ok(voice_style)
     }
 
+    /// Drop a voice style from the cache after its file was replaced, or
+    /// before it is deleted
+    pub async fn invalidate_voice_style(&self, voice_path: &str) {
+        let cache_key = voice_cache_key(voice_path);
+        if self.voice_cache.write().await.remove(&cache_key).is_some() {
+            debug!("Invalidated cached voice style: {}", voice_path);
+        }
+    }
+
     /// Add voice style to cache with LRU eviction
     async fn add_to_cache(&self, cache_key: String, voice_style: Style) -> Result<()> {
         let path = PathBuf::from(&cache_key);
@@ -303,6 +774,52 @@ impl TTSEnginePool {
         let stats = self.stats.read().await;
         let cache = self.voice_cache.read().await;
 
+        let waiting = self.scheduler.waiting();
+        let millis = |durations: Option<&RecentDurations>| {
+            durations.and_then(RecentDurations::average).map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+        };
+        let timings = self.timings.lock().unwrap();
+        let priority_classes = PriorityClass::ALL
+            .iter()
+            .map(|&class| {
+                let counts = stats.classes.get(&class).copied().unwrap_or_default();
+                PriorityClassStats {
+                    class: class.as_str(),
+                    weight: self.scheduler.weights().weight(class),
+                    queue_depth: waiting.get(&class).copied().unwrap_or(0),
+                    average_queue_wait_ms: millis(timings.class_waits.get(&class)),
+                    checkouts: counts.checkouts,
+                    rejected_requests: counts.rejected,
+                    timed_out_requests: counts.timed_out,
+                }
+            })
+            .collect();
+
+        let idle: Vec<String> = self.idle.lock().unwrap().iter().cloned().collect();
+        let mut engine_states: Vec<EngineStatus> = self
+            .health
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, health)| EngineStatus {
+                id: id.clone(),
+                state: if health.quarantined {
+                    "quarantined"
+                } else if idle.contains(id) {
+                    "idle"
+                } else {
+                    "leased"
+                },
+                loaded_at: health.loaded_at.clone(),
+                syntheses: health.syntheses,
+                consecutive_failures: health.consecutive_failures,
+                total_failures: health.total_failures,
+                last_error: health.last_error.clone(),
+                last_probe: health.last_probe.clone(),
+            })
+            .collect();
+        engine_states.sort_by(|a, b| a.loaded_at.cmp(&b.loaded_at).then_with(|| a.id.cmp(&b.id)));
+
         let cache_hit_rate = if stats.cache_hits + stats.cache_misses > 0 {
             (stats.cache_hits as f64 / (stats.cache_hits + stats.cache_misses) as f64) * 100.0
         } else {
@@ -311,13 +828,24 @@ impl TTSEnginePool {
 
         PoolStatsResponse {
             total_engines: engines.len(),
-            available_permits: self.semaphore.available_permits(),
+            idle_engines: self.idle.lock().unwrap().len(),
+            peak_leased_engines: self.peak_leased.load(Ordering::SeqCst),
+            available_permits: self.scheduler.free(),
+            queue_depth: waiting.values().sum(),
+            max_queue_depth: self.config.max_queue_depth,
+            average_queue_wait_ms: millis(Some(&timings.waits)),
+            average_lease_ms: millis(Some(&timings.leases)),
+            rejected_requests: stats.rejected_requests,
+            timed_out_requests: stats.timed_out_requests,
+            priority_classes,
             cached_voice_styles: cache.len(),
             total_checkouts: stats.total_checkouts,
             cache_hits: stats.cache_hits,
             cache_misses: stats.cache_misses,
             cache_hit_rate,
             engine_replacements: stats.engine_replacements,
+            failed_replacements: stats.failed_replacements,
+            engines: engine_states,
         }
     }
 
@@ -328,6 +856,7 @@ impl TTSEnginePool {
         let mut engines = self.engines.write().await;
         let engine_count = engines.len();
         engines.clear();
+        self.idle.lock().unwrap().clear();
 
         // Clear cache
         let mut cache = self.voice_cache.write().await;
@@ -338,14 +867,20 @@ impl TTSEnginePool {
     }
 }
 
-impl Clone for TTSEnginePool {
+impl<E: PoolEngine> Clone for TTSEnginePool<E> {
     fn clone(&self) -> Self {
         Self {
-            config: self.config.clone(),
+            config: Arc::clone(&self.config),
             engines: Arc::clone(&self.engines),
-            semaphore: Arc::clone(&self.semaphore),
+            idle: Arc::clone(&self.idle),
+            scheduler: Arc::clone(&self.scheduler),
+            leased: Arc::clone(&self.leased),
+            peak_leased: Arc::clone(&self.peak_leased),
+            timings: Arc::clone(&self.timings),
+            health: Arc::clone(&self.health),
             voice_cache: Arc::clone(&self.voice_cache),
             stats: Arc::clone(&self.stats),
+            runtime: self.runtime.clone(),
         }
     }
 }
@@ -356,12 +891,216 @@ impl Clone for TTSEnginePool {
 
 #[derive(Debug, Serialize)]
 pub struct PoolStatsResponse {
+    /// Engines loaded so far; the pool grows on demand up to its size
     pub total_engines: usize,
+    /// Loaded engines that are not checked out
+    pub idle_engines: usize,
+    /// Most engines checked out at the same time
+    pub peak_leased_engines: usize,
     pub available_permits: usize,
+    /// Requests waiting for an engine
+    pub queue_depth: usize,
+    pub max_queue_depth: usize,
+    /// Average time recent requests waited for an engine
+    pub average_queue_wait_ms: f64,
+    /// Average time recent requests held an engine
+    pub average_lease_ms: f64,
+    /// Requests turned away because the queue was full
+    pub rejected_requests: u64,
+    /// Requests that waited out the checkout timeout
+    pub timed_out_requests: u64,
+    pub priority_classes: Vec<PriorityClassStats>,
     pub cached_voice_styles: usize,
     pub total_checkouts: u64,
     pub cache_hits: u64,
     pub cache_misses: u64,
     pub cache_hit_rate: f64,
+    /// Engines replaced after failing `engine_failure_threshold` times in a row
     pub engine_replacements: u64,
-}
\ No newline at end of file
+    /// Replacements whose new engine failed to load
+    pub failed_replacements: u64,
+    pub engines: Vec<EngineStatus>,
+}
+
+/// State and failures of one engine
+#[derive(Debug, Serialize)]
+pub struct EngineStatus {
+    pub id: String,
+    /// "idle", "leased" or "quarantined" (being replaced)
+    pub state: &'static str,
+    pub loaded_at: String,
+    /// Syntheses and probes run, and how many failed
+    pub syntheses: u64,
+    pub consecutive_failures: u32,
+    pub total_failures: u64,
+    pub last_error: Option<String>,
+    pub last_probe: Option<ProbeResult>,
+}
+
+/// Queue metrics of one priority class
+#[derive(Debug, Serialize)]
+pub struct PriorityClassStats {
+    pub class: &'static str,
+    pub weight: u32,
+    pub queue_depth: usize,
+    pub average_queue_wait_ms: f64,
+    pub checkouts: u64,
+    pub rejected_requests: u64,
+    pub timed_out_requests: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    /// Probes run by fake engines, across all tests
+    static PROBES: AtomicUsize = AtomicUsize::new(0);
+
+    /// Engine that loads instantly and only counts its probes
+    struct FakeEngine;
+
+    impl PoolEngine for FakeEngine {
+        fn load(_config: &EnginePoolConfig) -> Result<Self> {
+            Ok(FakeEngine)
+        }
+
+        fn probe(&mut self, _style: &Style) -> Result<()> {
+            PROBES.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    async fn fake_pool(size: usize) -> TTSEnginePool<FakeEngine> {
+        TTSEnginePool::build(EnginePoolConfig {
+            engine_pool_size: size,
+            ..EnginePoolConfig::default()
+        })
+        .await
+        .unwrap()
+    }
+
+    fn ticket(client: &str) -> Ticket {
+        Ticket {
+            client: client.to_string(),
+            priority: PriorityClass::Interactive,
+            cost: 10,
+            max_wait: None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn engines_are_leased_exclusively_and_in_parallel() {
+        const SIZE: usize = 3;
+        let pool = fake_pool(SIZE).await;
+
+        let checkouts = (0..SIZE).map(|i| {
+            let pool = pool.clone();
+            async move { pool.checkout(&ticket(&format!("client-{}", i))).await.unwrap() }
+        });
+        let handles = futures_util::future::join_all(checkouts).await;
+        let ids: HashSet<&str> = handles.iter().map(|handle| handle.engine_id.as_str()).collect();
+        assert_eq!(ids.len(), SIZE, "every checkout gets its own engine");
+        assert_eq!(pool.engines.read().await.len(), SIZE);
+
+        // Each handle locks its engine on an inference thread and holds it
+        // until all of them do, which needs SIZE distinct engines
+        let barrier = Arc::new(std::sync::Barrier::new(SIZE));
+        let (handles, tasks): (Vec<_>, Vec<_>) = handles
+            .into_iter()
+            .map(|handle| {
+                let engine = handle.engine();
+                let barrier = Arc::clone(&barrier);
+                let task = tokio::task::spawn_blocking(move || {
+                    let _engine = engine.blocking_lock();
+                    barrier.wait();
+                });
+                (handle, task)
+            })
+            .unzip();
+        for task in tasks {
+            timeout(WAIT, task).await.expect("engines did not run in parallel").unwrap();
+        }
+
+        // The pool is full, so the next checkout queues instead of loading
+        let mut handles: VecDeque<EngineHandle<FakeEngine>> = handles.into();
+        let waiting = {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.checkout(&ticket("late")).await.unwrap() })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        assert_eq!(pool.scheduler.waiting().values().sum::<usize>(), 1);
+
+        // and gets the engine that was given back
+        let returned = handles.pop_front().unwrap();
+        let returned_id = returned.engine_id.clone();
+        drop(returned);
+        let late = timeout(WAIT, waiting).await.expect("queued checkout was not served").unwrap();
+        assert_eq!(late.engine_id, returned_id);
+        assert_eq!(pool.engines.read().await.len(), SIZE, "the pool grew past its size");
+    }
+
+    #[tokio::test]
+    async fn engines_grow_lazily_and_the_longest_idle_is_reused() {
+        let pool = fake_pool(3).await;
+        assert_eq!(pool.engines.read().await.len(), 0);
+
+        let first = pool.checkout(&ticket("a")).await.unwrap();
+        let second = pool.checkout(&ticket("a")).await.unwrap();
+        assert_eq!(pool.engines.read().await.len(), 2);
+        let (first_id, second_id) = (first.engine_id.clone(), second.engine_id.clone());
+
+        drop(second);
+        drop(first);
+        let next = pool.checkout(&ticket("a")).await.unwrap();
+        assert_eq!(next.engine_id, second_id, "least recently used engine first");
+        let after = pool.checkout(&ticket("a")).await.unwrap();
+        assert_eq!(after.engine_id, first_id);
+        assert_eq!(pool.engines.read().await.len(), 2, "idle engines are reused before loading more");
+    }
+
+    #[tokio::test]
+    async fn dropped_checkout_keeps_the_engine_idle() {
+        let pool = fake_pool(1).await;
+        drop(pool.checkout(&ticket("a")).await.unwrap());
+
+        // Drop a checkout while it waits for the engine map
+        let engines = pool.engines.write().await;
+        let impatient = ticket("b");
+        assert!(timeout(Duration::from_millis(20), pool.checkout(&impatient)).await.is_err());
+        drop(engines);
+
+        let handle = pool.checkout(&ticket("c")).await.unwrap();
+        assert_eq!(pool.engines.read().await.len(), 1, "the engine was lost and a new one loaded");
+        drop(handle);
+        assert_eq!(pool.idle.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn probes_only_idle_engines_and_never_load_one() {
+        let pool = fake_pool(3).await;
+        let style = Style {
+            ttl: ndarray::Array3::zeros((1, 1, 1)),
+            dp: ndarray::Array3::zeros((1, 1, 1)),
+        };
+
+        // The only engine is leased, while the scheduler has leases to spare
+        let leased = pool.checkout(&ticket("a")).await.unwrap();
+        let probes = PROBES.load(Ordering::SeqCst);
+        pool.probe_idle_engines(&style).await;
+        assert_eq!(PROBES.load(Ordering::SeqCst), probes);
+        assert_eq!(pool.engines.read().await.len(), 1, "a probe loaded an engine");
+
+        // Two idle engines of three: each is probed once, none is loaded
+        let other = pool.checkout(&ticket("a")).await.unwrap();
+        drop(leased);
+        drop(other);
+        pool.probe_idle_engines(&style).await;
+        assert_eq!(PROBES.load(Ordering::SeqCst), probes + 2);
+        assert_eq!(pool.engines.read().await.len(), 2, "a probe loaded an engine");
+        assert_eq!(pool.idle.lock().unwrap().len(), 2);
+        assert_eq!(pool.scheduler.free(), 3);
+    }
+}
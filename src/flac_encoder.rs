@@ -0,0 +1,528 @@
+// FLAC Encoder Module - Pure Rust lossless encoder for mono 16-bit output
+//
+// Each block is coded with the best of the fixed polynomial predictors
+// (orders 0-4) and partitioned Rice coding of the residual, falling back to
+// verbatim or constant subframes. This stays well within the FLAC subset, so
+// any decoder can play the output, at compression close to `flac -3`.
+use anyhow::{anyhow, Result};
+
+/// Samples per frame
+const BLOCK_SIZE: usize = 4096;
+
+/// Bits per encoded sample
+const BITS_PER_SAMPLE: u32 = 16;
+
+/// Highest fixed predictor order defined by the format
+const MAX_FIXED_ORDER: usize = 4;
+
+/// Highest Rice partition order tried
+const MAX_PARTITION_ORDER: u32 = 8;
+
+/// Largest Rice parameter codable with the 4-bit parameter field
+const MAX_RICE_PARAMETER: u32 = 14;
+
+/// Streaming FLAC encoder for mono `f32` samples in `[-1.0, 1.0]`
+pub struct FlacEncoder {
+    sample_rate: u32,
+    total_samples: Option<u64>,
+    frame_number: u64,
+    pending: Vec<i32>,
+    header_written: bool,
+}
+
+impl FlacEncoder {
+    /// Create an encoder. `total_samples` is recorded in the stream header
+    /// when known up front; streamed output leaves it unset.
+    pub fn new(sample_rate: u32, total_samples: Option<u64>) -> Result<Self> {
+        if sample_rate == 0 || sample_rate >= 1 << 20 {
+            return Err(anyhow!("FLAC does not support a sample rate of {} Hz", sample_rate));
+        }
+        Ok(Self {
+            sample_rate,
+            // An empty clip is written as one silent sample, see `finish`
+            total_samples: total_samples.map(|samples| samples.max(1)),
+            frame_number: 0,
+            pending: Vec::new(),
+            header_written: false,
+        })
+    }
+
+    /// Encode samples, returning the stream header and any complete frames
+    pub fn encode(&mut self, samples: &[f32]) -> Vec<u8> {
+        let mut output = self.take_header();
+        self.pending.extend(samples.iter().map(|&s| (s.clamp(-1.0, 1.0) * 32767.0) as i32));
+
+        let blocks = self.pending.len() / BLOCK_SIZE;
+        for block in 0..blocks {
+            let start = block * BLOCK_SIZE;
+            let frame = self.encode_frame(&self.pending[start..start + BLOCK_SIZE]);
+            output.extend_from_slice(&frame);
+            self.frame_number += 1;
+        }
+        self.pending.drain(..blocks * BLOCK_SIZE);
+
+        output
+    }
+
+    /// Encode the final, possibly shorter, block. A stream without any
+    /// samples gets a single silent one, since decoders cannot open a FLAC
+    /// stream that has no frames.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let mut output = self.take_header();
+        if self.frame_number == 0 && self.pending.is_empty() {
+            self.pending.push(0);
+        }
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            output.extend_from_slice(&self.encode_frame(&pending));
+            self.frame_number += 1;
+        }
+        output
+    }
+
+    fn take_header(&mut self) -> Vec<u8> {
+        if self.header_written {
+            return Vec::new();
+        }
+        self.header_written = true;
+
+        let mut writer = BitWriter::default();
+        writer.write_bytes(b"fLaC");
+
+        // Metadata block header: last block, type STREAMINFO, 34 bytes
+        writer.write(1, 1);
+        writer.write(0, 7);
+        writer.write(34, 24);
+
+        // STREAMINFO; zero frame sizes and MD5 mean "unknown"
+        writer.write(BLOCK_SIZE as u64, 16);
+        writer.write(BLOCK_SIZE as u64, 16);
+        writer.write(0, 24);
+        writer.write(0, 24);
+        writer.write(self.sample_rate as u64, 20);
+        writer.write(0, 3); // channels - 1
+        writer.write((BITS_PER_SAMPLE - 1) as u64, 5);
+        writer.write(self.total_samples.unwrap_or(0), 36);
+        writer.write_bytes(&[0; 16]);
+
+        writer.into_bytes()
+    }
+
+    fn encode_frame(&self, samples: &[i32]) -> Vec<u8> {
+        let mut writer = BitWriter::default();
+
+        // Frame header
+        writer.write(0b11_1111_1111_1110, 14); // sync code
+        writer.write(0, 1); // reserved
+        writer.write(0, 1); // fixed block size
+        let block_size_code = if samples.len() == BLOCK_SIZE { 0b1100 } else { 0b0111 };
+        writer.write(block_size_code, 4);
+        writer.write(sample_rate_code(self.sample_rate), 4);
+        writer.write(0b0000, 4); // mono
+        writer.write(0b100, 3); // 16 bits per sample
+        writer.write(0, 1); // reserved
+        write_utf8_number(&mut writer, self.frame_number);
+        if block_size_code == 0b0111 {
+            writer.write(samples.len() as u64 - 1, 16);
+        }
+        let crc = crc8(writer.bytes());
+        writer.write(crc as u64, 8);
+
+        write_subframe(&mut writer, samples);
+
+        writer.align();
+        let crc = crc16(writer.bytes());
+        writer.write(crc as u64, 16);
+        writer.into_bytes()
+    }
+}
+
+/// Sample rate code for the frame header; 0 refers back to STREAMINFO
+fn sample_rate_code(sample_rate: u32) -> u64 {
+    match sample_rate {
+        88200 => 0b0001,
+        176400 => 0b0010,
+        192000 => 0b0011,
+        8000 => 0b0100,
+        16000 => 0b0101,
+        22050 => 0b0110,
+        24000 => 0b0111,
+        32000 => 0b1000,
+        44100 => 0b1001,
+        48000 => 0b1010,
+        96000 => 0b1011,
+        _ => 0b0000,
+    }
+}
+
+/// Frame number in the extended UTF-8 style coding used by FLAC
+fn write_utf8_number(writer: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        writer.write(value, 8);
+        return;
+    }
+    let mut continuation = 1;
+    while value >= 1u64 << (5 * continuation + 6) {
+        continuation += 1;
+    }
+    let prefix = (0xFF00u64 >> (continuation + 1)) & 0xFF;
+    writer.write(prefix | (value >> (6 * continuation)), 8);
+    for i in (0..continuation).rev() {
+        writer.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+    }
+}
+
+fn write_subframe(writer: &mut BitWriter, samples: &[i32]) {
+    if samples.iter().all(|&s| s == samples[0]) {
+        writer.write(0, 1);
+        writer.write(0b000000, 6); // constant
+        writer.write(0, 1);
+        writer.write_signed(samples[0], BITS_PER_SAMPLE);
+        return;
+    }
+
+    let verbatim_bits = samples.len() * BITS_PER_SAMPLE as usize;
+    let mut best: Option<(usize, usize, Vec<i32>, RicePlan)> = None;
+    for order in 0..=MAX_FIXED_ORDER.min(samples.len() - 1) {
+        let residual = fixed_residual(samples, order);
+        let plan = plan_rice(&residual, samples.len(), order);
+        let bits = order * BITS_PER_SAMPLE as usize + plan.bits;
+        if best.as_ref().is_none_or(|(best_bits, ..)| bits < *best_bits) {
+            best = Some((bits, order, residual, plan));
+        }
+    }
+
+    match best {
+        Some((bits, order, residual, plan)) if bits < verbatim_bits => {
+            writer.write(0, 1);
+            writer.write(0b001000 | order as u64, 6); // fixed predictor
+            writer.write(0, 1);
+            for &sample in &samples[..order] {
+                writer.write_signed(sample, BITS_PER_SAMPLE);
+            }
+            write_residual(writer, &residual, &plan, samples.len(), order);
+        }
+        _ => {
+            writer.write(0, 1);
+            writer.write(0b000001, 6); // verbatim
+            writer.write(0, 1);
+            for &sample in samples {
+                writer.write_signed(sample, BITS_PER_SAMPLE);
+            }
+        }
+    }
+}
+
+/// Residual of the fixed polynomial predictor of the given order
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<i32> {
+    samples[order..]
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| {
+            let i = i + order;
+            match order {
+                0 => s,
+                1 => s - samples[i - 1],
+                2 => s - 2 * samples[i - 1] + samples[i - 2],
+                3 => s - 3 * samples[i - 1] + 3 * samples[i - 2] - samples[i - 3],
+                _ => s - 4 * samples[i - 1] + 6 * samples[i - 2] - 4 * samples[i - 3] + samples[i - 4],
+            }
+        })
+        .collect()
+}
+
+/// Partition order and per-partition Rice parameters for a residual
+struct RicePlan {
+    partition_order: u32,
+    parameters: Vec<u32>,
+    bits: usize,
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn plan_rice(residual: &[i32], block_size: usize, order: usize) -> RicePlan {
+    let folded: Vec<u32> = residual.iter().map(|&r| zigzag(r)).collect();
+
+    let mut best: Option<RicePlan> = None;
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partitions = 1usize << partition_order;
+        if !block_size.is_multiple_of(partitions) || block_size / partitions <= order {
+            break;
+        }
+
+        let mut parameters = Vec::with_capacity(partitions);
+        let mut bits = 2 + 4; // coding method + partition order
+        let mut start = 0;
+        for partition in 0..partitions {
+            let len = block_size / partitions - if partition == 0 { order } else { 0 };
+            let (parameter, partition_bits) = best_rice_parameter(&folded[start..start + len]);
+            parameters.push(parameter);
+            bits += 4 + partition_bits;
+            start += len;
+        }
+
+        if best.as_ref().is_none_or(|plan| bits < plan.bits) {
+            best = Some(RicePlan {
+                partition_order,
+                parameters,
+                bits,
+            });
+        }
+    }
+
+    best.expect("partition order 0 is always valid")
+}
+
+/// Rice parameter minimizing the coded size of `values`, with that size
+fn best_rice_parameter(values: &[u32]) -> (u32, usize) {
+    let sum: u64 = values.iter().map(|&v| v as u64).sum();
+    let mean = if values.is_empty() { 0 } else { sum / values.len() as u64 };
+    let estimate = if mean == 0 { 0 } else { (64 - mean.leading_zeros()).min(MAX_RICE_PARAMETER) };
+
+    let cost = |k: u32| -> usize {
+        values.len() * (k as usize + 1) + values.iter().map(|&v| (v >> k) as usize).sum::<usize>()
+    };
+
+    (estimate.saturating_sub(1)..=(estimate + 1).min(MAX_RICE_PARAMETER))
+        .map(|k| (k, cost(k)))
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap_or((0, 0))
+}
+
+fn write_residual(writer: &mut BitWriter, residual: &[i32], plan: &RicePlan, block_size: usize, order: usize) {
+    writer.write(0b00, 2); // Rice coding with 4-bit parameters
+    writer.write(plan.partition_order as u64, 4);
+
+    let partitions = 1usize << plan.partition_order;
+    let mut start = 0;
+    for (partition, &parameter) in plan.parameters.iter().enumerate() {
+        let len = block_size / partitions - if partition == 0 { order } else { 0 };
+        writer.write(parameter as u64, 4);
+        for &value in &residual[start..start + len] {
+            let folded = zigzag(value);
+            writer.write_unary((folded >> parameter) as usize);
+            writer.write((folded & ((1 << parameter) - 1)) as u64, parameter);
+        }
+        start += len;
+    }
+}
+
+/// CRC-8 with polynomial x^8 + x^2 + x + 1, used for frame headers
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// CRC-16 with polynomial x^16 + x^15 + x^2 + 1, used for whole frames
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// MSB-first bit writer with a 64-bit accumulator
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    /// Write the low `bits` bits of `value` (at most 32 at a time is fastest)
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits > 32 {
+            self.write(value >> 32, bits - 32);
+            self.write(value & 0xFFFF_FFFF, 32);
+            return;
+        }
+        if bits == 0 {
+            return;
+        }
+        self.accumulator = (self.accumulator << bits) | (value & ((1u64 << bits) - 1));
+        self.pending_bits += bits;
+        while self.pending_bits >= 8 {
+            self.pending_bits -= 8;
+            self.bytes.push((self.accumulator >> self.pending_bits) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i32, bits: u32) {
+        self.write(value as u32 as u64, bits);
+    }
+
+    fn write_unary(&mut self, zeros: usize) {
+        let mut remaining = zeros;
+        while remaining >= 32 {
+            self.write(0, 32);
+            remaining -= 32;
+        }
+        self.write(1, remaining as u32 + 1);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write(byte as u64, 8);
+        }
+    }
+
+    /// Pad with zero bits to the next byte boundary
+    fn align(&mut self) {
+        if self.pending_bits > 0 {
+            self.write(0, 8 - self.pending_bits);
+        }
+    }
+
+    /// Completed bytes so far; call `align` first to include every bit
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+/// Encode a complete clip as a FLAC file
+pub fn write_flac_to_buffer(buffer: &mut Vec<u8>, audio_data: &[f32], sample_rate: i32) -> Result<()> {
+    let mut encoder = FlacEncoder::new(sample_rate as u32, Some(audio_data.len() as u64))?;
+    buffer.extend_from_slice(&encoder.encode(audio_data));
+    buffer.extend_from_slice(&encoder.finish());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::DecoderOptions;
+    use symphonia::core::errors::Error as SymphoniaError;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    /// A tone, a stretch of silence, full-scale noise and clipped samples, so
+    /// that fixed, constant and verbatim subframes all show up
+    fn test_signal(sample_rate: u32, len: usize) -> Vec<f32> {
+        let mut seed = 0x2545_f491u32;
+        (0..len)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                match (i / 3000) % 4 {
+                    0 => 0.5 * (2.0 * std::f32::consts::PI * 220.0 * i as f32 / sample_rate as f32).sin(),
+                    1 => 0.0,
+                    2 => (seed >> 8) as f32 / (1 << 23) as f32 - 1.0,
+                    _ => 1.5 * (2.0 * std::f32::consts::PI * 90.0 * i as f32 / sample_rate as f32).sin(),
+                }
+            })
+            .collect()
+    }
+
+    /// The 16-bit samples the encoder stores for `samples`
+    fn quantize(samples: &[f32]) -> Vec<i16> {
+        samples.iter().map(|&s| (s.clamp(-1.0, 1.0) * 32767.0) as i16).collect()
+    }
+
+    /// Encode in odd-sized pieces so blocks straddle `encode` calls
+    fn encode_streamed(sample_rate: u32, samples: &[f32]) -> Vec<u8> {
+        let mut encoder = FlacEncoder::new(sample_rate, None).unwrap();
+        let mut bytes = Vec::new();
+        for piece in samples.chunks(1001) {
+            bytes.extend(encoder.encode(piece));
+        }
+        bytes.extend(encoder.finish());
+        bytes
+    }
+
+    /// Decode with CRC and checksum verification, returning the sample rate,
+    /// the sample count from the stream header and the samples
+    fn decode(bytes: Vec<u8>) -> (u32, Option<u64>, Vec<i16>) {
+        let source = MediaSourceStream::new(Box::new(std::io::Cursor::new(bytes)), Default::default());
+        let mut hint = Hint::new();
+        hint.with_extension("flac");
+        let probed = symphonia::default::get_probe()
+            .format(&hint, source, &FormatOptions::default(), &MetadataOptions::default())
+            .unwrap();
+        let mut format = probed.format;
+        let params = format.default_track().unwrap().codec_params.clone();
+        assert_eq!(params.channels.unwrap().count(), 1);
+        assert_eq!(params.bits_per_sample, Some(16));
+        let options = DecoderOptions { verify: true };
+        let mut decoder = symphonia::default::get_codecs().make(&params, &options).unwrap();
+
+        let mut samples = Vec::new();
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => panic!("demuxing failed: {}", e),
+            };
+            let decoded = decoder.decode(&packet).unwrap();
+            let mut buffer = SampleBuffer::<i16>::new(decoded.capacity() as u64, *decoded.spec());
+            buffer.copy_interleaved_ref(decoded);
+            samples.extend_from_slice(buffer.samples());
+        }
+        assert_ne!(decoder.finalize().verify_ok, Some(false));
+        (params.sample_rate.unwrap(), params.n_frames, samples)
+    }
+
+    #[test]
+    fn round_trip_is_bit_exact() {
+        // 11025 Hz has no frame header code and falls back to STREAMINFO
+        for sample_rate in [24000, 44100, 11025] {
+            for len in [1, 2, 3, 5, 17, 4095, 4096, 4097, 2 * BLOCK_SIZE + 1, 12_345] {
+                let input = test_signal(sample_rate, len);
+
+                let mut buffer = Vec::new();
+                write_flac_to_buffer(&mut buffer, &input, sample_rate as i32).unwrap();
+                let (decoded_rate, total, decoded) = decode(buffer);
+                assert_eq!(decoded_rate, sample_rate);
+                assert_eq!(total, Some(len as u64), "{} samples at {} Hz", len, sample_rate);
+                assert!(decoded == quantize(&input), "{} samples at {} Hz", len, sample_rate);
+
+                let (_, total, decoded) = decode(encode_streamed(sample_rate, &input));
+                assert_eq!(total, None, "streamed length is unknown");
+                assert!(decoded == quantize(&input), "{} streamed samples at {} Hz", len, sample_rate);
+            }
+        }
+    }
+
+    #[test]
+    fn compresses_tonal_signals() {
+        let input = test_signal(24000, 3000);
+        let mut buffer = Vec::new();
+        write_flac_to_buffer(&mut buffer, &input, 24000).unwrap();
+        assert!(buffer.len() < input.len(), "{} bytes for {} samples", buffer.len(), input.len());
+    }
+
+    #[test]
+    fn empty_input_decodes_to_one_silent_sample() {
+        let mut buffer = Vec::new();
+        write_flac_to_buffer(&mut buffer, &[], 24000).unwrap();
+        assert_eq!(decode(buffer), (24000, Some(1), vec![0]));
+
+        assert_eq!(decode(encode_streamed(24000, &[])), (24000, None, vec![0]));
+    }
+
+    #[test]
+    fn rejects_unsupported_sample_rates() {
+        assert!(FlacEncoder::new(24000, None).is_ok());
+        assert!(FlacEncoder::new(0, None).is_err());
+        assert!(FlacEncoder::new(1 << 20, None).is_err());
+    }
+}
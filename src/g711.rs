@@ -0,0 +1,45 @@
+// G.711 Module - mu-law and A-law companding for telephony output
+//
+// Both laws map a 16-bit linear sample to an 8-bit code using a piecewise
+// logarithmic curve of eight segments, following the ITU-T reference tables.
+
+/// Sample rate G.711 audio is defined at
+pub const G711_SAMPLE_RATE: u32 = 8000;
+
+/// mu-law bias added to 14-bit magnitudes before the segment search
+const ULAW_BIAS: i32 = 0x21;
+
+/// Largest 14-bit magnitude that is not clipped
+const ULAW_CLIP: i32 = 8159;
+
+/// Encode a 16-bit linear sample as mu-law
+pub fn linear_to_ulaw(sample: i16) -> u8 {
+    // mu-law works on 14-bit magnitudes
+    let sample = (sample as i32) >> 2;
+    let (mask, magnitude) = if sample < 0 { (0x7F, -sample) } else { (0xFF, sample) };
+    let magnitude = magnitude.min(ULAW_CLIP) + ULAW_BIAS;
+
+    let segment = (32 - (magnitude as u32).leading_zeros()).saturating_sub(6) as i32;
+    if segment >= 8 {
+        return (0x7F ^ mask) as u8;
+    }
+    let mantissa = (magnitude >> (segment + 1)) & 0x0F;
+
+    (((segment << 4) | mantissa) ^ mask) as u8
+}
+
+/// Encode a 16-bit linear sample as A-law
+pub fn linear_to_alaw(sample: i16) -> u8 {
+    // A-law works on 13-bit magnitudes
+    let sample = (sample as i32) >> 3;
+    let (mask, magnitude) = if sample >= 0 { (0xD5, sample) } else { (0x55, -sample - 1) };
+
+    let segment = (32 - (magnitude as u32).leading_zeros()).saturating_sub(5) as i32;
+    let mantissa = if segment < 2 {
+        (magnitude >> 1) & 0x0F
+    } else {
+        (magnitude >> segment) & 0x0F
+    };
+
+    (((segment << 4) | mantissa) ^ mask) as u8
+}
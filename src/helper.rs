@@ -1,17 +1,31 @@
 
 // TTS Helper Module - All utility functions and structures
-use ndarray::{Array, Array3};
+use ndarray::{Array, Array3, Axis};
 use serde::{Deserialize, Serialize};
-use serde_json;
 // use tracing::Event;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter, Write};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Arc;
 use anyhow::{Result, Context};
 use unicode_normalization::UnicodeNormalization;
 use hound::{WavWriter, WavSpec, SampleFormat};
 use rand_distr::{Distribution, Normal};
 use regex::Regex;
+use safetensors::tensor::{Dtype, SafeTensors, TensorView};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::flac_encoder::{write_flac_to_buffer, FlacEncoder};
+use crate::g711::{linear_to_alaw, linear_to_ulaw, G711_SAMPLE_RATE};
+use crate::markup::{to_speakable_text, InputFormat};
+use crate::normalizer::DEFAULT_LANGUAGE;
+use crate::segmenter::Segmenter;
+use crate::mp3_encoder::Mp3Encoder;
+use crate::opus_encoder::{write_opus_to_buffer, OggOpusEncoder};
+use crate::resampler::{resample, Resampler};
+use crate::ssml::{is_ssml, parse_ssml};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -54,6 +68,122 @@ pub struct StyleComponent {
     pub dtype: String,
 }
 
+/// Encoding of a voice style file, chosen by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceStyleFormat {
+    /// `VoiceStyleData` as JSON
+    Json,
+    /// float32 tensors `style_ttl` and `style_dp` in a safetensors file,
+    /// which loads far faster than JSON
+    Safetensors,
+}
+
+impl VoiceStyleFormat {
+    /// Format names, which are also their file extensions
+    pub const SUPPORTED: &'static [&'static str] = &["json", "safetensors"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "safetensors" => Some(Self::Safetensors),
+            _ => None,
+        }
+    }
+
+    /// Format of a file by its extension; other extensions are JSON
+    pub fn of<P: AsRef<Path>>(path: P) -> Self {
+        path.as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(Self::parse)
+            .unwrap_or(Self::Json)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Safetensors => "safetensors",
+        }
+    }
+}
+
+/// File name of a voice style file without its extension, or `None` for
+/// files of other types
+pub fn voice_style_stem(file_name: &str) -> Option<&str> {
+    let (stem, extension) = file_name.rsplit_once('.')?;
+    VoiceStyleFormat::parse(extension).map(|_| stem)
+}
+
+/// Shapes of the model's `style_ttl` and `style_dp` inputs, with -1 for
+/// dynamic dimensions
+#[derive(Debug, Clone)]
+pub struct StyleShapes {
+    pub ttl: Vec<i64>,
+    pub dp: Vec<i64>,
+}
+
+impl VoiceStyleData {
+    /// Check that both components hold one float32 voice whose data matches
+    /// its dims, and that the dims fit the model's inputs
+    pub fn validate(&self, shapes: &StyleShapes) -> Result<()> {
+        self.style_ttl.validate("style_ttl", &shapes.ttl)?;
+        self.style_dp.validate("style_dp", &shapes.dp)
+    }
+
+    /// Write the style as a JSON voice style file, replacing `path`
+    /// atomically
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let temp_path = path.with_extension("json.tmp");
+        let file = File::create(&temp_path)
+            .with_context(|| format!("Failed to create voice style file {}", temp_path.display()))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, self)
+            .with_context(|| format!("Failed to write voice style file {}", temp_path.display()))?;
+        writer
+            .flush()
+            .with_context(|| format!("Failed to write voice style file {}", temp_path.display()))?;
+        std::fs::rename(&temp_path, path)
+            .with_context(|| format!("Failed to replace voice style file {}", path.display()))
+    }
+}
+
+impl StyleComponent {
+    fn to_array(&self, name: &str) -> Result<Array3<f32>> {
+        let &[batch, rows, columns] = self.dims.as_slice() else {
+            anyhow::bail!("{} must have 3 dims, got {:?}", name, self.dims);
+        };
+        let values: Vec<f32> = self.data.iter().flatten().flatten().copied().collect();
+        Array3::from_shape_vec((batch, rows, columns), values)
+            .map_err(|_| anyhow::anyhow!("{} data does not have the shape of its dims {:?}", name, self.dims))
+    }
+
+    fn validate(&self, name: &str, expected: &[i64]) -> Result<()> {
+        if self.dtype != "float32" {
+            anyhow::bail!("{} has type '{}', but the model takes float32", name, self.dtype);
+        }
+        if self.dims.len() != 3 || self.dims[0] != 1 {
+            anyhow::bail!("{} must hold a single voice with dims [1, n, m], got {:?}", name, self.dims);
+        }
+        let fits = expected.len() == self.dims.len()
+            && expected.iter().zip(&self.dims).all(|(&model, &dim)| model < 0 || model as usize == dim);
+        if !fits {
+            anyhow::bail!("{} has dims {:?}, but the model expects {:?}", name, self.dims, expected);
+        }
+        let matches_dims = self.data.len() == self.dims[0]
+            && self.data.iter().all(|matrix| {
+                matrix.len() == self.dims[1] && matrix.iter().all(|row| row.len() == self.dims[2])
+            });
+        if !matches_dims {
+            anyhow::bail!("{} data does not have the shape of its dims {:?}", name, self.dims);
+        }
+        if self.data.iter().flatten().flatten().any(|value| !value.is_finite()) {
+            anyhow::bail!("{} contains values that are not finite", name);
+        }
+        Ok(())
+    }
+}
+
 pub struct UnicodeProcessor {
     indexer: Vec<i64>,
 }
@@ -97,6 +227,130 @@ impl UnicodeProcessor {
 
         (text_ids, text_mask)
     }
+
+    /// Whether the model has an embedding for every code point of `c` after
+    /// NFKD normalization
+    pub fn supports(&self, c: char) -> bool {
+        std::iter::once(c)
+            .nfkd()
+            .all(|d| matches!(self.indexer.get(d as usize), Some(&id) if id >= 0))
+    }
+
+    /// Characters of `text` the model cannot read, in order of first
+    /// appearance, with their character offsets
+    pub fn unsupported_chars(&self, text: &str) -> Vec<UnsupportedChar> {
+        let mut unsupported: Vec<UnsupportedChar> = Vec::new();
+        for (position, c) in text.chars().enumerate() {
+            if c.is_whitespace() || self.supports(c) {
+                continue;
+            }
+            match unsupported.iter_mut().find(|u| u.character == c) {
+                Some(u) => u.positions.push(position),
+                None => unsupported.push(UnsupportedChar::new(c, vec![position])),
+            }
+        }
+        unsupported
+    }
+
+    /// Make `text` readable by the model according to `policy`. Whitespace
+    /// the model lacks becomes a space; `Strict` fails with the other
+    /// unsupported characters.
+    pub fn apply_unknown_char_policy(
+        &self,
+        text: &str,
+        policy: UnknownCharPolicy,
+        replacement: &str,
+    ) -> std::result::Result<String, Vec<UnsupportedChar>> {
+        if policy == UnknownCharPolicy::Strict {
+            let unsupported = self.unsupported_chars(text);
+            if !unsupported.is_empty() {
+                return Err(unsupported);
+            }
+        }
+
+        let mut output = String::with_capacity(text.len());
+        for c in text.chars() {
+            if self.supports(c) {
+                output.push(c);
+            } else if c == '\n' {
+                // Blank lines separate paragraphs for `chunk_text`, which
+                // removes line breaks from the chunks it returns
+                output.push(c);
+            } else if c.is_whitespace() {
+                output.push(' ');
+            } else {
+                match policy {
+                    UnknownCharPolicy::Strip => {}
+                    UnknownCharPolicy::Transliterate => match deunicode::deunicode_char(c) {
+                        Some(ascii) if !ascii.trim().is_empty() && ascii.chars().all(|a| self.supports(a)) => {
+                            output.push_str(ascii)
+                        }
+                        _ => output.push_str(replacement),
+                    },
+                    UnknownCharPolicy::Strict | UnknownCharPolicy::Replace => output.push_str(replacement),
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// What to do with characters the model's unicode indexer does not cover
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownCharPolicy {
+    /// Reject the text, reporting the characters
+    Strict,
+    /// Use an ASCII transliteration, or the replacement if there is none
+    #[default]
+    Transliterate,
+    /// Remove the characters
+    Strip,
+    /// Substitute the replacement for each character
+    Replace,
+}
+
+impl UnknownCharPolicy {
+    pub const SUPPORTED: &'static [&'static str] = &["strict", "transliterate", "strip", "replace"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "strict" => Some(Self::Strict),
+            "transliterate" => Some(Self::Transliterate),
+            "strip" => Some(Self::Strip),
+            "replace" => Some(Self::Replace),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Strict => "strict",
+            Self::Transliterate => "transliterate",
+            Self::Strip => "strip",
+            Self::Replace => "replace",
+        }
+    }
+}
+
+/// A character the model cannot read and where it occurs
+#[derive(Debug, Clone, Serialize)]
+pub struct UnsupportedChar {
+    pub character: char,
+    /// Code point, e.g. "U+1F600"
+    pub code_point: String,
+    /// Character offsets of each occurrence
+    pub positions: Vec<usize>,
+}
+
+impl UnsupportedChar {
+    pub fn new(character: char, positions: Vec<usize>) -> Self {
+        UnsupportedChar {
+            character,
+            code_point: format!("U+{:04X}", character as u32),
+            positions,
+        }
+    }
 }
 
 pub fn preprocess_text(text: &str) -> String {
@@ -143,7 +397,7 @@ pub fn sample_noisy_latent(
         .collect();
 
     let chunk_size = (base_chunk_size * chunk_compress) as usize;
-    let latent_len = (wav_len_max + chunk_size - 1) / chunk_size;
+    let latent_len = wav_len_max.div_ceil(chunk_size);
     let latent_dim_val = (latent_dim * chunk_compress) as usize;
 
     let mut noisy_latent = Array3::<f32>::zeros((bsz, latent_dim_val, latent_len));
@@ -161,7 +415,7 @@ pub fn sample_noisy_latent(
 
     let latent_lengths: Vec<usize> = wav_lengths
         .iter()
-        .map(|&len| (len + chunk_size - 1) / chunk_size)
+        .map(|&len| len.div_ceil(chunk_size))
         .collect();
 
     let latent_mask = length_to_mask(&latent_lengths, Some(latent_len));
@@ -178,232 +432,809 @@ pub fn sample_noisy_latent(
     (noisy_latent, latent_mask)
 }
 
-pub fn write_wav_file<P: AsRef<Path>>(
-    filename: P,
+/// Sample encoding inside a WAV file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WavSampleFormat {
+    Int16,
+    Int24,
+    /// IEEE float, written without clamping or quantization
+    Float32,
+    /// 8-bit G.711 mu-law
+    MuLaw,
+    /// 8-bit G.711 A-law
+    ALaw,
+}
+
+impl WavSampleFormat {
+    /// WAV format tag and bits per sample
+    fn format_tag(self) -> (u16, u16) {
+        match self {
+            WavSampleFormat::Int16 => (1, 16), // PCM
+            WavSampleFormat::Int24 => (1, 24),
+            WavSampleFormat::Float32 => (3, 32), // IEEE float
+            WavSampleFormat::MuLaw => (7, 8),
+            WavSampleFormat::ALaw => (6, 8),
+        }
+    }
+
+    /// Sample rate the format is defined at, if it only supports one
+    pub fn fixed_sample_rate(self) -> Option<u32> {
+        match self {
+            WavSampleFormat::MuLaw | WavSampleFormat::ALaw => Some(G711_SAMPLE_RATE),
+            _ => None,
+        }
+    }
+
+    /// Encode samples as headerless little-endian data in this format
+    pub fn encode_samples(self, audio_data: &[f32]) -> Vec<u8> {
+        match self {
+            WavSampleFormat::Int16 => pcm16_bytes(audio_data),
+            WavSampleFormat::Int24 => {
+                let mut bytes = Vec::with_capacity(audio_data.len() * 3);
+                for &sample in audio_data {
+                    let val = (sample.clamp(-1.0, 1.0) * 8388607.0) as i32;
+                    bytes.extend_from_slice(&val.to_le_bytes()[..3]);
+                }
+                bytes
+            }
+            WavSampleFormat::Float32 => audio_data.iter().flat_map(|s| s.to_le_bytes()).collect(),
+            WavSampleFormat::MuLaw => audio_data.iter().map(|&s| linear_to_ulaw(to_i16(s))).collect(),
+            WavSampleFormat::ALaw => audio_data.iter().map(|&s| linear_to_alaw(to_i16(s))).collect(),
+        }
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * 32767.0) as i16
+}
+
+fn write_wav<W: std::io::Write + std::io::Seek>(
+    mut writer: W,
     audio_data: &[f32],
     sample_rate: i32,
+    sample_format: WavSampleFormat,
 ) -> Result<()> {
+    let (bits_per_sample, hound_format) = match sample_format {
+        WavSampleFormat::Int16 => (16, SampleFormat::Int),
+        WavSampleFormat::Int24 => (24, SampleFormat::Int),
+        WavSampleFormat::Float32 => (32, SampleFormat::Float),
+        WavSampleFormat::MuLaw | WavSampleFormat::ALaw => {
+            // hound only writes PCM and float, so companded files are assembled directly
+            let data = sample_format.encode_samples(audio_data);
+            writer.write_all(&wav_header(sample_rate, sample_format, data.len() as u32))?;
+            writer.write_all(&data)?;
+            return Ok(());
+        }
+    };
     let spec = WavSpec {
         channels: 1,
         sample_rate: sample_rate as u32,
-        bits_per_sample: 16,
-        sample_format: SampleFormat::Int,
+        bits_per_sample,
+        sample_format: hound_format,
     };
-
-    let mut writer = WavWriter::create(filename, spec)?;
+    let mut writer = WavWriter::new(writer, spec)?;
 
     for &sample in audio_data {
-        let clamped = sample.max(-1.0).min(1.0);
-        let val = (clamped * 32767.0) as i16;
-        writer.write_sample(val)?;
+        match sample_format {
+            WavSampleFormat::Int24 => {
+                let clamped = sample.clamp(-1.0, 1.0);
+                writer.write_sample((clamped * 8388607.0) as i32)?;
+            }
+            WavSampleFormat::Float32 => writer.write_sample(sample)?,
+            _ => writer.write_sample(to_i16(sample))?,
+        }
     }
 
     writer.finalize()?;
     Ok(())
 }
 
+pub fn write_wav_file<P: AsRef<Path>>(
+    filename: P,
+    audio_data: &[f32],
+    sample_rate: i32,
+    sample_format: WavSampleFormat,
+) -> Result<()> {
+    let file = std::io::BufWriter::new(File::create(filename)?);
+    write_wav(file, audio_data, sample_rate, sample_format)
+}
+
 pub fn write_wav_to_buffer(
     buffer: &mut Vec<u8>,
     audio_data: &[f32],
     sample_rate: i32,
+    sample_format: WavSampleFormat,
 ) -> Result<()> {
-    use std::io::Cursor;
+    write_wav(std::io::Cursor::new(buffer), audio_data, sample_rate, sample_format)
+}
 
-    let spec = WavSpec {
-        channels: 1,
-        sample_rate: sample_rate as u32,
-        bits_per_sample: 16,
-        sample_format: SampleFormat::Int,
-    };
+/// Mono WAV header for `data_len` bytes of samples. Formats other than
+/// integer PCM get the extended 18-byte `fmt ` chunk they require.
+fn wav_header(sample_rate: i32, sample_format: WavSampleFormat, data_len: u32) -> Vec<u8> {
+    let (format_tag, bits_per_sample) = sample_format.format_tag();
+    let fmt_len: u32 = if format_tag == 1 { 16 } else { 18 };
+    let sample_rate = sample_rate as u32;
+    let block_align = bits_per_sample / 8;
+    let byte_rate = sample_rate * block_align as u32;
+
+    let mut header = Vec::with_capacity(46);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&data_len.saturating_add(20 + fmt_len).to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&fmt_len.to_le_bytes());
+    header.extend_from_slice(&format_tag.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // mono
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&byte_rate.to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&bits_per_sample.to_le_bytes());
+    if fmt_len == 18 {
+        header.extend_from_slice(&0u16.to_le_bytes()); // no extension data
+    }
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
 
-    let mut cursor = Cursor::new(buffer);
-    let mut writer = WavWriter::new(&mut cursor, spec)?;
+/// Build a mono WAV header for streamed output whose total length is not
+/// known up front. RIFF and data sizes are set to `u32::MAX`, which players
+/// and ffmpeg treat as "read until end of stream".
+pub fn wav_stream_header(sample_rate: i32, sample_format: WavSampleFormat) -> Vec<u8> {
+    wav_header(sample_rate, sample_format, u32::MAX)
+}
 
+/// Convert float samples to headerless 16-bit little-endian PCM
+pub fn pcm16_bytes(audio_data: &[f32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(audio_data.len() * 2);
     for &sample in audio_data {
-        let clamped = sample.max(-1.0).min(1.0);
+        let clamped = sample.clamp(-1.0, 1.0);
         let val = (clamped * 32767.0) as i16;
-        writer.write_sample(val)?;
+        bytes.extend_from_slice(&val.to_le_bytes());
     }
+    bytes
+}
 
-    writer.finalize()?;
+/// Encode audio as a constant-bitrate mono MP3
+pub fn write_mp3_to_buffer(
+    buffer: &mut Vec<u8>,
+    audio_data: &[f32],
+    sample_rate: i32,
+    bitrate_kbps: u32,
+) -> Result<()> {
+    let mut encoder = Mp3Encoder::new(sample_rate as u32, bitrate_kbps)?;
+    buffer.extend_from_slice(&encoder.encode(audio_data));
+    buffer.extend_from_slice(&encoder.finish());
     Ok(())
 }
 
+/// Output audio format, as named by `response_format` or a CLI file extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioFormat {
+    Wav(WavSampleFormat),
+    /// Headerless 16-bit little-endian samples
+    Pcm,
+    /// Headerless 8 kHz G.711 mu-law
+    Ulaw,
+    /// Headerless 8 kHz G.711 A-law
+    Alaw,
+    Flac,
+    Mp3,
+    Opus,
+}
+
+impl AudioFormat {
+    pub const SUPPORTED: &'static [&'static str] = &[
+        "wav", "wav_s24", "wav_f32", "wav_ulaw", "wav_alaw", "pcm", "ulaw", "alaw", "flac", "mp3", "opus",
+    ];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "wav" => Some(AudioFormat::Wav(WavSampleFormat::Int16)),
+            "wav_s24" => Some(AudioFormat::Wav(WavSampleFormat::Int24)),
+            "wav_f32" => Some(AudioFormat::Wav(WavSampleFormat::Float32)),
+            "wav_ulaw" => Some(AudioFormat::Wav(WavSampleFormat::MuLaw)),
+            "wav_alaw" => Some(AudioFormat::Wav(WavSampleFormat::ALaw)),
+            "pcm" => Some(AudioFormat::Pcm),
+            "ulaw" | "mulaw" => Some(AudioFormat::Ulaw),
+            "alaw" => Some(AudioFormat::Alaw),
+            "flac" => Some(AudioFormat::Flac),
+            "mp3" => Some(AudioFormat::Mp3),
+            "opus" | "ogg" => Some(AudioFormat::Opus),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AudioFormat::Wav(WavSampleFormat::Int16) => "wav",
+            AudioFormat::Wav(WavSampleFormat::Int24) => "wav_s24",
+            AudioFormat::Wav(WavSampleFormat::Float32) => "wav_f32",
+            AudioFormat::Wav(WavSampleFormat::MuLaw) => "wav_ulaw",
+            AudioFormat::Wav(WavSampleFormat::ALaw) => "wav_alaw",
+            AudioFormat::Pcm => "pcm",
+            AudioFormat::Ulaw => "ulaw",
+            AudioFormat::Alaw => "alaw",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Opus => "opus",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            AudioFormat::Wav(_) => "audio/wav",
+            AudioFormat::Pcm => "audio/pcm",
+            AudioFormat::Ulaw => "audio/basic",
+            AudioFormat::Alaw => "audio/x-alaw-basic",
+            AudioFormat::Flac => "audio/flac",
+            AudioFormat::Mp3 => "audio/mpeg",
+            AudioFormat::Opus => "audio/ogg",
+        }
+    }
+
+    /// File extension used when saving this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Wav(_) => "wav",
+            _ => self.name(),
+        }
+    }
+
+    /// Sample rate the format is always encoded at, if it only supports one
+    pub fn fixed_sample_rate(self) -> Option<u32> {
+        match self {
+            AudioFormat::Wav(sample_format) => sample_format.fixed_sample_rate(),
+            AudioFormat::Ulaw | AudioFormat::Alaw => Some(G711_SAMPLE_RATE),
+            _ => None,
+        }
+    }
+
+    /// Check a requested output sample rate against this format and return
+    /// the rate to encode at; `None` keeps the model's rate
+    pub fn output_sample_rate(self, requested: Option<u32>) -> Result<Option<u32>> {
+        match (self.fixed_sample_rate(), requested) {
+            (Some(fixed), Some(rate)) if rate != fixed => Err(anyhow::anyhow!(
+                "Format '{}' is always {} Hz, sample_rate {} is not supported",
+                self.name(),
+                fixed,
+                rate
+            )),
+            (Some(fixed), _) => Ok(Some(fixed)),
+            (None, Some(rate)) if !SUPPORTED_SAMPLE_RATES.contains(&rate) => {
+                let supported: Vec<String> = SUPPORTED_SAMPLE_RATES.iter().map(|r| r.to_string()).collect();
+                Err(anyhow::anyhow!(
+                    "Sample rate {} Hz is not supported. Supported rates: {}",
+                    rate,
+                    supported.join(", ")
+                ))
+            }
+            (None, requested) => Ok(requested),
+        }
+    }
+}
+
+/// Output sample rates that audio can be resampled to, in Hz
+pub const SUPPORTED_SAMPLE_RATES: &[u32] = &[8000, 16000, 22050, 24000, 44100, 48000];
+
+/// Encoder options shared by all output formats
+#[derive(Debug, Clone, Copy)]
+pub struct EncoderSettings {
+    /// MP3 bitrate in kbps
+    pub mp3_bitrate: u32,
+    /// Opus bitrate in kbps
+    pub opus_bitrate: u32,
+    /// Rate to resample to before encoding; `None` keeps the synthesized rate
+    pub sample_rate: Option<u32>,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> Self {
+        Self {
+            mp3_bitrate: 128,
+            opus_bitrate: 64,
+            sample_rate: None,
+        }
+    }
+}
+
+impl EncoderSettings {
+    /// Sample rate `format` ends up encoded at for audio synthesized at `sample_rate`
+    pub fn output_sample_rate(&self, format: AudioFormat, sample_rate: i32) -> i32 {
+        format
+            .fixed_sample_rate()
+            .or(self.sample_rate)
+            .map(|rate| rate as i32)
+            .unwrap_or(sample_rate)
+    }
+}
+
+/// Resample a complete clip to the rate it is encoded at
+fn resample_for_output<'a>(
+    format: AudioFormat,
+    audio_data: &'a [f32],
+    sample_rate: i32,
+    settings: &EncoderSettings,
+) -> Result<(Cow<'a, [f32]>, i32)> {
+    let output_rate = settings.output_sample_rate(format, sample_rate);
+    if output_rate == sample_rate {
+        return Ok((Cow::Borrowed(audio_data), sample_rate));
+    }
+    let resampled = resample(audio_data, sample_rate as u32, output_rate as u32)?;
+    Ok((Cow::Owned(resampled), output_rate))
+}
+
+/// Encode a complete clip in the given format
+pub fn encode_audio(
+    format: AudioFormat,
+    audio_data: &[f32],
+    sample_rate: i32,
+    settings: &EncoderSettings,
+) -> Result<Vec<u8>> {
+    let (audio_data, sample_rate) = resample_for_output(format, audio_data, sample_rate, settings)?;
+    let audio_data = &audio_data[..];
+
+    let mut buffer = Vec::new();
+    match format {
+        AudioFormat::Wav(sample_format) => write_wav_to_buffer(&mut buffer, audio_data, sample_rate, sample_format)?,
+        AudioFormat::Pcm => buffer = pcm16_bytes(audio_data),
+        AudioFormat::Ulaw => buffer = WavSampleFormat::MuLaw.encode_samples(audio_data),
+        AudioFormat::Alaw => buffer = WavSampleFormat::ALaw.encode_samples(audio_data),
+        AudioFormat::Flac => write_flac_to_buffer(&mut buffer, audio_data, sample_rate)?,
+        AudioFormat::Mp3 => write_mp3_to_buffer(&mut buffer, audio_data, sample_rate, settings.mp3_bitrate)?,
+        AudioFormat::Opus => write_opus_to_buffer(&mut buffer, audio_data, sample_rate, settings.opus_bitrate)?,
+    }
+    Ok(buffer)
+}
+
+/// Write a complete clip to `filename` in the given format
+pub fn write_audio_file<P: AsRef<Path>>(
+    filename: P,
+    format: AudioFormat,
+    audio_data: &[f32],
+    sample_rate: i32,
+    settings: &EncoderSettings,
+) -> Result<()> {
+    match format {
+        AudioFormat::Wav(sample_format) => {
+            let (audio_data, sample_rate) = resample_for_output(format, audio_data, sample_rate, settings)?;
+            write_wav_file(filename, &audio_data, sample_rate, sample_format)
+        }
+        _ => {
+            let buffer = encode_audio(format, audio_data, sample_rate, settings)?;
+            std::fs::write(filename, buffer)?;
+            Ok(())
+        }
+    }
+}
+
+/// Incremental encoder for audio that is delivered while it is synthesized
+pub struct AudioStreamEncoder {
+    resampler: Resampler,
+    encoder: StreamEncoder,
+}
+
+enum StreamEncoder {
+    Wav {
+        sample_rate: i32,
+        sample_format: WavSampleFormat,
+        header_sent: bool,
+    },
+    /// Headerless samples
+    Raw(WavSampleFormat),
+    Flac(Box<FlacEncoder>),
+    Mp3(Box<Mp3Encoder>),
+    Opus(Box<OggOpusEncoder>),
+}
+
+impl AudioStreamEncoder {
+    pub fn new(format: AudioFormat, sample_rate: i32, settings: &EncoderSettings) -> Result<Self> {
+        let output_rate = settings.output_sample_rate(format, sample_rate);
+        let encoder = match format {
+            AudioFormat::Wav(sample_format) => StreamEncoder::Wav {
+                sample_rate: output_rate,
+                sample_format,
+                header_sent: false,
+            },
+            AudioFormat::Pcm => StreamEncoder::Raw(WavSampleFormat::Int16),
+            AudioFormat::Ulaw => StreamEncoder::Raw(WavSampleFormat::MuLaw),
+            AudioFormat::Alaw => StreamEncoder::Raw(WavSampleFormat::ALaw),
+            AudioFormat::Flac => StreamEncoder::Flac(Box::new(FlacEncoder::new(output_rate as u32, None)?)),
+            AudioFormat::Mp3 => StreamEncoder::Mp3(Box::new(Mp3Encoder::new(output_rate as u32, settings.mp3_bitrate)?)),
+            AudioFormat::Opus => {
+                StreamEncoder::Opus(Box::new(OggOpusEncoder::new(output_rate as u32, settings.opus_bitrate)?))
+            }
+        };
+
+        Ok(Self {
+            resampler: Resampler::new(sample_rate as u32, output_rate as u32)?,
+            encoder,
+        })
+    }
+
+    /// Encode the next block of samples, returning the bytes ready to send
+    pub fn encode(&mut self, audio_data: &[f32]) -> Result<Vec<u8>> {
+        let audio_data = self.resampler.process(audio_data)?;
+        self.encode_resampled(&audio_data)
+    }
+
+    /// Flush any buffered audio at the end of the stream
+    pub fn finish(&mut self) -> Result<Vec<u8>> {
+        let audio_data = self.resampler.finish()?;
+        let mut bytes = self.encode_resampled(&audio_data)?;
+        match &mut self.encoder {
+            StreamEncoder::Wav { .. } | StreamEncoder::Raw(_) => {}
+            StreamEncoder::Flac(encoder) => bytes.extend_from_slice(&encoder.finish()),
+            StreamEncoder::Mp3(encoder) => bytes.extend_from_slice(&encoder.finish()),
+            StreamEncoder::Opus(encoder) => bytes.extend_from_slice(&encoder.finish()?),
+        }
+        Ok(bytes)
+    }
+
+    fn encode_resampled(&mut self, audio_data: &[f32]) -> Result<Vec<u8>> {
+        match &mut self.encoder {
+            StreamEncoder::Wav {
+                sample_rate,
+                sample_format,
+                header_sent,
+            } => {
+                let mut bytes = if *header_sent {
+                    Vec::new()
+                } else {
+                    *header_sent = true;
+                    wav_stream_header(*sample_rate, *sample_format)
+                };
+                bytes.extend_from_slice(&sample_format.encode_samples(audio_data));
+                Ok(bytes)
+            }
+            StreamEncoder::Raw(sample_format) => Ok(sample_format.encode_samples(audio_data)),
+            StreamEncoder::Flac(encoder) => Ok(encoder.encode(audio_data)),
+            StreamEncoder::Mp3(encoder) => Ok(encoder.encode(audio_data)),
+            StreamEncoder::Opus(encoder) => encoder.encode(audio_data),
+        }
+    }
+}
+
 const MAX_CHUNK_LENGTH: usize = 300;
 
-const ABBREVIATIONS: &[&str] = &[
-    "Dr.", "Mr.", "Mrs.", "Ms.", "Prof.", "Sr.", "Jr.",
-    "St.", "Ave.", "Rd.", "Blvd.", "Dept.", "Inc.", "Ltd.",
-    "Co.", "Corp.", "etc.", "vs.", "i.e.", "e.g.", "Ph.D.",
-];
+/// Where chunk boundaries are placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitStrategy {
+    /// Keep each paragraph whole when it fits, otherwise pack sentences
+    /// (best prosody)
+    #[default]
+    Paragraph,
+    /// Start a new chunk at every sentence
+    Sentence,
+    /// Start a new chunk at every clause (lowest latency to first audio)
+    Clause,
+}
+
+impl SplitStrategy {
+    pub const SUPPORTED: &'static [&'static str] = &["paragraph", "sentence", "clause"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "paragraph" => Some(Self::Paragraph),
+            "sentence" => Some(Self::Sentence),
+            "clause" => Some(Self::Clause),
+            _ => None,
+        }
+    }
+}
+
+/// How text is cut into chunks for synthesis and how long the pauses
+/// between chunks are
+#[derive(Debug, Clone)]
+pub struct ChunkSettings {
+    /// Longest chunk in characters
+    pub max_chunk_chars: usize,
+    /// Chunks shorter than this are merged with a neighbour in the same
+    /// paragraph when the result still fits in `max_chunk_chars`
+    pub min_chunk_chars: usize,
+    pub strategy: SplitStrategy,
+    /// Pause between chunks of the same paragraph, in seconds
+    pub sentence_silence: f32,
+    /// Pause before the first chunk of a new paragraph, in seconds
+    pub paragraph_silence: f32,
+    /// Sentence boundary rules for the text's language
+    pub segmenter: Arc<Segmenter>,
+}
+
+impl Default for ChunkSettings {
+    fn default() -> Self {
+        ChunkSettings {
+            max_chunk_chars: MAX_CHUNK_LENGTH,
+            min_chunk_chars: 0,
+            strategy: SplitStrategy::Paragraph,
+            sentence_silence: 0.3,
+            paragraph_silence: 0.3,
+            segmenter: Arc::new(Segmenter::new(DEFAULT_LANGUAGE, Vec::new())),
+        }
+    }
+}
 
-pub fn chunk_text(text: &str, max_len: Option<usize>) -> Vec<String> {
-    let max_len = max_len.unwrap_or(MAX_CHUNK_LENGTH);
+impl ChunkSettings {
+    pub const MAX_CHUNK_CHARS_RANGE: std::ops::RangeInclusive<usize> = 10..=1000;
+    pub const SILENCE_RANGE: std::ops::RangeInclusive<f32> = 0.0..=5.0;
+
+    /// Check the settings are within the supported ranges
+    pub fn validate(&self) -> Result<()> {
+        let chars = Self::MAX_CHUNK_CHARS_RANGE;
+        if !chars.contains(&self.max_chunk_chars) {
+            anyhow::bail!("max_chunk_chars must be between {} and {}", chars.start(), chars.end());
+        }
+        if self.min_chunk_chars > self.max_chunk_chars {
+            anyhow::bail!("min_chunk_chars must not exceed max_chunk_chars ({})", self.max_chunk_chars);
+        }
+        let silence = Self::SILENCE_RANGE;
+        for (name, value) in [("sentence_silence", self.sentence_silence), ("paragraph_silence", self.paragraph_silence)] {
+            if !silence.contains(&value) {
+                anyhow::bail!("{} must be between {} and {} seconds", name, silence.start(), silence.end());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One chunk of text to synthesize
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextChunk {
+    pub text: String,
+    /// The chunk follows a paragraph break in the chunked text
+    pub after_paragraph_break: bool,
+}
+
+/// Split text into chunks of at most `settings.max_chunk_chars` characters.
+/// Paragraphs are never merged. Within a paragraph the split strategy
+/// decides where chunks must start; a piece that is still too long is split
+/// at sentence boundaries, then at clause punctuation, then between words,
+/// and as a last resort between grapheme clusters, so a chunk never ends
+/// inside a user-perceived character.
+pub fn chunk_text(text: &str, settings: &ChunkSettings) -> Vec<TextChunk> {
+    let max_len = settings.max_chunk_chars.max(1);
     let text = text.trim();
-    
+
     if text.is_empty() {
-        return vec![String::new()];
+        return vec![TextChunk { text: String::new(), after_paragraph_break: false }];
     }
 
     // Split by paragraphs
     let para_re = Regex::new(r"\n\s*\n").unwrap();
-    let paragraphs: Vec<&str> = para_re.split(text).collect();
     let mut chunks = Vec::new();
 
-    for para in paragraphs {
+    for para in para_re.split(text) {
         let para = para.trim();
         if para.is_empty() {
             continue;
         }
 
-        if para.len() <= max_len {
-            chunks.push(para.to_string());
-            continue;
+        let segmenter = &settings.segmenter;
+        let mut para_chunks = Vec::new();
+        match settings.strategy {
+            SplitStrategy::Paragraph if char_len(para) <= max_len => para_chunks.push(para.to_string()),
+            SplitStrategy::Paragraph => pack_pieces(para, SplitLevel::Sentence, max_len, segmenter, &mut para_chunks),
+            SplitStrategy::Sentence => split_pieces(para, SplitLevel::Sentence, max_len, segmenter, &mut para_chunks),
+            SplitStrategy::Clause => {
+                for sentence in segmenter.split(para) {
+                    split_pieces(sentence, SplitLevel::Clause, max_len, segmenter, &mut para_chunks);
+                }
+            }
         }
+        let para_chunks = merge_short_chunks(para_chunks, settings.min_chunk_chars, max_len);
+
+        let after_paragraph_break = !chunks.is_empty();
+        chunks.extend(para_chunks.into_iter().enumerate().map(|(i, text)| TextChunk {
+            // Line breaks within a paragraph are read as spaces
+            text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+            after_paragraph_break: after_paragraph_break && i == 0,
+        }));
+    }
 
-        // Split by sentences
-        let sentences = split_sentences(para);
-        let mut current = String::new();
-        let mut current_len = 0;
+    if chunks.is_empty() {
+        vec![TextChunk { text: String::new(), after_paragraph_break: false }]
+    } else {
+        chunks
+    }
+}
 
-        for sentence in sentences {
-            let sentence = sentence.trim();
-            if sentence.is_empty() {
-                continue;
-            }
+fn char_len(text: &str) -> usize {
+    text.chars().count()
+}
 
-            let sentence_len = sentence.len();
-            if sentence_len > max_len {
-                // If sentence is longer than max_len, split by comma or space
-                if !current.is_empty() {
-                    chunks.push(current.trim().to_string());
-                    current.clear();
-                    current_len = 0;
-                }
+/// Boundaries tried, from coarsest to finest, when a piece of text is too
+/// long for one chunk
+#[derive(Clone, Copy)]
+enum SplitLevel {
+    Sentence,
+    Clause,
+    Word,
+    Grapheme,
+}
 
-                // Try splitting by comma
-                let parts: Vec<&str> = sentence.split(',').collect();
-                for part in parts {
-                    let part = part.trim();
-                    if part.is_empty() {
-                        continue;
-                    }
-
-                    let part_len = part.len();
-                    if part_len > max_len {
-                        // Split by space as last resort
-                        let words: Vec<&str> = part.split_whitespace().collect();
-                        let mut word_chunk = String::new();
-                        let mut word_chunk_len = 0;
-
-                        for word in words {
-                            let word_len = word.len();
-                            if word_chunk_len + word_len + 1 > max_len && !word_chunk.is_empty() {
-                                chunks.push(word_chunk.trim().to_string());
-                                word_chunk.clear();
-                                word_chunk_len = 0;
-                            }
-
-                            if !word_chunk.is_empty() {
-                                word_chunk.push(' ');
-                                word_chunk_len += 1;
-                            }
-                            word_chunk.push_str(word);
-                            word_chunk_len += word_len;
-                        }
+impl SplitLevel {
+    fn finer(self) -> Option<Self> {
+        match self {
+            SplitLevel::Sentence => Some(SplitLevel::Clause),
+            SplitLevel::Clause => Some(SplitLevel::Word),
+            SplitLevel::Word => Some(SplitLevel::Grapheme),
+            SplitLevel::Grapheme => None,
+        }
+    }
 
-                        if !word_chunk.is_empty() {
-                            chunks.push(word_chunk.trim().to_string());
-                        }
-                    } else {
-                        if current_len + part_len + 1 > max_len && !current.is_empty() {
-                            chunks.push(current.trim().to_string());
-                            current.clear();
-                            current_len = 0;
-                        }
+    /// Split `text` into consecutive pieces that concatenate back to `text`;
+    /// separators stay attached to the end of the piece before them
+    fn split<'a>(self, text: &'a str, segmenter: &Segmenter) -> Vec<&'a str> {
+        let ends = match self {
+            SplitLevel::Sentence => segmenter.boundaries(text),
+            SplitLevel::Clause => clause_boundaries(text),
+            SplitLevel::Word => return text.split_inclusive(char::is_whitespace).collect(),
+            SplitLevel::Grapheme => return text.graphemes(true).collect(),
+        };
 
-                        if !current.is_empty() {
-                            current.push_str(", ");
-                            current_len += 2;
-                        }
-                        current.push_str(part);
-                        current_len += part_len;
-                    }
-                }
-                continue;
-            }
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        for end in ends {
+            pieces.push(&text[start..end]);
+            start = end;
+        }
+        if start < text.len() {
+            pieces.push(&text[start..]);
+        }
+        pieces
+    }
+}
 
-            if current_len + sentence_len + 1 > max_len && !current.is_empty() {
-                chunks.push(current.trim().to_string());
-                current.clear();
-                current_len = 0;
-            }
+/// Greedily pack the pieces of `text` at `level` into chunks of at most
+/// `max_len` characters, splitting oversized pieces at the next finer level
+fn pack_pieces(text: &str, level: SplitLevel, max_len: usize, segmenter: &Segmenter, chunks: &mut Vec<String>) {
+    let mut current = String::new();
+    let mut current_len = 0;
 
+    for piece in level.split(text, segmenter) {
+        let content = piece.trim();
+        if content.is_empty() {
             if !current.is_empty() {
-                current.push(' ');
-                current_len += 1;
+                current.push_str(piece);
+                current_len += char_len(piece);
+            }
+            continue;
+        }
+
+        let piece_len = char_len(piece.trim_end());
+        if piece_len > max_len {
+            flush_chunk(&mut current, &mut current_len, chunks);
+            match level.finer() {
+                Some(finer) => pack_pieces(content, finer, max_len, segmenter, chunks),
+                // A single grapheme cluster longer than the limit stays whole
+                None => chunks.push(content.to_string()),
             }
-            current.push_str(sentence);
-            current_len += sentence_len;
+            continue;
         }
 
-        if !current.is_empty() {
-            chunks.push(current.trim().to_string());
+        if !current.is_empty() && current_len + piece_len > max_len {
+            flush_chunk(&mut current, &mut current_len, chunks);
         }
+        let piece = if current.is_empty() { piece.trim_start() } else { piece };
+        current.push_str(piece);
+        current_len += char_len(piece);
     }
 
-    if chunks.is_empty() {
-        vec![String::new()]
-    } else {
-        chunks
+    flush_chunk(&mut current, &mut current_len, chunks);
+}
+
+/// Make every piece of `text` at `level` its own chunk, splitting pieces
+/// that are too long at finer levels
+fn split_pieces(text: &str, level: SplitLevel, max_len: usize, segmenter: &Segmenter, chunks: &mut Vec<String>) {
+    for piece in level.split(text, segmenter) {
+        let piece = piece.trim();
+        if piece.is_empty() {
+            continue;
+        }
+        if char_len(piece) <= max_len {
+            chunks.push(piece.to_string());
+        } else {
+            match level.finer() {
+                Some(finer) => pack_pieces(piece, finer, max_len, segmenter, chunks),
+                None => chunks.push(piece.to_string()),
+            }
+        }
     }
 }
 
-fn split_sentences(text: &str) -> Vec<String> {
-    // Rust's regex doesn't support lookbehind, so we use a simpler approach
-    // Split on sentence boundaries and then check if they're abbreviations
-    let re = Regex::new(r"([.!?])\s+").unwrap();
-    
-    // Find all matches
-    let matches: Vec<_> = re.find_iter(text).collect();
-    if matches.is_empty() {
-        return vec![text.to_string()];
+/// Join chunks shorter than `min_len` to the following chunk (or, for the
+/// last one, the preceding chunk) while the result fits in `max_len`
+fn merge_short_chunks(chunks: Vec<String>, min_len: usize, max_len: usize) -> Vec<String> {
+    if min_len == 0 {
+        return chunks;
     }
-    
-    let mut sentences = Vec::new();
-    let mut last_end = 0;
-    
-    for m in matches {
-        // Get the text before the punctuation
-        let before_punc = &text[last_end..m.start()];
-        
-        // Check if this ends with an abbreviation
-        let mut is_abbrev = false;
-        for abbrev in ABBREVIATIONS {
-            let combined = format!("{}{}", before_punc.trim(), &text[m.start()..m.start()+1]);
-            if combined.ends_with(abbrev) {
-                is_abbrev = true;
-                break;
+
+    // Full-width punctuation is not followed by a space
+    let join = |a: &str, b: &str| {
+        if a.ends_with(['。', '！', '？', '｡', '，', '、', '；']) {
+            format!("{}{}", a, b)
+        } else {
+            format!("{} {}", a, b)
+        }
+    };
+
+    let mut merged: Vec<String> = Vec::new();
+    for chunk in chunks {
+        match merged.last_mut() {
+            Some(last) if char_len(last) < min_len && char_len(&join(last, &chunk)) <= max_len => {
+                *last = join(last, &chunk);
             }
+            _ => merged.push(chunk),
         }
-        
-        if !is_abbrev {
-            // This is a real sentence boundary
-            sentences.push(text[last_end..m.end()].to_string());
-            last_end = m.end();
+    }
+
+    if merged.len() >= 2 && char_len(&merged[merged.len() - 1]) < min_len {
+        let last = merged.pop().unwrap();
+        let previous = merged.last_mut().unwrap();
+        let joined = join(previous, &last);
+        if char_len(&joined) <= max_len {
+            *previous = joined;
+        } else {
+            merged.push(last);
         }
     }
-    
-    // Add the remaining text
-    if last_end < text.len() {
-        sentences.push(text[last_end..].to_string());
+    merged
+}
+
+fn flush_chunk(current: &mut String, current_len: &mut usize, chunks: &mut Vec<String>) {
+    let chunk = current.trim();
+    if !chunk.is_empty() {
+        chunks.push(chunk.to_string());
     }
-    
-    if sentences.is_empty() {
-        vec![text.to_string()]
-    } else {
-        sentences
+    current.clear();
+    *current_len = 0;
+}
+
+/// Byte offsets just past each clause separator: a comma or semicolon
+/// followed by whitespace, or a full-width comma, enumeration comma or
+/// semicolon
+fn clause_boundaries(text: &str) -> Vec<usize> {
+    let re = Regex::new(r"[,;]\s+|[，、；]\s*").unwrap();
+    re.find_iter(text).map(|m| m.end()).collect()
+}
+
+/// Remove every complete sentence from the front of `buffer` and return them,
+/// leaving a trailing partial sentence in place. Used for incremental text
+/// input, where the end of the buffer may still be growing. A partial
+/// sentence longer than `max_chars` is cut like an oversized chunk (at
+/// clauses, then words, then grapheme clusters), keeping only its last piece,
+/// so the buffer stays bounded however long the input goes without a
+/// sentence end.
+pub fn drain_complete_sentences(buffer: &mut String, segmenter: &Segmenter, max_chars: usize) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let boundaries = segmenter.boundaries(buffer);
+    if let Some(&last) = boundaries.last() {
+        let mut start = 0;
+        for end in boundaries {
+            let sentence = buffer[start..end].trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence.to_string());
+            }
+            start = end;
+        }
+        buffer.drain(..last);
     }
+
+    if char_len(buffer.trim()) > max_chars {
+        let mut pieces = Vec::new();
+        pack_pieces(buffer, SplitLevel::Clause, max_chars.max(1), segmenter, &mut pieces);
+        // The last piece may be a word that is still being typed; a single
+        // oversized grapheme cluster is sent as it is
+        let rest = if pieces.len() > 1 { pieces.pop().unwrap_or_default() } else { String::new() };
+        let trailing_space = !rest.is_empty() && buffer.ends_with(char::is_whitespace);
+        sentences.extend(pieces);
+        *buffer = rest;
+        if trailing_space {
+            buffer.push(' ');
+        }
+    }
+    sentences
 }
 
 // Utility Functions
@@ -442,12 +1273,98 @@ use ort::{
  session::Session, value::Value
 };
 
+fn input_shape(session: &Session, name: &str) -> Result<Vec<i64>> {
+    session
+        .inputs
+        .iter()
+        .find(|input| input.name == name)
+        .and_then(|input| input.input_type.tensor_shape())
+        .map(|shape| shape.to_vec())
+        .ok_or_else(|| anyhow::anyhow!("The model has no tensor input '{}'", name))
+}
+
+/// Audio for one text chunk, as produced by `TextToSpeech::call_segments_streaming`
+#[derive(Clone, Debug)]
+pub struct SpeechChunk {
+    /// Position of the chunk in the output
+    pub index: usize,
+    /// Text of the chunk as returned by `chunk_text`
+    pub text: String,
+    /// Samples for this chunk, including the leading inter-chunk silence
+    pub audio: Vec<f32>,
+    /// Speech duration of the chunk in seconds (excluding silence)
+    pub duration: f32,
+    /// Start time of the chunk's speech within the full output, in seconds
+    pub start_time: f32,
+}
+
 #[derive(Clone, Debug)]
 pub struct Style {
     pub ttl: Array3<f32>,
     pub dp: Array3<f32>,
 }
 
+/// A span of input spoken with one voice and rate
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextSegment {
+    pub text: String,
+    /// Voice named in the input markup; `None` uses the request voice
+    pub voice: Option<String>,
+    /// Speed multiplier relative to the request speed
+    pub rate: f32,
+    /// Silence before the segment in seconds; `None` uses the normal gap between chunks
+    pub pause_before: Option<f32>,
+}
+
+impl TextSegment {
+    pub fn plain(text: &str) -> Self {
+        TextSegment {
+            text: text.to_string(),
+            voice: None,
+            rate: 1.0,
+            pause_before: None,
+        }
+    }
+}
+
+/// Split input into segments: SSML documents follow their markup, plain
+/// text is a single segment and Markdown or HTML is converted to a single
+/// segment of speakable text
+pub fn input_segments(input: &str, format: InputFormat, code_block_phrase: Option<&str>) -> Result<Vec<TextSegment>> {
+    match format {
+        InputFormat::Text if is_ssml(input) => parse_ssml(input),
+        InputFormat::Text => Ok(vec![TextSegment::plain(input)]),
+        _ => Ok(vec![TextSegment::plain(&to_speakable_text(input, format, code_block_phrase))]),
+    }
+}
+
+/// Voice styles available to one synthesis call
+#[derive(Clone, Debug)]
+pub struct VoiceStyles {
+    pub default: Style,
+    /// Styles for voices named in the input, keyed by name
+    pub named: HashMap<String, Style>,
+}
+
+impl VoiceStyles {
+    pub fn single(style: Style) -> Self {
+        VoiceStyles {
+            default: style,
+            named: HashMap::new(),
+        }
+    }
+
+    fn get(&self, voice: Option<&str>) -> Result<&Style> {
+        match voice {
+            None => Ok(&self.default),
+            Some(name) => self
+                .named
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("No voice style loaded for voice '{}'", name)),
+        }
+    }
+}
+
 pub struct TextToSpeech {
     cfgs: Config,
     text_processor: UnicodeProcessor,
@@ -585,40 +1502,114 @@ impl TextToSpeech {
         Ok((wav, duration))
     }
 
+    pub fn text_processor(&self) -> &UnicodeProcessor {
+        &self.text_processor
+    }
+
+    /// Style tensor shapes the model takes
+    pub fn style_shapes(&self) -> Result<StyleShapes> {
+        Ok(StyleShapes {
+            ttl: input_shape(&self.text_enc_ort, "style_ttl")?,
+            dp: input_shape(&self.dp_ort, "style_dp")?,
+        })
+    }
+
     pub fn call(
         &mut self,
         text: &str,
         style: &Style,
         total_step: usize,
         speed: f32,
-        silence_duration: f32,
+        chunking: &ChunkSettings,
+    ) -> Result<(Vec<f32>, f32)> {
+        let styles = VoiceStyles::single(style.clone());
+        self.call_segments(&[TextSegment::plain(text)], &styles, total_step, speed, chunking)
+    }
+
+    /// Synthesize segments into one continuous waveform
+    pub fn call_segments(
+        &mut self,
+        segments: &[TextSegment],
+        styles: &VoiceStyles,
+        total_step: usize,
+        speed: f32,
+        chunking: &ChunkSettings,
     ) -> Result<(Vec<f32>, f32)> {
-        let chunks = chunk_text(text, None);
-        
         let mut wav_cat: Vec<f32> = Vec::new();
-        let mut dur_cat: f32 = 0.0;
 
-        for (i, chunk) in chunks.iter().enumerate() {
-            let (wav, duration) = self._infer(&[chunk.clone()], style, total_step, speed)?;
-            
-            let dur = duration[0];
-            let wav_len = (self.sample_rate as f32 * dur) as usize;
-            let wav_chunk = &wav[..wav_len.min(wav.len())];
+        let dur_cat = self.call_segments_streaming(segments, styles, total_step, speed, chunking, |chunk| {
+            wav_cat.extend_from_slice(&chunk.audio);
+            Ok(())
+        })?;
+
+        Ok((wav_cat, dur_cat))
+    }
 
-            if i == 0 {
-                wav_cat.extend_from_slice(wav_chunk);
-                dur_cat = dur;
-            } else {
-                let silence_len = (silence_duration * self.sample_rate as f32) as usize;
-                let silence = vec![0.0f32; silence_len];
-                
-                wav_cat.extend_from_slice(&silence);
-                wav_cat.extend_from_slice(wav_chunk);
-                dur_cat += silence_duration + dur;
+    /// Synthesize segments chunk by chunk, handing each chunk's audio to
+    /// `on_chunk` as soon as it is generated. Concatenating the `audio` of
+    /// every chunk gives the same waveform as `call_segments`. Chunks are
+    /// separated by the sentence or paragraph silence of `chunking`. Each
+    /// segment uses its own voice and rate, and its `pause_before` replaces
+    /// the normal silence before its first chunk. Returns the total duration
+    /// in seconds; an error from `on_chunk` aborts synthesis.
+    pub fn call_segments_streaming<F>(
+        &mut self,
+        segments: &[TextSegment],
+        styles: &VoiceStyles,
+        total_step: usize,
+        speed: f32,
+        chunking: &ChunkSettings,
+        mut on_chunk: F,
+    ) -> Result<f32>
+    where
+        F: FnMut(SpeechChunk) -> Result<()>,
+    {
+        let mut dur_cat: f32 = 0.0;
+        let mut index = 0;
+
+        for segment in segments {
+            let style = styles.get(segment.voice.as_deref())?;
+            let segment_speed = (speed * segment.rate).clamp(0.25, 4.0);
+
+            // A segment without text only contributes its pause
+            let chunks = chunk_text(&segment.text, chunking);
+
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                let silence = match (i, segment.pause_before) {
+                    (0, Some(pause)) => pause,
+                    _ if index == 0 => 0.0,
+                    _ if chunk.after_paragraph_break => chunking.paragraph_silence,
+                    _ => chunking.sentence_silence,
+                };
+                let chunk = chunk.text;
+                let mut audio = vec![0.0f32; (silence * self.sample_rate as f32) as usize];
+                dur_cat += silence;
+
+                let dur = if chunk.is_empty() {
+                    0.0
+                } else {
+                    let (wav, duration) = self._infer(std::slice::from_ref(&chunk), style, total_step, segment_speed)?;
+                    let dur = duration[0];
+                    let wav_len = (self.sample_rate as f32 * dur) as usize;
+                    audio.extend_from_slice(&wav[..wav_len.min(wav.len())]);
+                    dur
+                };
+
+                let start_time = dur_cat;
+                dur_cat += dur;
+
+                on_chunk(SpeechChunk {
+                    index,
+                    text: chunk,
+                    audio,
+                    duration: dur,
+                    start_time,
+                })?;
+                index += 1;
             }
         }
 
-        Ok((wav_cat, dur_cat))
+        Ok(dur_cat)
     }
 
     pub fn batch(
@@ -632,72 +1623,192 @@ impl TextToSpeech {
     }
 }
 
-/// Load voice style from JSON files
-pub fn load_voice_style(voice_style_paths: &[String], verbose: bool) -> Result<Style> {
-    let bsz = voice_style_paths.len();
-
-    // Read first file to get dimensions
-    let first_file = File::open(&voice_style_paths[0])
-        .context("Failed to open voice style file")?;
-    let first_reader = BufReader::new(first_file);
-    let first_data: VoiceStyleData = serde_json::from_reader(first_reader)?;
-
-    let ttl_dims = &first_data.style_ttl.dims;
-    let dp_dims = &first_data.style_dp.dims;
-
-    let ttl_dim1 = ttl_dims[1];
-    let ttl_dim2 = ttl_dims[2];
-    let dp_dim1 = dp_dims[1];
-    let dp_dim2 = dp_dims[2];
-
-    // Pre-allocate arrays with full batch size
-    let ttl_size = bsz * ttl_dim1 * ttl_dim2;
-    let dp_size = bsz * dp_dim1 * dp_dim2;
-    let mut ttl_flat = vec![0.0f32; ttl_size];
-    let mut dp_flat = vec![0.0f32; dp_size];
-
-    // Fill in the data
-    for (i, path) in voice_style_paths.iter().enumerate() {
-        let file = File::open(path).context("Failed to open voice style file")?;
-        let reader = BufReader::new(file);
-        let data: VoiceStyleData = serde_json::from_reader(reader)?;
-
-        // Flatten TTL data
-        let ttl_offset = i * ttl_dim1 * ttl_dim2;
-        let mut idx = 0;
-        for batch in &data.style_ttl.data {
-            for row in batch {
-                for &val in row {
-                    ttl_flat[ttl_offset + idx] = val;
-                    idx += 1;
-                }
+/// Read a single-voice style file in the format its extension names
+fn read_voice_style_file(path: &str) -> Result<Style> {
+    let style = match VoiceStyleFormat::of(path) {
+        VoiceStyleFormat::Json => {
+            let file = File::open(path).with_context(|| format!("Failed to open voice style file {}", path))?;
+            let data: VoiceStyleData = serde_json::from_reader(BufReader::new(file))
+                .with_context(|| format!("Failed to parse voice style file {}", path))?;
+            Style {
+                ttl: data.style_ttl.to_array("style_ttl")?,
+                dp: data.style_dp.to_array("style_dp")?,
             }
         }
-
-        // Flatten DP data
-        let dp_offset = i * dp_dim1 * dp_dim2;
-        idx = 0;
-        for batch in &data.style_dp.data {
-            for row in batch {
-                for &val in row {
-                    dp_flat[dp_offset + idx] = val;
-                    idx += 1;
-                }
+        VoiceStyleFormat::Safetensors => {
+            let bytes = std::fs::read(path).with_context(|| format!("Failed to open voice style file {}", path))?;
+            let tensors = SafeTensors::deserialize(&bytes)
+                .with_context(|| format!("Failed to parse voice style file {}", path))?;
+            Style {
+                ttl: safetensors_array(&tensors, "style_ttl")?,
+                dp: safetensors_array(&tensors, "style_dp")?,
             }
         }
+    };
+    if style.ttl.shape()[0] != 1 || style.dp.shape()[0] != 1 {
+        anyhow::bail!("Voice style file {} must hold a single voice", path);
+    }
+    Ok(style)
+}
+
+fn safetensors_array(tensors: &SafeTensors, name: &str) -> Result<Array3<f32>> {
+    let tensor = tensors.tensor(name).with_context(|| format!("Voice style has no tensor '{}'", name))?;
+    if tensor.dtype() != Dtype::F32 {
+        anyhow::bail!("{} has type {:?}, but the model takes float32", name, tensor.dtype());
+    }
+    let &[batch, rows, columns] = tensor.shape() else {
+        anyhow::bail!("{} must have 3 dims, got {:?}", name, tensor.shape());
+    };
+    let values = tensor
+        .data()
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+    Ok(Array3::from_shape_vec((batch, rows, columns), values)?)
+}
+
+/// Load voice styles as a batch, one voice per file. Files ending in
+/// `.safetensors` are read as safetensors, all others as JSON.
+pub fn load_voice_style(voice_style_paths: &[String], verbose: bool) -> Result<Style> {
+    let Some(first_path) = voice_style_paths.first() else {
+        anyhow::bail!("No voice style files given");
+    };
+    let styles = voice_style_paths
+        .iter()
+        .map(|path| read_voice_style_file(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let first = &styles[0];
+    for (style, path) in styles.iter().zip(voice_style_paths).skip(1) {
+        if style.ttl.shape()[1..] != first.ttl.shape()[1..] || style.dp.shape()[1..] != first.dp.shape()[1..] {
+            anyhow::bail!(
+                "Voice style {} has dims ttl {:?} / dp {:?}, but {} has ttl {:?} / dp {:?}",
+                path,
+                style.ttl.shape(),
+                style.dp.shape(),
+                first_path,
+                first.ttl.shape(),
+                first.dp.shape()
+            );
+        }
     }
 
-    let ttl_style = Array3::from_shape_vec((bsz, ttl_dim1, ttl_dim2), ttl_flat)?;
-    let dp_style = Array3::from_shape_vec((bsz, dp_dim1, dp_dim2), dp_flat)?;
+    let ttl_views: Vec<_> = styles.iter().map(|style| style.ttl.view()).collect();
+    let dp_views: Vec<_> = styles.iter().map(|style| style.dp.view()).collect();
+    let style = Style {
+        ttl: ndarray::concatenate(Axis(0), &ttl_views)?,
+        dp: ndarray::concatenate(Axis(0), &dp_views)?,
+    };
 
     if verbose {
-        println!("Loaded {} voice styles\n", bsz);
+        println!("Loaded {} voice styles\n", voice_style_paths.len());
+    }
+
+    Ok(style)
+}
+
+/// Scale voice mix weights to sum to 1. Weights must be finite and
+/// non-negative, and at least one must be positive.
+pub fn normalize_mix_weights(weights: &[f32]) -> Result<Vec<f32>> {
+    if weights.is_empty() {
+        anyhow::bail!("A voice mix needs at least one voice");
+    }
+    if let Some(weight) = weights.iter().find(|weight| !weight.is_finite() || **weight < 0.0) {
+        anyhow::bail!("Voice mix weights must be non-negative numbers, got {}", weight);
+    }
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 {
+        anyhow::bail!("Voice mix weights must not all be zero");
+    }
+    Ok(weights.iter().map(|weight| weight / total).collect())
+}
+
+/// Mix single-voice styles by linear interpolation of their `ttl` and `dp`
+/// tensors. All styles must have the same shapes; weights are normalized
+/// with [`normalize_mix_weights`].
+pub fn blend_voice_styles(components: &[(Style, f32)]) -> Result<Style> {
+    let weights: Vec<f32> = components.iter().map(|(_, weight)| *weight).collect();
+    let weights = normalize_mix_weights(&weights)?;
+
+    let first = &components[0].0;
+    for (index, (style, _)) in components.iter().enumerate() {
+        if style.ttl.shape()[0] != 1 || style.dp.shape()[0] != 1 {
+            anyhow::bail!("Voice mix component {} holds more than one voice", index + 1);
+        }
+        if style.ttl.shape() != first.ttl.shape() || style.dp.shape() != first.dp.shape() {
+            anyhow::bail!(
+                "Voice mix component {} has dims ttl {:?} / dp {:?}, but the first has ttl {:?} / dp {:?}",
+                index + 1,
+                style.ttl.shape(),
+                style.dp.shape(),
+                first.ttl.shape(),
+                first.dp.shape()
+            );
+        }
+    }
+
+    let mut ttl = Array3::<f32>::zeros(first.ttl.raw_dim());
+    let mut dp = Array3::<f32>::zeros(first.dp.raw_dim());
+    for ((style, _), weight) in components.iter().zip(weights) {
+        ttl.scaled_add(weight, &style.ttl);
+        dp.scaled_add(weight, &style.dp);
     }
 
-    Ok(Style {
-        ttl: ttl_style,
-        dp: dp_style,
-    })
+    Ok(Style { ttl, dp })
+}
+
+/// Write a single-voice style file in the format its extension names,
+/// replacing `path` atomically
+pub fn save_voice_style<P: AsRef<Path>>(style: &Style, path: P) -> Result<()> {
+    if style.ttl.shape()[0] != 1 || style.dp.shape()[0] != 1 {
+        anyhow::bail!("Only a single voice can be saved as a voice style file");
+    }
+    let path = path.as_ref();
+    if VoiceStyleFormat::of(path) == VoiceStyleFormat::Safetensors {
+        return save_safetensors_style(style, path);
+    }
+
+    let component = |array: &Array3<f32>| StyleComponent {
+        data: array
+            .outer_iter()
+            .map(|matrix| matrix.outer_iter().map(|row| row.to_vec()).collect())
+            .collect(),
+        dims: array.shape().to_vec(),
+        dtype: "float32".to_string(),
+    };
+    VoiceStyleData {
+        style_ttl: component(&style.ttl),
+        style_dp: component(&style.dp),
+    }
+    .save(path)
+}
+
+fn save_safetensors_style(style: &Style, path: &Path) -> Result<()> {
+    let bytes = |array: &Array3<f32>| array.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<u8>>();
+    let (ttl, dp) = (bytes(&style.ttl), bytes(&style.dp));
+    let tensors = [
+        ("style_ttl", TensorView::new(Dtype::F32, style.ttl.shape().to_vec(), &ttl)?),
+        ("style_dp", TensorView::new(Dtype::F32, style.dp.shape().to_vec(), &dp)?),
+    ];
+    let serialized = safetensors::serialize(tensors, &None)?;
+
+    let temp_path = path.with_extension("safetensors.tmp");
+    std::fs::write(&temp_path, serialized)
+        .with_context(|| format!("Failed to write voice style file {}", temp_path.display()))?;
+    std::fs::rename(&temp_path, path)
+        .with_context(|| format!("Failed to replace voice style file {}", path.display()))
+}
+
+/// Rewrite a voice style file in `format`, next to the original with that
+/// format's extension. Returns the new file's path.
+pub fn convert_voice_style(path: &str, format: VoiceStyleFormat) -> Result<String> {
+    let output = Path::new(path).with_extension(format.extension());
+    if output == Path::new(path) {
+        anyhow::bail!("{} is already a {} voice style file", path, format.extension());
+    }
+    let style = load_voice_style(&[path.to_string()], false)?;
+    save_voice_style(&style, &output)?;
+    Ok(output.to_string_lossy().into_owned())
 }
 
 /// Load TTS components
@@ -735,3 +1846,100 @@ pub fn load_text_to_speech(onnx_dir: &str, use_gpu: bool) -> Result<TextToSpeech
         vocoder_ort,
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drain_complete_sentences_bounds_unpunctuated_input() {
+        let segmenter = Segmenter::new(DEFAULT_LANGUAGE, Vec::new());
+        let mut buffer = String::new();
+        let mut sentences = Vec::new();
+        for _ in 0..100 {
+            buffer.push_str("word ");
+            sentences.extend(drain_complete_sentences(&mut buffer, &segmenter, 50));
+            assert!(char_len(&buffer) <= 51, "buffer grew to {} chars", char_len(&buffer));
+        }
+        assert!(sentences.iter().all(|sentence| char_len(sentence) <= 50));
+        let spoken: String = sentences.iter().map(|sentence| format!("{} ", sentence)).collect::<String>() + &buffer;
+        assert_eq!(spoken.split_whitespace().count(), 100);
+    }
+
+    #[test]
+    fn drain_complete_sentences_keeps_partial_sentence() {
+        let segmenter = Segmenter::new(DEFAULT_LANGUAGE, Vec::new());
+        let mut buffer = "Hello there. How are".to_string();
+        assert_eq!(drain_complete_sentences(&mut buffer, &segmenter, 300), vec!["Hello there."]);
+        assert_eq!(buffer.trim(), "How are");
+    }
+
+    fn chunk_settings(language: &str, strategy: SplitStrategy, max_chunk_chars: usize, min_chunk_chars: usize) -> ChunkSettings {
+        ChunkSettings {
+            max_chunk_chars,
+            min_chunk_chars,
+            strategy,
+            segmenter: Arc::new(Segmenter::new(language, Vec::new())),
+            ..ChunkSettings::default()
+        }
+    }
+
+    fn chunk_texts(text: &str, settings: &ChunkSettings) -> Vec<String> {
+        chunk_text(text, settings).into_iter().map(|chunk| chunk.text).collect()
+    }
+
+    #[test]
+    fn chunk_text_limits_cjk_text_by_characters() {
+        // 7 + 9 + 7 characters, but three bytes each
+        let text = "今天天气很好。我们去公园散步吧！你觉得怎么样？";
+        let settings = chunk_settings("zh", SplitStrategy::Paragraph, 16, 0);
+        assert_eq!(chunk_texts(text, &settings), ["今天天气很好。我们去公园散步吧！", "你觉得怎么样？"]);
+
+        let settings = chunk_settings("zh", SplitStrategy::Paragraph, 23, 0);
+        assert_eq!(chunk_texts(text, &settings), [text]);
+    }
+
+    #[test]
+    fn chunk_text_splits_cjk_sentences_then_clauses() {
+        let settings = chunk_settings("zh", SplitStrategy::Sentence, 300, 0);
+        assert_eq!(chunk_texts("你好。真的吗？太好了！", &settings), ["你好。", "真的吗？", "太好了！"]);
+
+        // 8 + 7 + 7 characters, split at the full-width commas
+        let settings = chunk_settings("zh", SplitStrategy::Paragraph, 10, 0);
+        assert_eq!(
+            chunk_texts("我们今天去公园，然后去图书馆，最后回家吃饭。", &settings),
+            ["我们今天去公园，", "然后去图书馆，", "最后回家吃饭。"]
+        );
+    }
+
+    #[test]
+    fn chunk_text_never_splits_grapheme_clusters() {
+        // Seven characters joined by zero-width joiners into one emoji
+        let family = "👨\u{200d}👩\u{200d}👧\u{200d}👦";
+        let settings = chunk_settings(DEFAULT_LANGUAGE, SplitStrategy::Paragraph, 10, 0);
+        assert_eq!(chunk_texts(&family.repeat(3), &settings), [family, family, family]);
+
+        // A cluster longer than the limit stays whole
+        let settings = chunk_settings(DEFAULT_LANGUAGE, SplitStrategy::Paragraph, 5, 0);
+        assert_eq!(chunk_texts(family, &settings), [family]);
+
+        // Eight e's with combining acute accents, two characters each
+        let accented = "e\u{301}".repeat(8);
+        let settings = chunk_settings(DEFAULT_LANGUAGE, SplitStrategy::Paragraph, 11, 0);
+        let chunks = chunk_texts(&accented, &settings);
+        assert_eq!(chunks, ["e\u{301}".repeat(5), "e\u{301}".repeat(3)]);
+        assert!(chunks.iter().all(|chunk| char_len(chunk) <= 11));
+    }
+
+    #[test]
+    fn merge_short_chunks_adds_no_space_after_full_width_punctuation() {
+        let settings = chunk_settings("zh", SplitStrategy::Sentence, 20, 5);
+        assert_eq!(chunk_texts("你好。谢谢！再见。", &settings), ["你好。谢谢！再见。"]);
+
+        let settings = chunk_settings(DEFAULT_LANGUAGE, SplitStrategy::Sentence, 20, 5);
+        assert_eq!(chunk_texts("Hi. Ok. Fine.", &settings), ["Hi. Ok.", "Fine."]);
+
+        let chunks = vec!["好的，".to_string(), "走吧。".to_string(), "OK".to_string()];
+        assert_eq!(merge_short_chunks(chunks, 4, 20), ["好的，走吧。OK"]);
+    }
+}
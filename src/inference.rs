@@ -0,0 +1,82 @@
+// Inference Module - Dedicated threads for model inference
+//
+// Synthesis is CPU-bound and takes from milliseconds to many seconds, so it
+// runs on a fixed set of worker threads instead of the async runtime's.
+// Handlers send jobs over a channel and await their results, which keeps the
+// runtime free for I/O (including `/health`) however many requests are
+// synthesizing. Jobs start in submission order as workers become free.
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::sync::oneshot;
+use tracing::{debug, error};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Worker threads that run inference jobs
+pub struct InferenceWorkers {
+    sender: mpsc::Sender<Job>,
+    workers: usize,
+}
+
+impl InferenceWorkers {
+    /// Start `workers` threads
+    pub fn new(workers: usize) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for index in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("inference-{}", index))
+                .spawn(move || work(index, &receiver))
+                .context("Failed to start inference worker")?;
+        }
+        Ok(InferenceWorkers { sender, workers })
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Run `job` on a worker thread and wait for its result
+    pub async fn run<T, F>(&self, job: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.spawn(move || {
+            let _ = result_tx.send(job());
+        })?;
+        result_rx.await.map_err(|_| anyhow!("Inference job panicked"))
+    }
+
+    /// Run `job` on a worker thread without waiting for it
+    pub fn spawn<F>(&self, job: F) -> Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.sender
+            .send(Box::new(job))
+            .map_err(|_| anyhow!("Inference workers have stopped"))
+    }
+}
+
+fn work(index: usize, receiver: &Mutex<mpsc::Receiver<Job>>) {
+    loop {
+        // The lock is released before the job runs, so idle workers take
+        // turns waiting for the next job
+        let job = match receiver.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => break,
+        };
+        // A panicking job drops its result sender, which fails its request;
+        // the worker carries on
+        if catch_unwind(AssertUnwindSafe(job)).is_err() {
+            error!("Inference job panicked on worker {}", index);
+        }
+    }
+    debug!("Inference worker {} stopped", index);
+}
@@ -0,0 +1,213 @@
+// Lexicon Module - User pronunciation overrides applied before synthesis
+//
+// A lexicon maps a word (or a regular expression) to a respelling that the
+// model pronounces correctly, e.g. "Nginx" -> "engine x". It is stored as a
+// JSON object whose values are either the respelling or an entry with options:
+//
+//   { "Nginx": "engine x", "k8s": { "replacement": "kubernetes", "case_sensitive": true } }
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use regex::{Captures, NoExpand, Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// How one pattern is rewritten
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "EntrySpec")]
+pub struct LexiconEntry {
+    /// Text spoken in place of the match. Regex entries may use `$1` etc.
+    pub replacement: String,
+    /// Treat the pattern as a regular expression instead of literal text
+    pub regex: bool,
+    /// Only match the pattern as a whole word
+    pub whole_word: bool,
+    /// Match case exactly instead of ignoring it
+    pub case_sensitive: bool,
+}
+
+/// An entry as written in JSON: a bare respelling or the full options
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EntrySpec {
+    Replacement(String),
+    Entry {
+        replacement: String,
+        #[serde(default)]
+        regex: bool,
+        #[serde(default = "default_whole_word")]
+        whole_word: bool,
+        #[serde(default)]
+        case_sensitive: bool,
+    },
+}
+
+fn default_whole_word() -> bool { true }
+
+impl From<EntrySpec> for LexiconEntry {
+    fn from(spec: EntrySpec) -> Self {
+        match spec {
+            EntrySpec::Replacement(replacement) => LexiconEntry {
+                replacement,
+                regex: false,
+                whole_word: default_whole_word(),
+                case_sensitive: false,
+            },
+            EntrySpec::Entry { replacement, regex, whole_word, case_sensitive } => LexiconEntry {
+                replacement,
+                regex,
+                whole_word,
+                case_sensitive,
+            },
+        }
+    }
+}
+
+/// Entries keyed by pattern
+pub type LexiconEntries = BTreeMap<String, LexiconEntry>;
+
+struct Rule {
+    regex: Regex,
+    replacement: String,
+    expand: bool,
+    /// The regex ends in a `tail` group that checks the character after the
+    /// match and must be kept
+    tail: bool,
+}
+
+/// A compiled set of lexicon entries
+#[derive(Default)]
+pub struct Lexicon {
+    entries: LexiconEntries,
+    rules: Vec<Rule>,
+}
+
+impl Lexicon {
+    /// Compile `entries`, failing on the first invalid pattern
+    pub fn new(entries: LexiconEntries) -> Result<Self> {
+        let mut patterns: Vec<(&String, &LexiconEntry)> = entries.iter().collect();
+        // Longer patterns first, so "New York City" wins over "New York"
+        patterns.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.len()));
+
+        let rules = patterns
+            .into_iter()
+            .map(|(pattern, entry)| compile_rule(pattern, entry))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Lexicon { entries, rules })
+    }
+
+    /// Load a lexicon file; a file that does not exist yet is an empty lexicon
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open lexicon file {}", path.display()))?;
+        let entries: LexiconEntries = serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("Failed to parse lexicon file {}", path.display()))?;
+        Self::new(entries)
+    }
+
+    /// Write the entries to `path`, replacing the file atomically
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, serde_json::to_vec_pretty(&self.entries)?)
+            .with_context(|| format!("Failed to write lexicon file {}", temp_path.display()))?;
+        std::fs::rename(&temp_path, path)
+            .with_context(|| format!("Failed to replace lexicon file {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &LexiconEntries {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Rewrite every match of every entry in `text`
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for rule in &self.rules {
+            let replaced = if rule.expand {
+                rule.regex.replace_all(&text, rule.replacement.as_str())
+            } else if rule.tail {
+                rule.regex
+                    .replace_all(&text, |caps: &Captures| format!("{}{}", rule.replacement, &caps["tail"]))
+            } else {
+                rule.regex.replace_all(&text, NoExpand(&rule.replacement))
+            };
+            text = replaced.into_owned();
+        }
+        text
+    }
+}
+
+fn compile_rule(pattern: &str, entry: &LexiconEntry) -> Result<Rule> {
+    if pattern.trim().is_empty() {
+        return Err(anyhow!("Lexicon patterns cannot be empty"));
+    }
+
+    let mut source = if entry.regex { format!("(?:{})", pattern) } else { regex::escape(pattern) };
+    let mut tail = false;
+    if entry.whole_word {
+        // Word boundaries only make sense next to word characters. A literal
+        // like "C++" instead must not be followed by a word character; the
+        // regex crate has no lookahead, so that character is captured and
+        // put back by `apply`.
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if entry.regex || is_word(pattern.chars().next()) {
+            source = format!(r"\b{}", source);
+        }
+        if entry.regex || is_word(pattern.chars().next_back()) {
+            source = format!(r"{}\b", source);
+        } else {
+            source = format!(r"{}(?P<tail>$|\W)", source);
+            tail = true;
+        }
+    }
+
+    let regex = RegexBuilder::new(&source)
+        .case_insensitive(!entry.case_sensitive)
+        .build()
+        .map_err(|e| anyhow!("Invalid lexicon pattern '{}': {}", pattern, e))?;
+
+    Ok(Rule {
+        regex,
+        replacement: entry.replacement.clone(),
+        expand: entry.regex,
+        tail,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lexicon(entries: &[(&str, &str)]) -> Lexicon {
+        let entries = entries
+            .iter()
+            .map(|&(pattern, replacement)| (pattern.to_string(), LexiconEntry::from(EntrySpec::Replacement(replacement.to_string()))))
+            .collect();
+        Lexicon::new(entries).unwrap()
+    }
+
+    #[test]
+    fn whole_word_literals_ending_in_symbols() {
+        let lexicon = lexicon(&[("C++", "see plus plus")]);
+        assert_eq!(lexicon.apply("I write C++."), "I write see plus plus.");
+        assert_eq!(lexicon.apply("c++ and C++"), "see plus plus and see plus plus");
+        assert_eq!(lexicon.apply("c++x and xc++"), "c++x and xc++");
+    }
+
+    #[test]
+    fn whole_word_literals() {
+        let lexicon = lexicon(&[("Nginx", "engine x"), ("New York", "new york"), ("New York City", "the big apple")]);
+        assert_eq!(lexicon.apply("nginx in New York City"), "engine x in the big apple");
+        assert_eq!(lexicon.apply("Nginxes in New York"), "Nginxes in new york");
+    }
+}
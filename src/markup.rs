@@ -0,0 +1,321 @@
+// Markup Module - Turns Markdown and HTML into speakable text
+//
+// Headings become their own paragraph, list items, table rows and definition
+// terms end a sentence, and paragraphs stay separated by a blank line, so that
+// `chunk_text` and the model pause where a reader would. Link and image
+// targets are dropped (only the link text is kept) and code blocks are
+// skipped or replaced with a fixed phrase.
+use std::sync::LazyLock;
+
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+/// How request input is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputFormat {
+    /// Plain text, or SSML when the input starts with `<speak>`
+    #[default]
+    Text,
+    Markdown,
+    Html,
+}
+
+impl InputFormat {
+    pub const SUPPORTED: &'static [&'static str] = &["text", "markdown", "html"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "text" | "plain" => Some(Self::Text),
+            "markdown" | "md" => Some(Self::Markdown),
+            "html" => Some(Self::Html),
+            _ => None,
+        }
+    }
+}
+
+/// Convert `input` to speakable text. Code blocks are replaced with
+/// `code_block_phrase`, or dropped when it is `None` or empty.
+pub fn to_speakable_text(input: &str, format: InputFormat, code_block_phrase: Option<&str>) -> String {
+    let mut out = SpeechText::new(code_block_phrase);
+    match format {
+        InputFormat::Text => return input.to_string(),
+        InputFormat::Markdown => push_markdown(input, &mut out),
+        InputFormat::Html => push_html(input, &mut out),
+    }
+    out.finish()
+}
+
+fn push_markdown(input: &str, out: &mut SpeechText) {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut list_depth = 0usize;
+    // Images and code blocks are not spoken; nothing inside them is either
+    let mut skip_depth = 0usize;
+    let mut html = String::new();
+
+    for event in Parser::new_ext(input, options) {
+        if skip_depth > 0 {
+            match event {
+                Event::Start(Tag::Image { .. }) | Event::Start(Tag::CodeBlock(_)) => skip_depth += 1,
+                Event::End(TagEnd::Image) | Event::End(TagEnd::CodeBlock) => skip_depth -= 1,
+                _ => {}
+            }
+            continue;
+        }
+
+        match event {
+            Event::Start(Tag::Heading { .. }) => out.paragraph_break(),
+            Event::End(TagEnd::Heading(_)) => {
+                out.sentence_break();
+                out.paragraph_break();
+            }
+            Event::Start(Tag::Paragraph) => {}
+            Event::End(TagEnd::Paragraph) if list_depth > 0 => out.sentence_break(),
+            Event::End(TagEnd::Paragraph) => out.paragraph_break(),
+            Event::Start(Tag::List(_)) => {
+                if list_depth == 0 {
+                    out.paragraph_break();
+                } else {
+                    out.sentence_break();
+                }
+                list_depth += 1;
+            }
+            Event::End(TagEnd::List(_)) => {
+                list_depth -= 1;
+                if list_depth == 0 {
+                    out.sentence_break();
+                    out.paragraph_break();
+                }
+            }
+            Event::Start(Tag::Item) => out.sentence_break(),
+            Event::End(TagEnd::Item) => out.sentence_break(),
+            Event::Start(Tag::CodeBlock(_)) => {
+                out.code_block();
+                skip_depth = 1;
+            }
+            Event::Start(Tag::Image { .. }) => skip_depth = 1,
+            Event::Start(Tag::BlockQuote(_)) | Event::End(TagEnd::BlockQuote(_)) => out.paragraph_break(),
+            Event::Start(Tag::Table(_)) | Event::End(TagEnd::Table) => out.paragraph_break(),
+            Event::End(TagEnd::TableHead) | Event::End(TagEnd::TableRow) => out.sentence_break(),
+            Event::End(TagEnd::TableCell) => out.cell_break(),
+            Event::Start(Tag::DefinitionListTitle) | Event::End(TagEnd::DefinitionListTitle) => out.sentence_break(),
+            Event::End(TagEnd::DefinitionListDefinition) => out.sentence_break(),
+            Event::Start(Tag::HtmlBlock) => html.clear(),
+            Event::Html(text) => html.push_str(&text),
+            Event::End(TagEnd::HtmlBlock) => {
+                out.paragraph_break();
+                push_html(&html, out);
+                out.paragraph_break();
+            }
+            Event::Text(text) | Event::Code(text) => out.text(&text),
+            Event::SoftBreak | Event::HardBreak => out.text(" "),
+            Event::Rule => out.paragraph_break(),
+            // Inline tags, math, footnote references and task list markers
+            // carry nothing to say
+            _ => {}
+        }
+    }
+}
+
+static HTML_SKIPPED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<!--.*?-->|<(script|style|head|template|noscript|svg)\b[^>]*>.*?</(?:script|style|head|template|noscript|svg)\s*>")
+        .unwrap()
+});
+static HTML_TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(/?)([a-zA-Z][a-zA-Z0-9-]*)\b[^>]*>|<![^>]*>").unwrap());
+static HTML_ENTITY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"&(#[0-9]{1,7}|#[xX][0-9a-fA-F]{1,6}|[a-zA-Z][a-zA-Z0-9]{1,31});").unwrap());
+
+fn push_html(input: &str, out: &mut SpeechText) {
+    let input = HTML_SKIPPED.replace_all(input, " ");
+    let mut position = 0;
+    // Inside <pre>: text is skipped
+    let mut pre_depth = 0usize;
+
+    for tag in HTML_TAG.captures_iter(&input) {
+        let whole = tag.get(0).unwrap();
+        if pre_depth == 0 {
+            out.text(&decode_entities(&input[position..whole.start()]));
+        }
+        position = whole.end();
+
+        let Some(name) = tag.get(2) else {
+            // <!DOCTYPE> and the like
+            continue;
+        };
+        let closing = !tag[1].is_empty();
+        let name = name.as_str().to_ascii_lowercase();
+
+        if name == "pre" {
+            if closing {
+                pre_depth = pre_depth.saturating_sub(1);
+            } else {
+                if pre_depth == 0 {
+                    out.code_block();
+                }
+                pre_depth += 1;
+            }
+            continue;
+        }
+        if pre_depth > 0 {
+            continue;
+        }
+
+        match name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if closing {
+                    out.sentence_break();
+                }
+                out.paragraph_break();
+            }
+            "p" | "div" | "section" | "article" | "aside" | "header" | "footer" | "main" | "nav" | "blockquote"
+            | "figure" | "figcaption" | "table" | "ul" | "ol" | "dl" | "hr" | "address" | "details" | "summary" => {
+                if closing && matches!(name.as_str(), "ul" | "ol" | "dl" | "table" | "summary") {
+                    out.sentence_break();
+                }
+                out.paragraph_break();
+            }
+            "li" | "dt" | "dd" | "tr" | "caption" => out.sentence_break(),
+            "td" | "th" if closing => out.cell_break(),
+            "br" => out.text(" "),
+            _ => {}
+        }
+    }
+
+    if pre_depth == 0 {
+        out.text(&decode_entities(&input[position..]));
+    }
+}
+
+/// Replace character references with the characters they stand for
+fn decode_entities(text: &str) -> String {
+    HTML_ENTITY
+        .replace_all(text, |caps: &Captures| {
+            let name = &caps[1];
+            let decoded = if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(decimal) = name.strip_prefix('#') {
+                decimal.parse().ok().and_then(char::from_u32)
+            } else {
+                named_entity(name)
+            };
+            match decoded {
+                Some(c) => c.to_string(),
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "bull" => '•',
+        "middot" => '·',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "deg" => '°',
+        "cent" => '¢',
+        "pound" => '£',
+        "euro" => '€',
+        "yen" => '¥',
+        "times" => '×',
+        "divide" => '÷',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// Speakable text under construction
+struct SpeechText<'a> {
+    text: String,
+    code_block_phrase: Option<&'a str>,
+}
+
+impl<'a> SpeechText<'a> {
+    fn new(code_block_phrase: Option<&'a str>) -> Self {
+        SpeechText {
+            text: String::new(),
+            code_block_phrase: code_block_phrase.map(str::trim).filter(|phrase| !phrase.is_empty()),
+        }
+    }
+
+    /// Append text, collapsing runs of whitespace into one space
+    fn text(&mut self, text: &str) {
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !self.text.is_empty() && !self.text.ends_with(char::is_whitespace) {
+                    self.text.push(' ');
+                }
+            } else {
+                self.text.push(c);
+            }
+        }
+    }
+
+    /// End the current line as a sentence, adding a full stop when it has
+    /// no terminal punctuation
+    fn sentence_break(&mut self) {
+        self.trim_spaces();
+        if self.text.is_empty() || self.text.ends_with('\n') {
+            return;
+        }
+        let trimmed = self.text.trim_end_matches([',', ';', ':']).len();
+        self.text.truncate(trimmed);
+        if !self.text.ends_with(['.', '!', '?', '…', '。', '！', '？']) {
+            self.text.push('.');
+        }
+        self.text.push('\n');
+    }
+
+    fn paragraph_break(&mut self) {
+        self.trim_spaces();
+        if !self.text.is_empty() && !self.text.ends_with("\n\n") {
+            let trimmed = self.text.trim_end().len();
+            self.text.truncate(trimmed);
+            self.text.push_str("\n\n");
+        }
+    }
+
+    /// Separate table cells of one row
+    fn cell_break(&mut self) {
+        self.trim_spaces();
+        if !self.text.is_empty() && !self.text.ends_with(['\n', ',']) {
+            self.text.push_str(", ");
+        }
+    }
+
+    fn code_block(&mut self) {
+        self.paragraph_break();
+        if let Some(phrase) = self.code_block_phrase {
+            self.text(phrase);
+            self.sentence_break();
+            self.paragraph_break();
+        }
+    }
+
+    fn trim_spaces(&mut self) {
+        let trimmed = self.text.trim_end_matches([' ', '\t']).len();
+        self.text.truncate(trimmed);
+    }
+
+    fn finish(self) -> String {
+        self.text.trim().to_string()
+    }
+}
@@ -0,0 +1,497 @@
+// Text Normalization Module - Spells out numbers and symbols before synthesis
+//
+// The model reads characters, so "$3.50", "12/03/2025" or "10km" have to be
+// expanded into words first. Normalizers are looked up per language; text in
+// a language without one is passed through unchanged.
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+
+/// Language assumed when the request does not name one
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// Expands numbers, dates and symbols in one language into words
+pub trait TextNormalizer: Send + Sync {
+    fn normalize(&self, text: &str) -> String;
+}
+
+static ENGLISH: LazyLock<EnglishNormalizer> = LazyLock::new(EnglishNormalizer::new);
+
+/// Normalizer for a language code such as "en" or "en-US"
+pub fn normalizer_for(language: &str) -> Option<&'static dyn TextNormalizer> {
+    let primary = language.split(['-', '_']).next().unwrap_or_default();
+    match primary.to_ascii_lowercase().as_str() {
+        "en" => Some(&*ENGLISH),
+        _ => None,
+    }
+}
+
+/// Normalize `text` for `language`, leaving it as is if no normalizer exists
+pub fn normalize_text(text: &str, language: &str) -> String {
+    match normalizer_for(language) {
+        Some(normalizer) => normalizer.normalize(text),
+        None => text.to_string(),
+    }
+}
+
+// English
+
+/// Digits with optional thousands separators, e.g. "12" or "1,234,567"
+const INTEGER: &str = r"\d{1,3}(?:,\d{3})+|\d+";
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+/// Units spelled out after a number: (symbol, singular, plural)
+const UNITS: &[(&str, &str, &str)] = &[
+    ("km/h", "kilometer per hour", "kilometers per hour"),
+    ("kph", "kilometer per hour", "kilometers per hour"),
+    ("mph", "mile per hour", "miles per hour"),
+    ("kWh", "kilowatt hour", "kilowatt hours"),
+    ("kW", "kilowatt", "kilowatts"),
+    ("km", "kilometer", "kilometers"),
+    ("cm", "centimeter", "centimeters"),
+    ("mm", "millimeter", "millimeters"),
+    ("m", "meter", "meters"),
+    ("mi", "mile", "miles"),
+    ("ft", "foot", "feet"),
+    ("yd", "yard", "yards"),
+    ("kg", "kilogram", "kilograms"),
+    ("mg", "milligram", "milligrams"),
+    ("g", "gram", "grams"),
+    ("lbs", "pound", "pounds"),
+    ("lb", "pound", "pounds"),
+    ("oz", "ounce", "ounces"),
+    ("ml", "milliliter", "milliliters"),
+    ("mL", "milliliter", "milliliters"),
+    ("L", "liter", "liters"),
+    ("°C", "degree Celsius", "degrees Celsius"),
+    ("°F", "degree Fahrenheit", "degrees Fahrenheit"),
+    ("ms", "millisecond", "milliseconds"),
+    ("min", "minute", "minutes"),
+    ("hrs", "hour", "hours"),
+    ("hr", "hour", "hours"),
+    ("h", "hour", "hours"),
+    ("GHz", "gigahertz", "gigahertz"),
+    ("MHz", "megahertz", "megahertz"),
+    ("kHz", "kilohertz", "kilohertz"),
+    ("Hz", "hertz", "hertz"),
+    ("TB", "terabyte", "terabytes"),
+    ("GB", "gigabyte", "gigabytes"),
+    ("MB", "megabyte", "megabytes"),
+    ("KB", "kilobyte", "kilobytes"),
+    ("kB", "kilobyte", "kilobytes"),
+];
+
+/// English normalizer. Patterns are applied most specific first, so that
+/// e.g. the digits of a date are not read as plain numbers.
+pub struct EnglishNormalizer {
+    currency: Regex,
+    iso_date: Regex,
+    numeric_date: Regex,
+    month_date: Regex,
+    clock_time: Regex,
+    hour_time: Regex,
+    minus: Regex,
+    percent: Regex,
+    unit: Regex,
+    ordinal: Regex,
+    number: Regex,
+}
+
+impl EnglishNormalizer {
+    pub fn new() -> Self {
+        let months = MONTHS
+            .iter()
+            .flat_map(|month| [month.to_string(), format!("{}\\.?", &month[..3])])
+            .chain(["Sept\\.?".to_string()])
+            .collect::<Vec<_>>()
+            .join("|");
+
+        // Longest symbols first so "km/h" wins over "km" and "m"
+        let mut units: Vec<&str> = UNITS.iter().map(|(symbol, _, _)| *symbol).collect();
+        units.sort_by_key(|symbol| std::cmp::Reverse(symbol.len()));
+        let units = units.iter().map(|symbol| regex::escape(symbol)).collect::<Vec<_>>().join("|");
+
+        let pattern = |p: String| Regex::new(&p).unwrap();
+        EnglishNormalizer {
+            currency: pattern(format!(
+                r"([$£€¥₹])\s?({INTEGER})(?:\.(\d+))?(?:\s?(thousand|million|billion|trillion|k|K|m|M|bn|B)\b)?"
+            )),
+            iso_date: pattern(r"\b(\d{4})-(\d{2})-(\d{2})\b".to_string()),
+            numeric_date: pattern(r"\b(\d{1,2})/(\d{1,2})/(\d{4})\b".to_string()),
+            month_date: pattern(format!(r"\b({months})\s+(\d{{1,2}})(?:st|nd|rd|th)?\b(?:,?\s+(\d{{4}})\b)?")),
+            clock_time: pattern(r"\b(\d{1,2}):(\d{2})(?::(\d{2}))?(?:\s*([AaPp])\.?[Mm]\b|\b)".to_string()),
+            hour_time: pattern(r"\b(\d{1,2})\s*([AaPp])\.?[Mm]\b".to_string()),
+            minus: pattern(r"(^|[\s(])-(\d)".to_string()),
+            percent: pattern(format!(r"\b({INTEGER})(?:\.(\d+))?\s?%")),
+            unit: pattern(format!(r"\b({INTEGER})(?:\.(\d+))?\s?({units})\b")),
+            ordinal: pattern(r"\b(\d+)(st|nd|rd|th)\b".to_string()),
+            // Digit groups joined by '.', '/', ':' or '-' are matched as a
+            // whole so that they are read together or not at all
+            number: pattern(format!(r"\b({INTEGER})((?:[./:-]\d+)*)(s)?\b")),
+        }
+    }
+}
+
+impl Default for EnglishNormalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextNormalizer for EnglishNormalizer {
+    fn normalize(&self, text: &str) -> String {
+        let text = self.currency.replace_all(text, |caps: &Captures| {
+            let (singular, plural, minor_singular, minor_plural) = currency_names(&caps[1]);
+            let whole = &caps[2];
+            let fraction = caps.get(3).map(|m| m.as_str());
+
+            if let Some(scale) = caps.get(4) {
+                let scale = match scale.as_str() {
+                    "k" | "K" => "thousand",
+                    "m" | "M" => "million",
+                    "bn" | "B" => "billion",
+                    other => other,
+                };
+                return format!("{} {} {}", decimal_words(whole, fraction), scale, plural);
+            }
+
+            let units = parse_integer(whole);
+            let minor = match fraction {
+                None => Some(0),
+                Some(_) if minor_plural.is_empty() => None,
+                Some(f) if f.len() == 2 => f.parse::<u64>().ok(),
+                Some(f) if f.len() == 1 => f.parse::<u64>().ok().map(|d| d * 10),
+                Some(_) => None,
+            };
+            let minor_name = |cents: u64| if cents == 1 { minor_singular } else { minor_plural };
+            match (units, minor) {
+                // Amounts like "$1.005" have no minor unit reading
+                (_, None) => format!("{} {}", decimal_words(whole, fraction), plural),
+                (Some(0), Some(cents)) if cents > 0 => format!("{} {}", cardinal(cents), minor_name(cents)),
+                (units, Some(cents)) => {
+                    let name = if units == Some(1) { singular } else { plural };
+                    let mut spoken = format!("{} {}", integer_words(whole), name);
+                    if cents > 0 {
+                        spoken.push_str(&format!(" and {} {}", cardinal(cents), minor_name(cents)));
+                    }
+                    spoken
+                }
+            }
+        });
+
+        let text = self.iso_date.replace_all(&text, |caps: &Captures| {
+            spoken_date(&caps[1], &caps[2], &caps[3]).unwrap_or_else(|| caps[0].to_string())
+        });
+
+        let text = self.numeric_date.replace_all(&text, |caps: &Captures| {
+            // Month first as in US usage, unless that cannot be a valid date
+            let (first, second) = (&caps[1], &caps[2]);
+            spoken_date(&caps[3], first, second)
+                .or_else(|| spoken_date(&caps[3], second, first))
+                .unwrap_or_else(|| caps[0].to_string())
+        });
+
+        let text = self.month_date.replace_all(&text, |caps: &Captures| {
+            let month = caps[1].trim_end_matches('.');
+            let month = MONTHS.iter().find(|m| m.starts_with(&month[..3])).copied().unwrap_or(month);
+            let day: u64 = caps[2].parse().unwrap_or(0);
+            if !(1..=31).contains(&day) {
+                return caps[0].to_string();
+            }
+            match caps.get(3) {
+                Some(year) => format!("{} {}, {}", month, ordinal(day), year_words(year.as_str())),
+                None => format!("{} {}", month, ordinal(day)),
+            }
+        });
+
+        let text = self.clock_time.replace_all(&text, |caps: &Captures| {
+            let hour: u64 = caps[1].parse().unwrap_or(99);
+            let minute: u64 = caps[2].parse().unwrap_or(99);
+            let second = caps.get(3).and_then(|s| s.as_str().parse::<u64>().ok());
+            let meridiem = caps.get(4).map(|m| m.as_str().to_ascii_lowercase());
+            let max_hour = if meridiem.is_some() { 12 } else { 23 };
+            if hour > max_hour || minute > 59 || second.is_some_and(|s| s > 59) {
+                return caps[0].to_string();
+            }
+
+            let mut spoken = cardinal(hour);
+            match (minute, &meridiem) {
+                (0, Some(_)) => {}
+                (0, None) if (1..=12).contains(&hour) => spoken.push_str(" o'clock"),
+                (0, None) => spoken.push_str(" hundred"),
+                (1..=9, _) => spoken.push_str(&format!(" oh {}", cardinal(minute))),
+                _ => spoken.push_str(&format!(" {}", cardinal(minute))),
+            }
+            if let Some(second) = second {
+                let name = if second == 1 { "second" } else { "seconds" };
+                spoken.push_str(&format!(" and {} {}", cardinal(second), name));
+            }
+            if let Some(meridiem) = meridiem {
+                spoken.push_str(&format!(" {} m", meridiem));
+            }
+            spoken
+        });
+
+        let text = self.hour_time.replace_all(&text, |caps: &Captures| {
+            let hour: u64 = caps[1].parse().unwrap_or(99);
+            if !(1..=12).contains(&hour) {
+                return caps[0].to_string();
+            }
+            format!("{} {} m", cardinal(hour), caps[2].to_ascii_lowercase())
+        });
+
+        // A hyphen is a minus sign only at the start of a word
+        let text = self.minus.replace_all(&text, "${1}minus ${2}");
+
+        let text = self.percent.replace_all(&text, |caps: &Captures| {
+            format!("{} percent", decimal_words(&caps[1], caps.get(2).map(|m| m.as_str())))
+        });
+
+        let text = self.unit.replace_all(&text, |caps: &Captures| {
+            let whole = &caps[1];
+            let fraction = caps.get(2).map(|m| m.as_str());
+            let (_, singular, plural) = UNITS.iter().find(|(symbol, _, _)| *symbol == &caps[3]).unwrap();
+            let name = if fraction.is_none() && whole == "1" { singular } else { plural };
+            format!("{} {}", decimal_words(whole, fraction), name)
+        });
+
+        let text = self.ordinal.replace_all(&text, |caps: &Captures| match caps[1].parse::<u64>() {
+            Ok(n) if caps[1].len() <= 18 => ordinal(n),
+            _ => caps[0].to_string(),
+        });
+
+        let text = self.number.replace_all(&text, |caps: &Captures| {
+            let whole = &caps[1];
+            let joined = &caps[2];
+            let is_digits = |digits: &&str| digits.chars().all(|c| c.is_ascii_digit());
+            let words = if joined.is_empty() {
+                match parse_integer(whole) {
+                    // Four-digit whole numbers like 1984 read best as years
+                    Some(n) if !whole.contains(',') && (1100..=1999).contains(&n) => year_words(whole),
+                    _ => integer_words(whole),
+                }
+            } else if let Some(fraction) = joined.strip_prefix('.').filter(is_digits) {
+                decimal_words(whole, Some(fraction))
+            } else if let Some(words) = joined.strip_prefix('/').filter(is_digits).and_then(|d| fraction_words(whole, d)) {
+                words
+            } else {
+                // Versions, phone numbers, ranges and invalid times have no
+                // single reading, so they are left as written
+                return caps[0].to_string();
+            };
+            match caps.get(3) {
+                // "1990s" -> "nineteen nineties"
+                Some(_) => plural_words(&words),
+                None => words,
+            }
+        });
+
+        text.into_owned()
+    }
+}
+
+/// (singular, plural, minor singular, minor plural) for a currency symbol
+fn currency_names(symbol: &str) -> (&'static str, &'static str, &'static str, &'static str) {
+    match symbol {
+        "£" => ("pound", "pounds", "penny", "pence"),
+        "€" => ("euro", "euros", "cent", "cents"),
+        "¥" => ("yen", "yen", "", ""),
+        "₹" => ("rupee", "rupees", "paisa", "paise"),
+        _ => ("dollar", "dollars", "cent", "cents"),
+    }
+}
+
+/// "March third, twenty twenty-five" for a valid year, month and day
+fn spoken_date(year: &str, month: &str, day: &str) -> Option<String> {
+    let month: usize = month.parse().ok()?;
+    let day: u64 = day.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(format!("{} {}, {}", MONTHS[month - 1], ordinal(day), year_words(year)))
+}
+
+/// Parse digits with optional thousands separators
+fn parse_integer(digits: &str) -> Option<u64> {
+    digits.replace(',', "").parse().ok()
+}
+
+/// Whole number in words; leading zeros and very long numbers are read digit by digit
+fn integer_words(digits: &str) -> String {
+    let plain = digits.replace(',', "");
+    match plain.parse::<u64>() {
+        Ok(n) if plain.len() <= 18 && (plain.len() == 1 || !plain.starts_with('0')) => cardinal(n),
+        _ => digit_words(&plain),
+    }
+}
+
+/// Number with an optional fractional part, e.g. "three point one four"
+fn decimal_words(whole: &str, fraction: Option<&str>) -> String {
+    match fraction {
+        Some(fraction) => format!("{} point {}", integer_words(whole), digit_words(fraction)),
+        None => integer_words(whole),
+    }
+}
+
+/// A proper fraction in words, e.g. "3", "4" -> "three quarters"
+fn fraction_words(numerator: &str, denominator: &str) -> Option<String> {
+    let numerator: u64 = numerator.parse().ok()?;
+    let denominator: u64 = denominator.parse().ok()?;
+    if numerator == 0 || numerator >= denominator || denominator > 100 {
+        return None;
+    }
+    let name = match denominator {
+        2 => "half".to_string(),
+        4 => "quarter".to_string(),
+        _ => ordinal(denominator),
+    };
+    let name = if numerator == 1 { name } else { plural_words(&name) };
+    Some(format!("{} {}", cardinal(numerator), name))
+}
+
+/// Plural of a number in words, e.g. "nineteen ninety" -> "nineteen nineties"
+fn plural_words(words: &str) -> String {
+    if let Some(stem) = words.strip_suffix("lf") {
+        format!("{}lves", stem)
+    } else if let Some(stem) = words.strip_suffix('y') {
+        format!("{}ies", stem)
+    } else if words.ends_with('x') {
+        format!("{}es", words)
+    } else {
+        format!("{}s", words)
+    }
+}
+
+fn digit_words(digits: &str) -> String {
+    digits
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|d| ONES[d as usize])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Year in the usual spoken form: "nineteen eighty-four", "two thousand five"
+fn year_words(digits: &str) -> String {
+    let Ok(year) = digits.parse::<u64>() else {
+        return digit_words(digits);
+    };
+    let (century, rest) = (year / 100, year % 100);
+    if digits.len() != 4 || year < 1100 || year % 1000 < 10 {
+        return cardinal(year);
+    }
+    match rest {
+        0 => format!("{} hundred", cardinal(century)),
+        1..=9 => format!("{} oh {}", cardinal(century), cardinal(rest)),
+        _ => format!("{} {}", cardinal(century), cardinal(rest)),
+    }
+}
+
+const ONES: [&str; 20] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+];
+
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+const SCALES: [&str; 7] = ["", "thousand", "million", "billion", "trillion", "quadrillion", "quintillion"];
+
+/// Cardinal number in words, e.g. 1234 -> "one thousand two hundred thirty-four"
+fn cardinal(n: u64) -> String {
+    if n == 0 {
+        return ONES[0].to_string();
+    }
+
+    let mut groups = Vec::new();
+    let mut rest = n;
+    let mut scale = 0;
+    while rest > 0 {
+        let group = rest % 1000;
+        if group > 0 {
+            let words = below_thousand(group);
+            groups.push(if scale == 0 { words } else { format!("{} {}", words, SCALES[scale]) });
+        }
+        rest /= 1000;
+        scale += 1;
+    }
+    groups.reverse();
+    groups.join(" ")
+}
+
+fn below_thousand(n: u64) -> String {
+    let (hundreds, rest) = (n / 100, n % 100);
+    let rest_words = match rest {
+        0 => String::new(),
+        1..=19 => ONES[rest as usize].to_string(),
+        _ if rest % 10 == 0 => TENS[(rest / 10) as usize].to_string(),
+        _ => format!("{}-{}", TENS[(rest / 10) as usize], ONES[(rest % 10) as usize]),
+    };
+    match (hundreds, rest) {
+        (0, _) => rest_words,
+        (_, 0) => format!("{} hundred", ONES[hundreds as usize]),
+        _ => format!("{} hundred {}", ONES[hundreds as usize], rest_words),
+    }
+}
+
+/// Ordinal number in words, e.g. 23 -> "twenty-third"
+fn ordinal(n: u64) -> String {
+    let words = cardinal(n);
+    let split = words.rfind([' ', '-']).map(|i| i + 1).unwrap_or(0);
+    let (head, last) = words.split_at(split);
+    let last = match last {
+        "one" => "first".to_string(),
+        "two" => "second".to_string(),
+        "three" => "third".to_string(),
+        "five" => "fifth".to_string(),
+        "eight" => "eighth".to_string(),
+        "nine" => "ninth".to_string(),
+        "twelve" => "twelfth".to_string(),
+        _ if last.ends_with('y') => format!("{}ieth", &last[..last.len() - 1]),
+        _ => format!("{}th", last),
+    };
+    format!("{}{}", head, last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english() {
+        let cases = [
+            (
+                "$3.50 on 12/03/2025 at 5pm, 10km",
+                "three dollars and fifty cents on December third, twenty twenty-five at five p m, ten kilometers",
+            ),
+            ("It cost $1,200.", "It cost one thousand two hundred dollars."),
+            ("Meet at 10:30 or 7:05 pm.", "Meet at ten thirty or seven oh five p m."),
+            ("Pi is about 3.14.", "Pi is about three point one four."),
+            ("Born in 1984, on the 3rd.", "Born in nineteen eighty-four, on the third."),
+            ("It is -5 outside, 20% off.", "It is minus five outside, twenty percent off."),
+            ("Version 1.2.3", "Version 1.2.3"),
+            ("Call 555-1234", "Call 555-1234"),
+            ("Pages 10-20", "Pages 10-20"),
+            ("3/4 cup", "three quarters cup"),
+            ("1/2 and 2/3", "one half and two thirds"),
+            ("24/7", "24/7"),
+            ("10:60", "10:60"),
+            ("25:00", "25:00"),
+            ("the 1990s", "the nineteen nineties"),
+            ("the 80s", "the eighties"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize_text(input, "en"), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn other_languages_unchanged() {
+        assert_eq!(normalize_text("Es kostet 3,50 €", "de"), "Es kostet 3,50 €");
+    }
+}
@@ -0,0 +1,225 @@
+// Opus Encoder Module - Ogg Opus output via libopus
+//
+// Opus only runs at a handful of rates, so model output is resampled to
+// 48 kHz first. Packets are 20 ms and each call to `encode` ends with a
+// flushed Ogg page, which keeps streamed responses playable as they arrive.
+use anyhow::{anyhow, Result};
+use audiopus_sys as ffi;
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use std::ffi::CStr;
+
+use crate::resampler::Resampler;
+
+/// Opus internal sample rate
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+
+/// Samples per 20 ms packet at 48 kHz
+const FRAME_SIZE: usize = 960;
+
+/// Maximum size of one encoded packet, as recommended by libopus
+const MAX_PACKET_BYTES: usize = 4000;
+
+/// Ogg logical stream serial number; there is only ever one stream per file
+const STREAM_SERIAL: u32 = 1;
+
+/// Owned libopus encoder state
+struct EncoderState(*mut ffi::OpusEncoder);
+
+// The encoder state is plain heap memory owned by this handle and is only
+// ever used through `&mut self`
+unsafe impl Send for EncoderState {}
+
+impl Drop for EncoderState {
+    fn drop(&mut self) {
+        unsafe { ffi::opus_encoder_destroy(self.0) }
+    }
+}
+
+/// Streaming Ogg Opus encoder for mono `f32` samples
+pub struct OggOpusEncoder {
+    encoder: EncoderState,
+    resampler: Resampler,
+    writer: PacketWriter<Vec<u8>>,
+    input_sample_rate: u32,
+    pre_skip: usize,
+    pending: Vec<f32>,
+    samples_in: u64,
+    samples_encoded: u64,
+    headers_written: bool,
+}
+
+impl OggOpusEncoder {
+    /// Create an encoder for audio at `sample_rate`, targeting `bitrate_kbps`
+    pub fn new(sample_rate: u32, bitrate_kbps: u32) -> Result<Self> {
+        if !(6..=510).contains(&bitrate_kbps) {
+            return Err(anyhow!("Opus bitrate must be between 6 and 510 kbps, got {}", bitrate_kbps));
+        }
+
+        let mut error = 0;
+        let state = unsafe {
+            ffi::opus_encoder_create(OPUS_SAMPLE_RATE as i32, 1, ffi::OPUS_APPLICATION_AUDIO, &mut error)
+        };
+        if error != ffi::OPUS_OK || state.is_null() {
+            return Err(anyhow!("Failed to create Opus encoder: {}", opus_error(error)));
+        }
+        let encoder = EncoderState(state);
+
+        let result = unsafe {
+            ffi::opus_encoder_ctl(encoder.0, ffi::OPUS_SET_BITRATE_REQUEST, (bitrate_kbps * 1000) as i32)
+        };
+        if result != ffi::OPUS_OK {
+            return Err(anyhow!("Failed to set Opus bitrate: {}", opus_error(result)));
+        }
+
+        let mut lookahead: i32 = 0;
+        let result = unsafe {
+            ffi::opus_encoder_ctl(encoder.0, ffi::OPUS_GET_LOOKAHEAD_REQUEST, &mut lookahead as *mut i32)
+        };
+        if result != ffi::OPUS_OK {
+            return Err(anyhow!("Failed to query Opus lookahead: {}", opus_error(result)));
+        }
+
+        Ok(Self {
+            encoder,
+            resampler: Resampler::new(sample_rate, OPUS_SAMPLE_RATE)?,
+            writer: PacketWriter::new(Vec::new()),
+            input_sample_rate: sample_rate,
+            pre_skip: lookahead.max(0) as usize,
+            pending: Vec::new(),
+            samples_in: 0,
+            samples_encoded: 0,
+            headers_written: false,
+        })
+    }
+
+    /// Encode samples, returning the Ogg pages completed by this call
+    pub fn encode(&mut self, samples: &[f32]) -> Result<Vec<u8>> {
+        let resampled = self.resampler.process(samples)?;
+        self.samples_in += resampled.len() as u64;
+        self.pending.extend_from_slice(&resampled);
+        self.write_headers()?;
+
+        let pending = std::mem::take(&mut self.pending);
+        let frames = pending.chunks_exact(FRAME_SIZE);
+        self.pending = frames.remainder().to_vec();
+        let packets = frames.len();
+        for (packet, frame) in frames.enumerate() {
+            let end_info = if packet + 1 == packets {
+                PacketWriteEndInfo::EndPage
+            } else {
+                PacketWriteEndInfo::NormalPacket
+            };
+            self.write_frame(frame, end_info)?;
+        }
+
+        Ok(self.take_output())
+    }
+
+    /// Encode the remaining audio plus the encoder lookahead and end the stream
+    pub fn finish(&mut self) -> Result<Vec<u8>> {
+        let resampled = self.resampler.finish()?;
+        self.samples_in += resampled.len() as u64;
+        self.pending.extend_from_slice(&resampled);
+        self.write_headers()?;
+
+        // The decoder drops `pre_skip` samples, so encode that much silence
+        // past the end to get the tail of the real audio out of the encoder
+        let mut pending = std::mem::take(&mut self.pending);
+        let total = pending.len() + self.pre_skip;
+        pending.resize(total.div_ceil(FRAME_SIZE).max(1) * FRAME_SIZE, 0.0);
+
+        let packets = pending.len() / FRAME_SIZE;
+        for (packet, frame) in pending.chunks_exact(FRAME_SIZE).enumerate() {
+            let end_info = if packet + 1 == packets {
+                PacketWriteEndInfo::EndStream
+            } else {
+                PacketWriteEndInfo::NormalPacket
+            };
+            self.write_frame(frame, end_info)?;
+        }
+
+        Ok(self.take_output())
+    }
+
+    fn write_headers(&mut self) -> Result<()> {
+        if self.headers_written {
+            return Ok(());
+        }
+        self.headers_written = true;
+
+        // RFC 7845 identification header
+        let mut head = Vec::with_capacity(19);
+        head.extend_from_slice(b"OpusHead");
+        head.push(1); // version
+        head.push(1); // channels
+        head.extend_from_slice(&(self.pre_skip as u16).to_le_bytes());
+        head.extend_from_slice(&self.input_sample_rate.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+        head.push(0); // channel mapping family
+        self.writer
+            .write_packet(head.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        // Comment header with the vendor string and no user comments
+        let vendor = concat!("superTTS ", env!("CARGO_PKG_VERSION"));
+        let mut tags = Vec::new();
+        tags.extend_from_slice(b"OpusTags");
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor.as_bytes());
+        tags.extend_from_slice(&0u32.to_le_bytes());
+        self.writer
+            .write_packet(tags.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &[f32], end_info: PacketWriteEndInfo) -> Result<()> {
+        let mut packet = vec![0u8; MAX_PACKET_BYTES];
+        let length = unsafe {
+            ffi::opus_encode_float(
+                self.encoder.0,
+                frame.as_ptr(),
+                FRAME_SIZE as i32,
+                packet.as_mut_ptr(),
+                MAX_PACKET_BYTES as i32,
+            )
+        };
+        if length < 0 {
+            return Err(anyhow!("Opus encoding failed: {}", opus_error(length)));
+        }
+        packet.truncate(length as usize);
+        self.samples_encoded += FRAME_SIZE as u64;
+
+        // Granule position counts decoded samples including pre-skip; on the
+        // last page it marks where the real audio ends
+        let granule = if end_info == PacketWriteEndInfo::EndStream {
+            self.samples_in + self.pre_skip as u64
+        } else {
+            self.samples_encoded
+        };
+        self.writer.write_packet(packet.into_boxed_slice(), STREAM_SERIAL, end_info, granule)?;
+        Ok(())
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(self.writer.inner_mut())
+    }
+}
+
+fn opus_error(code: i32) -> String {
+    unsafe { CStr::from_ptr(ffi::opus_strerror(code)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Encode a complete clip as an Ogg Opus file
+pub fn write_opus_to_buffer(
+    buffer: &mut Vec<u8>,
+    audio_data: &[f32],
+    sample_rate: i32,
+    bitrate_kbps: u32,
+) -> Result<()> {
+    let mut encoder = OggOpusEncoder::new(sample_rate as u32, bitrate_kbps)?;
+    buffer.extend_from_slice(&encoder.encode(audio_data)?);
+    buffer.extend_from_slice(&encoder.finish()?);
+    Ok(())
+}
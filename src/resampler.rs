@@ -0,0 +1,111 @@
+// Resampler Module - Band-limited sample rate conversion for output encoders
+use anyhow::{anyhow, Result};
+use rubato::{FftFixedIn, Resampler as _};
+
+/// Input frames processed per FFT block
+const CHUNK_SIZE: usize = 1024;
+
+/// Sub-chunks per block; more sub-chunks lower latency at some CPU cost
+const SUB_CHUNKS: usize = 2;
+
+/// Streaming mono resampler. Output is aligned with the input (the filter
+/// delay is removed) and, once `finish` is called, has exactly
+/// `round(input_len * output_rate / input_rate)` samples.
+pub struct Resampler {
+    inner: Option<FftFixedIn<f32>>,
+    input_rate: usize,
+    output_rate: usize,
+    pending: Vec<f32>,
+    delay_remaining: usize,
+    input_total: usize,
+    output_total: usize,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Result<Self> {
+        let inner = if input_rate == output_rate {
+            None
+        } else {
+            let resampler = FftFixedIn::new(input_rate as usize, output_rate as usize, CHUNK_SIZE, SUB_CHUNKS, 1)
+                .map_err(|e| anyhow!("Cannot resample {} Hz to {} Hz: {}", input_rate, output_rate, e))?;
+            Some(resampler)
+        };
+        let delay_remaining = inner.as_ref().map(|r| r.output_delay()).unwrap_or(0);
+
+        Ok(Self {
+            inner,
+            input_rate: input_rate as usize,
+            output_rate: output_rate as usize,
+            pending: Vec::new(),
+            delay_remaining,
+            input_total: 0,
+            output_total: 0,
+        })
+    }
+
+    /// Resample the next block of samples, returning whatever output is ready
+    pub fn process(&mut self, input: &[f32]) -> Result<Vec<f32>> {
+        self.input_total += input.len();
+        let Some(inner) = self.inner.as_mut() else {
+            self.output_total += input.len();
+            return Ok(input.to_vec());
+        };
+
+        self.pending.extend_from_slice(input);
+        let mut output = Vec::new();
+        let mut consumed = 0;
+        while self.pending.len() - consumed >= inner.input_frames_next() {
+            let frames = inner.input_frames_next();
+            let block = inner
+                .process(&[&self.pending[consumed..consumed + frames]], None)
+                .map_err(|e| anyhow!("Resampling failed: {}", e))?;
+            consumed += frames;
+            output.extend_from_slice(&block[0]);
+        }
+        self.pending.drain(..consumed);
+
+        Ok(self.trim_delay(output))
+    }
+
+    /// Flush the filter with silence and return the remaining output
+    pub fn finish(&mut self) -> Result<Vec<f32>> {
+        let expected = ((self.input_total as u64 * self.output_rate as u64 + self.input_rate as u64 / 2)
+            / self.input_rate as u64) as usize;
+
+        let mut output = Vec::new();
+        let mut partial = Some(std::mem::take(&mut self.pending));
+        while self.output_total < expected {
+            let Some(inner) = self.inner.as_mut() else {
+                break;
+            };
+            let block = match partial.take() {
+                Some(samples) => inner.process_partial(Some(&[samples]), None),
+                None => inner.process_partial(None::<&[Vec<f32>]>, None),
+            }
+            .map_err(|e| anyhow!("Resampling failed: {}", e))?;
+            let block = block.into_iter().next().unwrap_or_default();
+            output.extend(self.trim_delay(block));
+        }
+
+        let excess = self.output_total.saturating_sub(expected);
+        output.truncate(output.len().saturating_sub(excess));
+        self.output_total -= excess;
+        Ok(output)
+    }
+
+    fn trim_delay(&mut self, mut output: Vec<f32>) -> Vec<f32> {
+        let skip = self.delay_remaining.min(output.len());
+        self.delay_remaining -= skip;
+        output.drain(..skip);
+        self.output_total += output.len();
+        output
+    }
+}
+
+/// Resample a complete clip
+pub fn resample(input: &[f32], input_rate: u32, output_rate: u32) -> Result<Vec<f32>> {
+    let mut resampler = Resampler::new(input_rate, output_rate)?;
+    let mut output = resampler.process(input)?;
+    output.extend(resampler.finish()?);
+    Ok(output)
+}
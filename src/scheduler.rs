@@ -0,0 +1,448 @@
+// Scheduler Module - Weighted fair queuing of engine leases
+//
+// Every engine lease goes through the scheduler. While an engine is free and
+// nobody waits, requests get one at once; otherwise they queue, and each
+// freed engine goes to the waiting request with the earliest virtual finish
+// time (self-clocked fair queuing). A request's finish time is its client's
+// previous finish time, or the current virtual time if that is later, plus
+// its cost (input characters) divided by the weight of its priority class.
+// A client sending many long inputs therefore queues behind its own earlier
+// work, while other clients' short requests, and interactive ones in
+// particular, are served in between.
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+/// Class of a request, chosen per API key or by the `X-Priority` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriorityClass {
+    /// Someone is waiting for the audio, e.g. an assistant's reply
+    Interactive,
+    /// Bulk work such as audiobooks
+    Batch,
+}
+
+impl PriorityClass {
+    pub const ALL: [PriorityClass; 2] = [PriorityClass::Interactive, PriorityClass::Batch];
+    pub const SUPPORTED: [&'static str; 2] = ["interactive", "batch"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "interactive" => Some(PriorityClass::Interactive),
+            "batch" => Some(PriorityClass::Batch),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PriorityClass::Interactive => "interactive",
+            PriorityClass::Batch => "batch",
+        }
+    }
+}
+
+/// Share of the engines each priority class gets while both are waiting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityWeights {
+    pub interactive: u32,
+    pub batch: u32,
+}
+
+impl Default for PriorityWeights {
+    fn default() -> Self {
+        PriorityWeights {
+            interactive: 8,
+            batch: 1,
+        }
+    }
+}
+
+impl PriorityWeights {
+    pub fn weight(&self, class: PriorityClass) -> u32 {
+        match class {
+            PriorityClass::Interactive => self.interactive,
+            PriorityClass::Batch => self.batch,
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if PriorityClass::ALL.iter().any(|&class| self.weight(class) == 0) {
+            anyhow::bail!("Priority weights must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// Who is asking for an engine and how much work they bring
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    /// Name of the client (API key) the request is scheduled for
+    pub client: String,
+    pub priority: PriorityClass,
+    /// Input length in characters
+    pub cost: usize,
+    /// Longest the request will queue for an engine, if shorter than the
+    /// pool's checkout timeout
+    pub max_wait: Option<Duration>,
+}
+
+/// Outcome of asking for a lease
+pub enum Admission {
+    Leased(Lease),
+    Queued(Waiting),
+    /// The queue is full; `waiting` requests are ahead
+    Full { waiting: usize },
+}
+
+/// Right to lease one engine, handed on to the next waiter when dropped
+pub struct Lease {
+    scheduler: Arc<Scheduler>,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+/// A queued request; leaves the queue when dropped before its turn
+pub struct Waiting {
+    scheduler: Arc<Scheduler>,
+    id: u64,
+    receiver: oneshot::Receiver<Lease>,
+}
+
+impl Waiting {
+    /// Wait for this request's turn
+    pub async fn lease(mut self) -> Lease {
+        // The scheduler drops a waiter's sender only after sending to it
+        (&mut self.receiver).await.expect("Scheduler dropped a waiting request")
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        state.waiting.retain(|waiter| waiter.id != self.id);
+    }
+}
+
+struct Waiter {
+    id: u64,
+    priority: PriorityClass,
+    finish: f64,
+    sender: oneshot::Sender<Lease>,
+}
+
+struct SchedulerState {
+    free: usize,
+    /// Finish time of the request most recently given an engine
+    virtual_time: f64,
+    /// Finish time of each client's latest request, while ahead of the
+    /// virtual time
+    finish_times: HashMap<String, f64>,
+    waiting: Vec<Waiter>,
+    next_id: u64,
+}
+
+impl SchedulerState {
+    fn finish_time(&mut self, ticket: &Ticket, weights: &PriorityWeights) -> f64 {
+        let start = self
+            .finish_times
+            .get(&ticket.client)
+            .copied()
+            .unwrap_or(self.virtual_time)
+            .max(self.virtual_time);
+        let finish = start + ticket.cost.max(1) as f64 / f64::from(weights.weight(ticket.priority));
+        self.finish_times.insert(ticket.client.clone(), finish);
+        finish
+    }
+
+    /// Advance the virtual time to a request being given an engine
+    fn dispatch(&mut self, finish: f64) {
+        self.virtual_time = self.virtual_time.max(finish);
+        let virtual_time = self.virtual_time;
+        self.finish_times.retain(|_, finish| *finish > virtual_time);
+    }
+}
+
+/// Hands out a fixed number of leases in weighted fair order
+pub struct Scheduler {
+    state: Mutex<SchedulerState>,
+    weights: PriorityWeights,
+    max_waiting: usize,
+}
+
+impl Scheduler {
+    /// `slots` leases, with at most `max_waiting` requests queued for them
+    pub fn new(slots: usize, max_waiting: usize, weights: PriorityWeights) -> Arc<Self> {
+        Arc::new(Scheduler {
+            state: Mutex::new(SchedulerState {
+                free: slots,
+                virtual_time: 0.0,
+                finish_times: HashMap::new(),
+                waiting: Vec::new(),
+                next_id: 0,
+            }),
+            weights,
+            max_waiting,
+        })
+    }
+
+    /// A lease right away if one is free and nobody waits, otherwise a
+    /// place in the queue if it has room
+    pub fn admit(self: &Arc<Self>, ticket: &Ticket) -> Admission {
+        let mut state = self.state.lock().unwrap();
+        if state.free > 0 && state.waiting.is_empty() {
+            state.free -= 1;
+            let finish = state.finish_time(ticket, &self.weights);
+            state.dispatch(finish);
+            return Admission::Leased(Lease {
+                scheduler: Arc::clone(self),
+            });
+        }
+        if state.waiting.len() >= self.max_waiting {
+            return Admission::Full {
+                waiting: state.waiting.len(),
+            };
+        }
+
+        let finish = state.finish_time(ticket, &self.weights);
+        let id = state.next_id;
+        state.next_id += 1;
+        let (sender, receiver) = oneshot::channel();
+        state.waiting.push(Waiter {
+            id,
+            priority: ticket.priority,
+            finish,
+            sender,
+        });
+        Admission::Queued(Waiting {
+            scheduler: Arc::clone(self),
+            id,
+            receiver,
+        })
+    }
+
+    /// Give a returned lease to the waiter with the earliest finish time
+    fn release(self: &Arc<Self>) {
+        let waiter = {
+            let mut state = self.state.lock().unwrap();
+            let next = state
+                .waiting
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.finish.total_cmp(&b.finish).then(a.id.cmp(&b.id)))
+                .map(|(index, _)| index);
+            match next {
+                Some(index) => {
+                    let waiter = state.waiting.remove(index);
+                    state.dispatch(waiter.finish);
+                    waiter
+                }
+                None => {
+                    state.free += 1;
+                    return;
+                }
+            }
+        };
+        // A waiter that gave up in the meantime drops the lease again, which
+        // passes it on to the next one
+        let _ = waiter.sender.send(Lease {
+            scheduler: Arc::clone(self),
+        });
+    }
+
+    pub fn weights(&self) -> &PriorityWeights {
+        &self.weights
+    }
+
+    pub fn free(&self) -> usize {
+        self.state.lock().unwrap().free
+    }
+
+    /// Number of queued requests per priority class
+    pub fn waiting(&self) -> BTreeMap<PriorityClass, usize> {
+        let state = self.state.lock().unwrap();
+        let mut waiting: BTreeMap<PriorityClass, usize> =
+            PriorityClass::ALL.iter().map(|&class| (class, 0)).collect();
+        for waiter in &state.waiting {
+            *waiting.entry(waiter.priority).or_default() += 1;
+        }
+        waiting
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use tokio::sync::Barrier;
+    use tokio::time::timeout;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    fn ticket(client: &str, priority: PriorityClass, cost: usize) -> Ticket {
+        Ticket {
+            client: client.to_string(),
+            priority,
+            cost,
+            max_wait: None,
+        }
+    }
+
+    fn leased(admission: Admission) -> Lease {
+        match admission {
+            Admission::Leased(lease) => lease,
+            _ => panic!("expected a lease"),
+        }
+    }
+
+    fn queued(admission: Admission) -> Waiting {
+        match admission {
+            Admission::Queued(waiting) => waiting,
+            _ => panic!("expected to queue"),
+        }
+    }
+
+    fn queue_depth(scheduler: &Scheduler) -> usize {
+        scheduler.waiting().values().sum()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn leases_are_held_in_parallel() {
+        const SLOTS: usize = 4;
+        let scheduler = Scheduler::new(SLOTS, 8, PriorityWeights::default());
+        let barrier = Arc::new(Barrier::new(SLOTS));
+
+        // Every task holds its lease until all of them hold one, which only
+        // happens if the scheduler hands out SLOTS leases at once
+        let tasks: Vec<_> = (0..SLOTS)
+            .map(|i| {
+                let scheduler = Arc::clone(&scheduler);
+                let barrier = Arc::clone(&barrier);
+                tokio::spawn(async move {
+                    let lease = match scheduler.admit(&ticket(&format!("client-{}", i), PriorityClass::Interactive, 10)) {
+                        Admission::Leased(lease) => lease,
+                        Admission::Queued(waiting) => waiting.lease().await,
+                        Admission::Full { .. } => panic!("queue full"),
+                    };
+                    barrier.wait().await;
+                    drop(lease);
+                })
+            })
+            .collect();
+        for task in tasks {
+            timeout(WAIT, task).await.expect("leases were not held in parallel").unwrap();
+        }
+        assert_eq!(scheduler.free(), SLOTS);
+    }
+
+    #[tokio::test]
+    async fn extra_requests_queue_until_a_lease_is_dropped() {
+        let scheduler = Scheduler::new(2, 8, PriorityWeights::default());
+        let first = leased(scheduler.admit(&ticket("a", PriorityClass::Interactive, 10)));
+        let _second = leased(scheduler.admit(&ticket("b", PriorityClass::Interactive, 10)));
+        assert_eq!(scheduler.free(), 0);
+
+        let third = queued(scheduler.admit(&ticket("c", PriorityClass::Interactive, 10)));
+        let fourth = queued(scheduler.admit(&ticket("d", PriorityClass::Interactive, 10)));
+        assert_eq!(queue_depth(&scheduler), 2);
+
+        // One dropped lease wakes exactly one waiter, the earlier of equals
+        drop(first);
+        assert_eq!(queue_depth(&scheduler), 1);
+        assert_eq!(scheduler.free(), 0);
+        let third = timeout(WAIT, third.lease()).await.expect("waiter was not woken");
+        assert!(timeout(Duration::from_millis(50), fourth.lease()).await.is_err());
+
+        // The timed-out waiter left the queue, so the lease becomes free
+        drop(third);
+        assert_eq!(queue_depth(&scheduler), 0);
+        assert_eq!(scheduler.free(), 1);
+    }
+
+    #[tokio::test]
+    async fn full_queue_rejects_requests() {
+        let scheduler = Scheduler::new(1, 2, PriorityWeights::default());
+        let _lease = leased(scheduler.admit(&ticket("a", PriorityClass::Interactive, 10)));
+        let _waiting = [
+            queued(scheduler.admit(&ticket("b", PriorityClass::Interactive, 10))),
+            queued(scheduler.admit(&ticket("c", PriorityClass::Batch, 10))),
+        ];
+        match scheduler.admit(&ticket("d", PriorityClass::Interactive, 10)) {
+            Admission::Full { waiting } => assert_eq!(waiting, 2),
+            _ => panic!("expected the queue to be full"),
+        }
+    }
+
+    #[tokio::test]
+    async fn dropped_waiter_passes_its_lease_on() {
+        let scheduler = Scheduler::new(1, 8, PriorityWeights::default());
+        let lease = leased(scheduler.admit(&ticket("a", PriorityClass::Interactive, 10)));
+        let gone = queued(scheduler.admit(&ticket("b", PriorityClass::Interactive, 10)));
+        let next = queued(scheduler.admit(&ticket("c", PriorityClass::Interactive, 10)));
+
+        // The lease is sent to "b", which gives up before receiving it
+        drop(lease);
+        drop(gone);
+        let lease = timeout(WAIT, next.lease()).await.expect("lease was leaked");
+
+        drop(lease);
+        assert_eq!(scheduler.free(), 1);
+        assert_eq!(queue_depth(&scheduler), 0);
+    }
+
+    #[tokio::test]
+    async fn flooding_client_does_not_starve_short_requests() {
+        for flood_priority in PriorityClass::ALL {
+            let scheduler = Scheduler::new(1, 64, PriorityWeights::default());
+            let lease = leased(scheduler.admit(&ticket("holder", PriorityClass::Interactive, 1)));
+            let _flood: Vec<Waiting> = (0..20)
+                .map(|_| queued(scheduler.admit(&ticket("audiobooks", flood_priority, 5000))))
+                .collect();
+            let short = queued(scheduler.admit(&ticket("assistant", PriorityClass::Interactive, 50)));
+
+            drop(lease);
+            timeout(Duration::from_millis(500), short.lease())
+                .await
+                .unwrap_or_else(|_| panic!("short request starved by {} flood", flood_priority.as_str()));
+        }
+    }
+
+    #[tokio::test]
+    async fn classes_are_served_in_weight_ratio() {
+        let weights = PriorityWeights::default();
+        let scheduler = Scheduler::new(1, 64, weights.clone());
+        let mut lease = leased(scheduler.admit(&ticket("holder", PriorityClass::Interactive, 1)));
+        let mut queues: BTreeMap<PriorityClass, VecDeque<Waiting>> = BTreeMap::new();
+        for _ in 0..20 {
+            for (client, class) in [("assistant", PriorityClass::Interactive), ("audiobooks", PriorityClass::Batch)] {
+                queues.entry(class).or_default().push_back(queued(scheduler.admit(&ticket(client, class, 10))));
+            }
+        }
+
+        // Pass the lease on and see whose queue it went to
+        let rounds = (weights.interactive + weights.batch) as usize * 2;
+        let mut served: BTreeMap<PriorityClass, usize> = BTreeMap::new();
+        for _ in 0..rounds {
+            let before = scheduler.waiting();
+            drop(lease);
+            let after = scheduler.waiting();
+            let class = *PriorityClass::ALL
+                .iter()
+                .find(|class| after[class] < before[class])
+                .expect("nobody was served");
+            *served.entry(class).or_default() += 1;
+            let waiting = queues.get_mut(&class).unwrap().pop_front().unwrap();
+            lease = timeout(WAIT, waiting.lease()).await.expect("lease was not handed over");
+        }
+        assert_eq!(served[&PriorityClass::Interactive], weights.interactive as usize * 2);
+        assert_eq!(served[&PriorityClass::Batch], weights.batch as usize * 2);
+    }
+}
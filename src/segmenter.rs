@@ -0,0 +1,352 @@
+// Segmenter Module - Language-aware sentence boundaries
+//
+// A full stop ends a sentence unless it belongs to an abbreviation of the
+// text's language ("Dr.", "z.B."), an initial ("J. R. R. Tolkien"), an
+// ordinal in languages that write them with a dot ("am 3. Mai"), or it is
+// followed by a lowercase word (ellipses mid-sentence, "e.g. this"). Decimals
+// such as "3.14" never match because a boundary needs whitespace after the
+// punctuation. Terminators inside brackets or quotes do not end the sentence
+// around them. Full-width (CJK) and other scripts' terminators always end a
+// sentence.
+//
+// Extra abbreviations can be loaded from a JSON file keyed by language:
+//
+//   { "en": ["Approx.", "Est."], "de": ["Bsp."] }
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// Extra abbreviations keyed by language code
+pub type AbbreviationTable = BTreeMap<String, Vec<String>>;
+
+const ENGLISH_ABBREVIATIONS: &[&str] = &[
+    "Mr.", "Mrs.", "Ms.", "Dr.", "Prof.", "Sr.", "Jr.", "St.", "Mt.", "Ave.", "Rd.", "Blvd.", "Dept.", "Inc.",
+    "Ltd.", "Co.", "Corp.", "Gen.", "Col.", "Capt.", "Lt.", "Sgt.", "Gov.", "Sen.", "Rep.", "Rev.", "No.", "Fig.",
+    "Vol.", "Jan.", "Feb.", "Mar.", "Apr.", "Jun.", "Jul.", "Aug.", "Sep.", "Sept.", "Oct.", "Nov.", "Dec.",
+    "etc.", "vs.", "i.e.", "e.g.", "cf.", "al.", "approx.", "ca.", "Ph.D.", "U.S.", "U.K.",
+];
+
+const GERMAN_ABBREVIATIONS: &[&str] = &[
+    "Dr.", "Prof.", "Hr.", "Fr.", "Nr.", "Str.", "Abs.", "Abb.", "Jh.", "Mio.", "Mrd.", "Tel.", "bzw.", "usw.",
+    "z.B.", "d.h.", "u.a.", "o.ä.", "u.U.", "z.T.", "ca.", "evtl.", "ggf.", "inkl.", "vgl.", "etc.", "bspw.",
+    "sog.", "geb.", "Jan.", "Feb.", "Apr.", "Aug.", "Sept.", "Okt.", "Nov.", "Dez.",
+];
+
+const FRENCH_ABBREVIATIONS: &[&str] = &[
+    "Mme.", "Mmes.", "Mlle.", "Mlles.", "Dr.", "Pr.", "St.", "Ste.", "Cie.", "etc.", "av.", "bd.", "cf.", "env.",
+    "ex.", "éd.", "vol.", "chap.", "janv.", "févr.", "avr.", "juil.", "sept.", "oct.", "nov.", "déc.",
+];
+
+const SPANISH_ABBREVIATIONS: &[&str] = &[
+    "Sr.", "Sra.", "Srta.", "Sres.", "Dr.", "Dra.", "Lic.", "Ing.", "Ud.", "Uds.", "Vd.", "Vds.", "Dña.", "Av.",
+    "Avda.", "etc.", "pág.", "págs.", "ej.", "aprox.", "núm.", "tel.", "admón.",
+];
+
+const ITALIAN_ABBREVIATIONS: &[&str] = &[
+    "Sig.", "Sigg.", "Dott.", "Prof.", "Avv.", "Ing.", "Geom.", "ecc.", "es.", "pag.",
+    "pagg.", "tel.", "S.p.A.",
+];
+
+const PORTUGUESE_ABBREVIATIONS: &[&str] = &[
+    "Sr.", "Sra.", "Srta.", "Dr.", "Dra.", "Prof.", "Profa.", "Av.", "Ltda.", "etc.", "pág.", "págs.", "ex.",
+    "tel.", "aprox.",
+];
+
+/// Built-in abbreviations and whether the language writes ordinals with a
+/// full stop
+fn builtin_rules(language: &str) -> Option<(&'static [&'static str], bool)> {
+    let rules: (&'static [&'static str], bool) = match language {
+        "en" => (ENGLISH_ABBREVIATIONS, false),
+        "de" => (GERMAN_ABBREVIATIONS, true),
+        "fr" => (FRENCH_ABBREVIATIONS, false),
+        "es" => (SPANISH_ABBREVIATIONS, false),
+        "it" => (ITALIAN_ABBREVIATIONS, false),
+        "pt" => (PORTUGUESE_ABBREVIATIONS, false),
+        "da" | "no" | "nb" | "nn" | "fi" | "cs" | "sk" | "pl" | "hu" | "sl" | "hr" | "sr" | "et" | "lv" | "tr" => {
+            (&[], true)
+        }
+        _ => return None,
+    };
+    Some(rules)
+}
+
+/// Whether `language` looks like a language code such as "en" or "pt-BR"
+pub fn is_valid_language(language: &str) -> bool {
+    static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z]{2,3}(?:[-_][A-Za-z0-9]{2,8})*$").unwrap());
+    TAG.is_match(language)
+}
+
+fn primary_subtag(language: &str) -> String {
+    language.split(['-', '_']).next().unwrap_or_default().to_ascii_lowercase()
+}
+
+static TERMINATOR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"[.!?…]+["'”’»)\]]*\s+|[。！？｡]+[」』”’）"']*\s*|[।॥؟۔።։]+\s*"#).unwrap()
+});
+
+/// Sentence boundary rules for one language
+#[derive(Debug)]
+pub struct Segmenter {
+    abbreviations: HashSet<String>,
+    ordinal_dot: bool,
+}
+
+impl Segmenter {
+    /// Rules for `language`: its built-in abbreviations plus `extra`
+    pub fn new<I: IntoIterator<Item = String>>(language: &str, extra: I) -> Self {
+        let (builtin, ordinal_dot) = builtin_rules(&primary_subtag(language)).unwrap_or((&[], false));
+        let abbreviations = builtin
+            .iter()
+            .map(|abbreviation| abbreviation.to_string())
+            .chain(extra)
+            .map(|abbreviation| abbreviation.trim().trim_end_matches('.').to_string())
+            .filter(|abbreviation| !abbreviation.is_empty())
+            .collect();
+        Segmenter { abbreviations, ordinal_dot }
+    }
+
+    /// Byte offsets just past each sentence boundary in `text`, i.e. after
+    /// the terminating punctuation, any closing quotes or brackets, and the
+    /// whitespace that follows
+    pub fn boundaries(&self, text: &str) -> Vec<usize> {
+        let mut boundaries = Vec::new();
+        let mut nesting = Nesting::default();
+        let mut scanned = 0;
+
+        for m in TERMINATOR.find_iter(text) {
+            nesting.scan(&text[scanned..m.end()]);
+            scanned = m.end();
+
+            if nesting.is_open() {
+                continue;
+            }
+            let punctuation = m.as_str().trim_end();
+            let latin = punctuation.starts_with(['.', '!', '?', '…']);
+            if latin && starts_lowercase(&text[m.end()..]) {
+                continue;
+            }
+            if punctuation.starts_with('.') && !punctuation.starts_with("..") && self.is_abbreviation(&text[..m.start()]) {
+                continue;
+            }
+            boundaries.push(m.end());
+        }
+
+        boundaries
+    }
+
+    /// Split `text` into sentences that concatenate back to `text`
+    pub fn split<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut sentences = Vec::new();
+        let mut start = 0;
+        for end in self.boundaries(text) {
+            sentences.push(&text[start..end]);
+            start = end;
+        }
+        if start < text.len() {
+            sentences.push(&text[start..]);
+        }
+        sentences
+    }
+
+    /// Whether the word ending `before` (the text up to a full stop) is an
+    /// abbreviation, an initial or a dotted ordinal
+    fn is_abbreviation(&self, before: &str) -> bool {
+        let word = before
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches(['"', '\'', '“', '‘', '«', '„', '(', '[', '¿', '¡']);
+        if word.is_empty() {
+            return false;
+        }
+
+        let mut chars = word.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_uppercase() {
+                return true;
+            }
+        }
+        if self.ordinal_dot && word.len() <= 2 && word.chars().all(|c| c.is_ascii_digit()) {
+            return true;
+        }
+        if self.abbreviations.contains(word) {
+            return true;
+        }
+        // Lowercase entries also match when capitalized at a sentence start
+        let lowercase = word.to_lowercase();
+        lowercase != word && self.abbreviations.contains(&lowercase)
+    }
+}
+
+fn starts_lowercase(text: &str) -> bool {
+    text.chars().next().is_some_and(char::is_lowercase)
+}
+
+/// Open brackets and quotes at a point in the text. Nothing stays open past
+/// a paragraph break, so one unpaired quote cannot swallow the rest of the
+/// input.
+#[derive(Default)]
+struct Nesting {
+    brackets: usize,
+    quotes: usize,
+    straight_quote: bool,
+    /// Last character scanned, carried over between calls
+    previous: Option<char>,
+    /// Line breaks since the last non-whitespace character
+    newlines: usize,
+}
+
+impl Nesting {
+    fn scan(&mut self, text: &str) {
+        for c in text.chars() {
+            match c {
+                '\n' => {
+                    self.newlines += 1;
+                    if self.newlines == 2 {
+                        *self = Nesting {
+                            newlines: self.newlines,
+                            ..Nesting::default()
+                        };
+                    }
+                }
+                c if c.is_whitespace() => {}
+                _ => self.newlines = 0,
+            }
+            match c {
+                '(' | '[' | '（' => self.brackets += 1,
+                ')' | ']' | '）' => self.brackets = self.brackets.saturating_sub(1),
+                '“' | '«' | '「' | '『' => self.quotes += 1,
+                '”' | '»' | '」' | '』' => self.quotes = self.quotes.saturating_sub(1),
+                // After a digit it is an inch mark, as in 12" pizza
+                '"' if !self.previous.is_some_and(|p| p.is_ascii_digit()) => {
+                    self.straight_quote = !self.straight_quote
+                }
+                _ => {}
+            }
+            self.previous = Some(c);
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.brackets > 0 || self.quotes > 0 || self.straight_quote
+    }
+}
+
+/// Segmenters for every language, with the abbreviations of an optional
+/// configuration file added to the built-in ones
+pub struct Segmenters {
+    languages: HashMap<String, Arc<Segmenter>>,
+}
+
+impl Default for Segmenters {
+    fn default() -> Self {
+        Self::new(AbbreviationTable::new())
+    }
+}
+
+impl Segmenters {
+    pub fn new(extra: AbbreviationTable) -> Self {
+        let mut extra: HashMap<String, Vec<String>> = extra
+            .into_iter()
+            .fold(HashMap::new(), |mut merged, (language, abbreviations)| {
+                merged.entry(primary_subtag(&language)).or_default().extend(abbreviations);
+                merged
+            });
+
+        let mut languages = HashMap::new();
+        for language in ["en", "de", "fr", "es", "it", "pt"] {
+            let segmenter = Segmenter::new(language, extra.remove(language).unwrap_or_default());
+            languages.insert(language.to_string(), Arc::new(segmenter));
+        }
+        for (language, abbreviations) in extra {
+            languages.insert(language.clone(), Arc::new(Segmenter::new(&language, abbreviations)));
+        }
+        Segmenters { languages }
+    }
+
+    /// Load extra abbreviations from a JSON file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open abbreviations file {}", path.display()))?;
+        let extra: AbbreviationTable = serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("Failed to parse abbreviations file {}", path.display()))?;
+        Ok(Self::new(extra))
+    }
+
+    /// Segmenter for a language code such as "en" or "de-AT"; languages
+    /// without abbreviations still get the general rules
+    pub fn get(&self, language: &str) -> Arc<Segmenter> {
+        let primary = primary_subtag(language);
+        match self.languages.get(&primary) {
+            Some(segmenter) => Arc::clone(segmenter),
+            None => Arc::new(Segmenter::new(&primary, Vec::new())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(language: &str, text: &str) -> Vec<String> {
+        Segmenter::new(language, Vec::new())
+            .split(text)
+            .into_iter()
+            .map(|sentence| sentence.trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn english() {
+        let cases: &[(&str, &[&str])] = &[
+            ("Dr. Smith arrived. He sat down.", &["Dr. Smith arrived.", "He sat down."]),
+            ("I met Mr. and Mrs. Jones, i.e. my neighbours.", &["I met Mr. and Mrs. Jones, i.e. my neighbours."]),
+            ("Written by J. R. R. Tolkien. A classic.", &["Written by J. R. R. Tolkien.", "A classic."]),
+            ("Pi is 3.14 or so. Roughly.", &["Pi is 3.14 or so.", "Roughly."]),
+            ("Wait... what? Yes!", &["Wait... what?", "Yes!"]),
+            ("He paused... Then he left.", &["He paused...", "Then he left."]),
+            ("She said \"Stop. Now.\" Then she left.", &["She said \"Stop. Now.\"", "Then she left."]),
+            ("He left (at noon. Or so.) and came back.", &["He left (at noon. Or so.) and came back."]),
+            ("“Go. Run.” And so he ran.", &["“Go. Run.”", "And so he ran."]),
+            ("It's a 12\" pizza. It was good. Very good.", &["It's a 12\" pizza.", "It was good.", "Very good."]),
+            (
+                "An \"unpaired quote. Still one.\n\nNew paragraph. Two sentences.",
+                &["An \"unpaired quote. Still one.", "New paragraph.", "Two sentences."],
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(split("en", text), *expected, "text: {}", text);
+        }
+    }
+
+    #[test]
+    fn german() {
+        assert_eq!(
+            split("de", "Wir treffen uns am 3. Mai in Berlin. Das ist z.B. möglich."),
+            ["Wir treffen uns am 3. Mai in Berlin.", "Das ist z.B. möglich."]
+        );
+        assert_eq!(split("de-AT", "Es kam Hr. Meier. Danach kam niemand."), ["Es kam Hr. Meier.", "Danach kam niemand."]);
+        // English has no dotted ordinals
+        assert_eq!(split("en", "We met on day 3. Mai was there."), ["We met on day 3.", "Mai was there."]);
+    }
+
+    #[test]
+    fn cjk_terminators() {
+        assert_eq!(split("zh", "你好。今天天气很好！你呢？"), ["你好。", "今天天气很好！", "你呢？"]);
+        assert_eq!(split("ja", "「行こう。」と彼は言った。"), ["「行こう。」", "と彼は言った。"]);
+        assert_eq!(split("en", "It costs 5元。Cheap. Right?"), ["It costs 5元。", "Cheap.", "Right?"]);
+    }
+
+    #[test]
+    fn extra_abbreviations() {
+        let segmenters = Segmenters::new(AbbreviationTable::from([("en".to_string(), vec!["Approx.".to_string()])]));
+        assert_eq!(
+            segmenters.get("en-US").split("Approx. ten people came. Then more."),
+            ["Approx. ten people came. ", "Then more."]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+// SSML Module - Turns the supported subset of SSML into synthesis segments
+//
+// Supported elements: <speak>, <p>, <s>, <break>, <prosody rate>, <voice name>,
+// <say-as>, <sub alias>, plus <emphasis>, <lang>, <phoneme> and unknown
+// elements, whose text is spoken as is. Pitch and volume are not supported by
+// the model and are ignored.
+use anyhow::{anyhow, bail, Result};
+use roxmltree::{Document, Node};
+
+use crate::helper::TextSegment;
+
+/// Longest pause a single `<break>` may request, in seconds
+const MAX_BREAK_SECONDS: f32 = 10.0;
+
+/// Range accepted for a `<prosody rate>` multiplier
+const MIN_RATE: f32 = 0.25;
+const MAX_RATE: f32 = 4.0;
+
+/// Whether `input` is an SSML document rather than plain text
+pub fn is_ssml(input: &str) -> bool {
+    let mut input = input.trim_start();
+    if input.starts_with("<?xml") {
+        input = input.find("?>").map(|end| input[end + 2..].trim_start()).unwrap_or("");
+    }
+    input.starts_with("<speak")
+}
+
+/// Parse an SSML document into segments of uniform voice and rate
+pub fn parse_ssml(input: &str) -> Result<Vec<TextSegment>> {
+    let document = Document::parse(input).map_err(|e| anyhow!("Invalid SSML: {}", e))?;
+    let root = document.root_element();
+    if root.tag_name().name() != "speak" {
+        bail!("Invalid SSML: the root element must be <speak>, found <{}>", root.tag_name().name());
+    }
+
+    let mut builder = SegmentBuilder::default();
+    let context = Context { rate: 1.0, voice: None };
+    walk(root, &context, &mut builder)?;
+    Ok(builder.finish())
+}
+
+/// Prosody and voice in effect at a point in the document
+#[derive(Clone)]
+struct Context {
+    rate: f32,
+    voice: Option<String>,
+}
+
+fn walk(node: Node, context: &Context, builder: &mut SegmentBuilder) -> Result<()> {
+    for child in node.children() {
+        if child.is_text() {
+            builder.push_text(child.text().unwrap_or_default(), context);
+            continue;
+        }
+        if !child.is_element() {
+            continue;
+        }
+
+        match child.tag_name().name() {
+            "break" => builder.push_break(break_duration(child)?),
+            "p" => {
+                builder.push_paragraph();
+                walk(child, context, builder)?;
+                builder.push_paragraph();
+            }
+            "s" => {
+                builder.push_text(" ", context);
+                walk(child, context, builder)?;
+                builder.push_text(" ", context);
+            }
+            "prosody" => {
+                let mut inner = context.clone();
+                if let Some(rate) = child.attribute("rate") {
+                    inner.rate = (context.rate * parse_rate(rate)?).clamp(MIN_RATE, MAX_RATE);
+                }
+                walk(child, &inner, builder)?;
+            }
+            "voice" => {
+                let mut inner = context.clone();
+                if let Some(name) = child.attribute("name") {
+                    inner.voice = Some(name.trim().to_string());
+                }
+                walk(child, &inner, builder)?;
+            }
+            "say-as" => {
+                let text = element_text(child);
+                let spoken = match child.attribute("interpret-as").unwrap_or_default() {
+                    "characters" | "spell-out" | "verbatim" => spell_out(&text),
+                    "digits" => read_digits(&text),
+                    // Numbers, dates and the like are left to text normalization
+                    _ => text,
+                };
+                builder.push_text(&spoken, context);
+            }
+            "sub" => match child.attribute("alias") {
+                Some(alias) => builder.push_text(alias, context),
+                None => walk(child, context, builder)?,
+            },
+            "mark" | "desc" => {}
+            _ => walk(child, context, builder)?,
+        }
+    }
+    Ok(())
+}
+
+fn element_text(node: Node) -> String {
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+/// Pause for a `<break>`, from its `time` or else its `strength`
+fn break_duration(node: Node) -> Result<f32> {
+    let seconds = match node.attribute("time") {
+        Some(time) => parse_time(time)?,
+        None => match node.attribute("strength").unwrap_or("medium") {
+            "none" => 0.0,
+            "x-weak" => 0.1,
+            "weak" => 0.2,
+            "medium" => 0.4,
+            "strong" => 0.7,
+            "x-strong" => 1.2,
+            other => bail!("Invalid SSML: unknown break strength '{}'", other),
+        },
+    };
+    if seconds > MAX_BREAK_SECONDS {
+        bail!("Invalid SSML: breaks may be at most {} seconds", MAX_BREAK_SECONDS);
+    }
+    Ok(seconds)
+}
+
+/// Parse an SSML time value such as "500ms" or "1.5s"
+fn parse_time(value: &str) -> Result<f32> {
+    let value = value.trim();
+    let (number, scale) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(s) = value.strip_suffix('s') {
+        (s, 1.0)
+    } else {
+        bail!("Invalid SSML: break time '{}' must end in 's' or 'ms'", value);
+    };
+    match number.trim().parse::<f32>() {
+        Ok(n) if n >= 0.0 && n.is_finite() => Ok(n * scale),
+        _ => bail!("Invalid SSML: invalid break time '{}'", value),
+    }
+}
+
+/// Parse a `<prosody rate>` value into a speed multiplier
+fn parse_rate(value: &str) -> Result<f32> {
+    let value = value.trim();
+    let rate = match value {
+        "x-slow" => 0.5,
+        "slow" => 0.75,
+        "medium" | "default" => 1.0,
+        "fast" => 1.25,
+        "x-fast" => 1.5,
+        _ => {
+            let parsed = if let Some(percent) = value.strip_suffix('%') {
+                // "+20%" and "-20%" are relative changes, "120%" is absolute
+                percent.parse::<f32>().ok().map(|p| {
+                    if value.starts_with('+') || value.starts_with('-') {
+                        1.0 + p / 100.0
+                    } else {
+                        p / 100.0
+                    }
+                })
+            } else {
+                value.parse::<f32>().ok()
+            };
+            match parsed {
+                Some(rate) if rate > 0.0 && rate.is_finite() => rate,
+                _ => bail!("Invalid SSML: invalid prosody rate '{}'", value),
+            }
+        }
+    };
+    Ok(rate)
+}
+
+fn spell_out(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn read_digits(text: &str) -> String {
+    const DIGITS: [&str; 10] = ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine"];
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c.to_digit(10) {
+            Some(d) => DIGITS[d as usize].to_string(),
+            None => c.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Collects text into segments, starting a new one whenever the voice or
+/// rate changes or a break is requested
+#[derive(Default)]
+struct SegmentBuilder {
+    segments: Vec<TextSegment>,
+    text: String,
+    rate: f32,
+    voice: Option<String>,
+    pending_pause: Option<f32>,
+}
+
+impl SegmentBuilder {
+    fn push_text(&mut self, text: &str, context: &Context) {
+        let has_words = text.chars().any(|c| !c.is_whitespace());
+        if has_words && !self.text.trim().is_empty() && (context.rate != self.rate || context.voice != self.voice) {
+            self.flush();
+        }
+        if self.text.trim().is_empty() {
+            self.rate = context.rate;
+            self.voice = context.voice.clone();
+        }
+
+        // Collapse markup whitespace; paragraph breaks are added explicitly
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !self.text.is_empty() && !self.text.ends_with(char::is_whitespace) {
+                    self.text.push(' ');
+                }
+            } else {
+                self.text.push(c);
+            }
+        }
+    }
+
+    fn push_paragraph(&mut self) {
+        if !self.text.trim().is_empty() {
+            let trimmed = self.text.trim_end().len();
+            self.text.truncate(trimmed);
+            self.text.push_str("\n\n");
+        }
+    }
+
+    fn push_break(&mut self, seconds: f32) {
+        self.flush();
+        *self.pending_pause.get_or_insert(0.0) += seconds;
+    }
+
+    fn flush(&mut self) {
+        let text = self.text.trim();
+        if !text.is_empty() {
+            self.segments.push(TextSegment {
+                text: text.to_string(),
+                voice: self.voice.clone(),
+                rate: self.rate,
+                pause_before: self.pending_pause.take(),
+            });
+        }
+        self.text.clear();
+    }
+
+    fn finish(mut self) -> Vec<TextSegment> {
+        self.flush();
+        // A trailing break becomes a silent segment
+        if let Some(pause) = self.pending_pause.take() {
+            self.segments.push(TextSegment {
+                text: String::new(),
+                voice: None,
+                rate: 1.0,
+                pause_before: Some(pause),
+            });
+        }
+        self.segments
+    }
+}
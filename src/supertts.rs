@@ -1,7 +1,7 @@
 use anyhow::Result;
 use clap::Parser;
 use tracing_subscriber::{prelude::*};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::mem;
 use tracing::info;
@@ -9,11 +9,31 @@ use tracing::info;
 mod helper;
 mod api_server;
 mod engine_pool;
+mod flac_encoder;
+mod g711;
+mod inference;
+mod lexicon;
+mod markup;
+mod mp3_encoder;
+mod normalizer;
+mod opus_encoder;
+mod resampler;
+mod scheduler;
+mod segmenter;
+mod ssml;
+mod voices;
 
 use helper::{
-    load_text_to_speech, load_voice_style, timer, write_wav_file, sanitize_filename,
+    blend_voice_styles, convert_voice_style, input_segments, load_text_to_speech, load_voice_style,
+    save_voice_style, timer, voice_style_stem, write_audio_file, sanitize_filename, AudioFormat, ChunkSettings,
+    EncoderSettings, SplitStrategy, UnknownCharPolicy, VoiceStyleFormat, VoiceStyles,
 };
-use api_server::{start_server, ServerConfig};
+use api_server::{resolve_voice_style_path, start_server, ServerConfig};
+use lexicon::Lexicon;
+use markup::{to_speakable_text, InputFormat};
+use normalizer::normalize_text;
+use segmenter::{is_valid_language, Segmenters};
+use ssml::is_ssml;
 
 #[derive(Parser, Debug)]
 #[command(name = "TTS ONNX Inference")]
@@ -59,19 +79,168 @@ struct Args {
     #[arg(long, value_delimiter = ',', default_values_t = vec!["assets/voice_styles/M1.json".to_string()])]
     voice_style: Vec<String>,
 
+    /// Blend voices by weight instead of using --voice-style, e.g.
+    /// "M1:0.7,F1:0.3" (voice names or style files)
+    #[arg(long, value_delimiter = ',')]
+    voice_mix: Vec<String>,
+
+    /// Write the voice style (e.g. a --voice-mix blend) to this file, as
+    /// safetensors if it ends in .safetensors and JSON otherwise, and exit
+    /// without synthesizing
+    #[arg(long)]
+    save_voice: Option<String>,
+
+    /// Convert voice style files, or all style files in the given
+    /// directories, to --voice-format and exit. Each converted file is
+    /// written next to its original.
+    #[arg(long, value_delimiter = ',')]
+    convert_voice: Vec<String>,
+
+    /// Format written by --convert-voice: json or safetensors
+    #[arg(long, default_value = "safetensors")]
+    voice_format: String,
+
     /// Text(s) to synthesize
     #[arg(long, value_delimiter = '|', default_values_t = vec!["This morning, I took a walk in the park, and the sound of the birds and the breeze was so pleasant that I stopped for a long time just to listen.".to_string()])]
     text: Vec<String>,
 
+    /// How the text is written: text (plain text or SSML), markdown or html
+    #[arg(long, default_value = "text")]
+    input_format: String,
+
+    /// Phrase spoken in place of code blocks in Markdown or HTML input
+    /// (default: code blocks are skipped)
+    #[arg(long)]
+    code_block_phrase: Option<String>,
+
     /// Output directory
     #[arg(long, default_value = "results")]
     save_dir: String,
 
+    /// Output file format: wav, wav_s24, wav_f32, wav_ulaw, wav_alaw, pcm, ulaw, alaw, flac, mp3 or opus
+    #[arg(long, default_value = "wav")]
+    format: String,
+
+    /// Resample output to this rate in Hz: 8000, 16000, 22050, 24000, 44100 or 48000
+    #[arg(long)]
+    sample_rate: Option<u32>,
+
+    /// Pronunciation lexicon file (JSON) applied before normalization
+    #[arg(long)]
+    lexicon: Option<String>,
+
+    /// Feed text to the model as written, without spelling out numbers,
+    /// currency, dates, times, percentages and units
+    #[arg(long, default_value = "false")]
+    no_normalize: bool,
+
+    /// Language of the text, e.g. en or de; selects the sentence splitting
+    /// rules and the normalizer
+    #[arg(long, default_value = "en")]
+    language: String,
+
+    /// JSON file of extra abbreviations per language for sentence splitting
+    #[arg(long)]
+    abbreviations: Option<String>,
+
+    /// Characters the model cannot read: strict (fail), transliterate, strip
+    /// or replace (with a space)
+    #[arg(long, default_value = "transliterate")]
+    unknown_chars: String,
+
+    /// Longest text chunk synthesized in one pass, in characters (default: 300)
+    #[arg(long)]
+    max_chunk_chars: Option<usize>,
+
+    /// Merge chunks shorter than this many characters with a neighbour (default: 0)
+    #[arg(long)]
+    min_chunk_chars: Option<usize>,
+
+    /// Where chunks start: paragraph (default), sentence or clause
+    #[arg(long)]
+    split_strategy: Option<String>,
+
+    /// Silence between chunks of a paragraph, in seconds (default: 0.3)
+    #[arg(long)]
+    sentence_silence: Option<f32>,
+
+    /// Silence between paragraphs, in seconds (default: 0.3)
+    #[arg(long)]
+    paragraph_silence: Option<f32>,
+
     /// Enable batch mode (multiple text-style pairs)
     #[arg(long, default_value = "false")]
     batch: bool,
 }
 
+fn parse_split_strategy(value: Option<&str>) -> Result<Option<SplitStrategy>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    match SplitStrategy::parse(value) {
+        Some(strategy) => Ok(Some(strategy)),
+        None => anyhow::bail!(
+            "Unsupported split strategy '{}'. Supported strategies: {}",
+            value,
+            SplitStrategy::SUPPORTED.join(", ")
+        ),
+    }
+}
+
+/// Parse `--voice-mix` entries of the form `voice:weight`
+fn parse_voice_mix(entries: &[String]) -> Result<Vec<(String, f32)>> {
+    entries
+        .iter()
+        .map(|entry| {
+            let Some((voice, weight)) = entry.rsplit_once(':') else {
+                anyhow::bail!("Invalid voice mix entry '{}', expected voice:weight", entry);
+            };
+            let Ok(weight) = weight.trim().parse::<f32>() else {
+                anyhow::bail!("Invalid weight '{}' in voice mix entry '{}'", weight, entry);
+            };
+            Ok((voice.trim().to_string(), weight))
+        })
+        .collect()
+}
+
+/// Convert the style files named by `--convert-voice`, skipping those of a
+/// directory that are already in `format`
+fn convert_voice_styles(paths: &[String], format: &str) -> Result<()> {
+    let Some(format) = VoiceStyleFormat::parse(format) else {
+        anyhow::bail!(
+            "Unsupported voice format '{}'. Supported formats: {}",
+            format,
+            VoiceStyleFormat::SUPPORTED.join(", ")
+        );
+    };
+
+    let mut files = Vec::new();
+    for path in paths {
+        if !Path::new(path).is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut entries: Vec<String> = fs::read_dir(path)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|file| {
+                let name = file.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+                voice_style_stem(name).is_some() && VoiceStyleFormat::of(file) != format
+            })
+            .map(|file| file.to_string_lossy().into_owned())
+            .collect();
+        entries.sort();
+        files.extend(entries);
+    }
+
+    for file in &files {
+        let output = convert_voice_style(file, format)?;
+        println!("Converted {} -> {}", file, output);
+    }
+    println!("Converted {} voice style file(s)", files.len());
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     println!("=== TTS Inference with ONNX Runtime (Rust) ===\n");
@@ -102,6 +271,21 @@ async fn main() -> Result<()> {
         if let Some(speed) = args.speed {
             server_config.tts.speed = speed;
         }
+        if let Some(max_chunk_chars) = args.max_chunk_chars {
+            server_config.tts.max_chunk_chars = max_chunk_chars;
+        }
+        if let Some(min_chunk_chars) = args.min_chunk_chars {
+            server_config.tts.min_chunk_chars = min_chunk_chars;
+        }
+        if let Some(split_strategy) = parse_split_strategy(args.split_strategy.as_deref())? {
+            server_config.tts.split_strategy = split_strategy;
+        }
+        if let Some(sentence_silence) = args.sentence_silence {
+            server_config.tts.sentence_silence = sentence_silence;
+        }
+        if let Some(paragraph_silence) = args.paragraph_silence {
+            server_config.tts.paragraph_silence = paragraph_silence;
+        }
 
         let log_filter = format!("{},ort={}", server_config.logging.level, server_config.logging.ort_level);
 
@@ -118,6 +302,10 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    if !args.convert_voice.is_empty() {
+        return convert_voice_styles(&args.convert_voice, &args.voice_format);
+    }
+
     // --- CLI Mode (original functionality) --- //
     let total_step = args.total_step.unwrap_or(5);
     let speed = args.speed.unwrap_or(1.05);
@@ -126,22 +314,129 @@ async fn main() -> Result<()> {
     let text_list = &args.text;
     let save_dir = &args.save_dir;
     let batch = args.batch;
+    let Some(format) = AudioFormat::parse(&args.format) else {
+        anyhow::bail!(
+            "Unsupported output format '{}'. Supported formats: {}",
+            args.format,
+            AudioFormat::SUPPORTED.join(", ")
+        );
+    };
+    let encoder_settings = EncoderSettings {
+        sample_rate: format.output_sample_rate(args.sample_rate)?,
+        ..EncoderSettings::default()
+    };
+    let Some(unknown_char_policy) = UnknownCharPolicy::parse(&args.unknown_chars) else {
+        anyhow::bail!(
+            "Unsupported unknown character policy '{}'. Supported policies: {}",
+            args.unknown_chars,
+            UnknownCharPolicy::SUPPORTED.join(", ")
+        );
+    };
+    let Some(input_format) = InputFormat::parse(&args.input_format) else {
+        anyhow::bail!(
+            "Unsupported input format '{}'. Supported formats: {}",
+            args.input_format,
+            InputFormat::SUPPORTED.join(", ")
+        );
+    };
+    if !is_valid_language(&args.language) {
+        anyhow::bail!("Invalid language code '{}'", args.language);
+    }
+    let segmenters = match &args.abbreviations {
+        Some(path) => Segmenters::load(path)?,
+        None => Segmenters::default(),
+    };
+    let defaults = ChunkSettings::default();
+    let chunking = ChunkSettings {
+        max_chunk_chars: args.max_chunk_chars.unwrap_or(defaults.max_chunk_chars),
+        min_chunk_chars: args.min_chunk_chars.unwrap_or(defaults.min_chunk_chars),
+        strategy: parse_split_strategy(args.split_strategy.as_deref())?.unwrap_or(defaults.strategy),
+        sentence_silence: args.sentence_silence.unwrap_or(defaults.sentence_silence),
+        paragraph_silence: args.paragraph_silence.unwrap_or(defaults.paragraph_silence),
+        segmenter: segmenters.get(&args.language),
+    };
+    chunking.validate()?;
+
+    // Batch synthesis takes plain strings, so SSML breaks, prosody and
+    // voices have nothing to map to
+    if batch && input_format == InputFormat::Text && text_list.iter().any(|text| is_ssml(text)) {
+        anyhow::bail!("SSML input cannot be combined with --batch; synthesize SSML texts one at a time");
+    }
 
-    if batch {
-        if voice_style_paths.len() != text_list.len() {
-            anyhow::bail!(
-                "Number of voice styles ({}) must match number of texts ({})",
-                voice_style_paths.len(),
-                text_list.len()
-            );
-        }
+    if batch && voice_style_paths.len() != text_list.len() {
+        anyhow::bail!(
+            "Number of voice styles ({}) must match number of texts ({})",
+            voice_style_paths.len(),
+            text_list.len()
+        );
     }
 
     let bsz = voice_style_paths.len();
 
-    let mut text_to_speech = load_text_to_speech(&args.onnx_dir.as_deref().unwrap_or("assets/onnx"), args.use_gpu.unwrap_or(false))?;
+    // Voice names are looked up in the registry of the config file
+    let voices = ServerConfig::load_or_default(&args.config).tts.voice_registry()?;
 
-    let style = load_voice_style(voice_style_paths, true)?;
+    let style = if args.voice_mix.is_empty() {
+        load_voice_style(voice_style_paths, true)?
+    } else {
+        if batch {
+            anyhow::bail!("--voice-mix cannot be combined with --batch");
+        }
+        let mut components = Vec::new();
+        for (voice, weight) in parse_voice_mix(&args.voice_mix)? {
+            let path = resolve_voice_style_path(&voices, Some(&voice), &voice_style_paths[0])?;
+            components.push((load_voice_style(&[path], false)?, weight));
+        }
+        let style = blend_voice_styles(&components)?;
+        println!("Blended {} voice styles\n", components.len());
+        style
+    };
+
+    if let Some(path) = &args.save_voice {
+        save_voice_style(&style, path)?;
+        println!("Saved voice style to {}", path);
+        return Ok(());
+    }
+
+    let mut text_to_speech = load_text_to_speech(args.onnx_dir.as_deref().unwrap_or("assets/onnx"), args.use_gpu.unwrap_or(false))?;
+
+    // Non-batch text may be SSML, whose <voice> elements need their own styles
+    let code_block_phrase = args.code_block_phrase.as_deref();
+    let mut segments = if batch { Vec::new() } else { input_segments(&text_list[0], input_format, code_block_phrase)? };
+    let mut batch_texts: Vec<String> = text_list
+        .iter()
+        .map(|text| to_speakable_text(text, input_format, code_block_phrase))
+        .collect();
+    let lexicon = match &args.lexicon {
+        Some(path) if !PathBuf::from(path).exists() => anyhow::bail!("Lexicon file not found: {}", path),
+        Some(path) => Some(Lexicon::load(path)?),
+        None => None,
+    };
+    for text in segments.iter_mut().map(|segment| &mut segment.text).chain(batch_texts.iter_mut()) {
+        if let Some(lexicon) = &lexicon {
+            *text = lexicon.apply(text);
+        }
+        if !args.no_normalize {
+            *text = normalize_text(text, &args.language);
+        }
+        *text = text_to_speech
+            .text_processor()
+            .apply_unknown_char_policy(text, unknown_char_policy, " ")
+            .map_err(|unsupported| {
+                let described = unsupported
+                    .iter()
+                    .map(|u| format!("'{}' ({})", u.character, u.code_point))
+                    .collect::<Vec<_>>();
+                anyhow::anyhow!("Text contains characters the model cannot read: {}", described.join(", "))
+            })?;
+    }
+    let mut styles = VoiceStyles::single(style.clone());
+    for voice in segments.iter().filter_map(|segment| segment.voice.as_deref()) {
+        if !styles.named.contains_key(voice) {
+            let path = resolve_voice_style_path(&voices, Some(voice), &voice_style_paths[0])?;
+            styles.named.insert(voice.to_string(), load_voice_style(&[path], false)?);
+        }
+    }
 
     fs::create_dir_all(save_dir)?;
 
@@ -150,18 +445,18 @@ async fn main() -> Result<()> {
 
         let (wav, duration) = if batch {
             timer("Generating speech from text", || {
-                text_to_speech.batch(text_list, &style, total_step, speed)
+                text_to_speech.batch(&batch_texts, &style, total_step, speed)
             })?
         } else {
             let (w, d) = timer("Generating speech from text", || {
-                text_to_speech.call(&text_list[0], &style, total_step, speed, 0.3)
+                text_to_speech.call_segments(&segments, &styles, total_step, speed, &chunking)
             })?;
             (w, vec![d])
         };
 
         // Save outputs
         for i in 0..bsz {
-            let fname = format!("{}_{}.wav", sanitize_filename(&text_list[i], 20), n + 1);
+            let fname = format!("{}_{}.{}", sanitize_filename(&text_list[i], 20), n + 1, format.extension());
             let wav_slice = if batch {
                 let wav_len = wav.len() / bsz;
                 let actual_len = (text_to_speech.sample_rate as f32 * duration[i]) as usize;
@@ -175,7 +470,7 @@ async fn main() -> Result<()> {
             };
 
             let output_path = PathBuf::from(save_dir).join(&fname);
-            write_wav_file(&output_path, wav_slice, text_to_speech.sample_rate)?;
+            write_audio_file(&output_path, format, wav_slice, text_to_speech.sample_rate, &encoder_settings)?;
             println!("Saved: {}", output_path.display());
         }
     }
@@ -0,0 +1,192 @@
+// Voices Module - Registry of the named voice styles
+//
+// Every voice has a canonical ID, aliases it can also be requested by
+// (including OpenAI voice names such as "alloy" or "nova"), its style file and
+// the metadata listed by `GET /voices`. A voice's speed applies to requests
+// that do not set one. Style file paths are relative to the voice directory
+// unless absolute.
+//
+// Voices uploaded through the API are kept apart from the configured ones, so
+// that only they can be deleted and only they are written to the uploaded
+// voices file.
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// A voice in the registry, as written in the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoiceEntry {
+    pub id: String,
+    /// Other names the voice can be requested by
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Voice style file, relative to the voice directory unless absolute
+    pub path: String,
+    /// Language code, e.g. "en"
+    pub language: Option<String>,
+    pub gender: Option<String>,
+    pub description: Option<String>,
+    /// Speed used when a request does not set one (0.25 to 4.0)
+    pub speed: Option<f32>,
+}
+
+impl VoiceEntry {
+    /// The ID and aliases
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.id.as_str()).chain(self.aliases.iter().map(String::as_str))
+    }
+}
+
+/// The voices that ship with the model
+pub fn default_voices() -> Vec<VoiceEntry> {
+    let voice = |id: &str, aliases: &[&str], gender: &str, description: &str| VoiceEntry {
+        id: id.to_string(),
+        aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+        path: format!("{}.json", id),
+        language: Some("en".to_string()),
+        gender: Some(gender.to_string()),
+        description: Some(description.to_string()),
+        speed: None,
+    };
+    vec![
+        voice("M1", &["m1", "male1", "alloy", "echo", "ballad"], "male", "Male voice 1"),
+        voice("M2", &["m2", "male2", "onyx", "fable", "ash", "verse"], "male", "Male voice 2"),
+        voice("F1", &["f1", "female1", "nova", "shimmer"], "female", "Female voice 1"),
+        voice("F2", &["f2", "female2", "coral", "sage"], "female", "Female voice 2"),
+    ]
+}
+
+/// Whether `name` can be a voice ID or alias: 1 to 64 ASCII letters,
+/// digits, '-' or '_', so that it is also a safe file name
+pub fn is_valid_voice_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 64 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The configured and uploaded voices and the directory their style files
+/// live in
+#[derive(Debug, Clone)]
+pub struct VoiceRegistry {
+    dir: String,
+    voices: Vec<VoiceEntry>,
+    uploaded: Vec<VoiceEntry>,
+}
+
+impl Default for VoiceRegistry {
+    fn default() -> Self {
+        VoiceRegistry {
+            dir: "assets/voice_styles".to_string(),
+            voices: default_voices(),
+            uploaded: Vec::new(),
+        }
+    }
+}
+
+impl VoiceRegistry {
+    /// Check that IDs and aliases are unique (ignoring case) and speeds are
+    /// in range
+    pub fn new(dir: &str, voices: Vec<VoiceEntry>) -> Result<Self> {
+        let registry = VoiceRegistry {
+            dir: dir.to_string(),
+            voices,
+            uploaded: Vec::new(),
+        };
+        registry.validate()?;
+        Ok(registry)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut owners: HashMap<String, &str> = HashMap::new();
+        for voice in self.voices() {
+            if voice.id.trim().is_empty() {
+                anyhow::bail!("Voice IDs must not be empty");
+            }
+            if voice.path.trim().is_empty() {
+                anyhow::bail!("Voice '{}' has no style file path", voice.id);
+            }
+            if let Some(speed) = voice.speed {
+                if !(0.25..=4.0).contains(&speed) {
+                    anyhow::bail!("Speed of voice '{}' must be between 0.25 and 4.0, got {}", voice.id, speed);
+                }
+            }
+            let mut names: Vec<String> = voice.names().map(str::to_lowercase).collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                if let Some(owner) = owners.insert(name.clone(), &voice.id) {
+                    anyhow::bail!("Voice name '{}' is used by both '{}' and '{}'", name, owner, voice.id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the voices listed in an uploaded voices file, if it exists
+    pub fn load_uploaded<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(());
+        }
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open uploaded voices file {}", path.display()))?;
+        let uploaded: Vec<VoiceEntry> = serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("Failed to parse uploaded voices file {}", path.display()))?;
+        self.uploaded = uploaded;
+        self.validate()
+    }
+
+    /// Write the uploaded voices to `path`, replacing the file atomically
+    pub fn save_uploaded<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, serde_json::to_vec_pretty(&self.uploaded)?)
+            .with_context(|| format!("Failed to write uploaded voices file {}", temp_path.display()))?;
+        std::fs::rename(&temp_path, path)
+            .with_context(|| format!("Failed to replace uploaded voices file {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Register an uploaded voice; its names must not be taken
+    pub fn add_uploaded(&mut self, voice: VoiceEntry) -> Result<()> {
+        self.uploaded.push(voice);
+        if let Err(e) = self.validate() {
+            self.uploaded.pop();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Unregister the uploaded voice with this ID
+    pub fn remove_uploaded(&mut self, id: &str) -> Option<VoiceEntry> {
+        let index = self.uploaded.iter().position(|voice| voice.id == id)?;
+        Some(self.uploaded.remove(index))
+    }
+
+    pub fn is_uploaded(&self, id: &str) -> bool {
+        self.uploaded.iter().any(|voice| voice.id == id)
+    }
+
+    /// Directory that relative style file paths are resolved against
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+
+    /// The configured voices, then the uploaded ones
+    pub fn voices(&self) -> impl Iterator<Item = &VoiceEntry> {
+        self.voices.iter().chain(&self.uploaded)
+    }
+
+    /// Style file of `voice`
+    pub fn path_of(&self, voice: &VoiceEntry) -> String {
+        Path::new(&self.dir).join(&voice.path).to_string_lossy().into_owned()
+    }
+
+    /// The voice with this ID or alias (ignoring case), or with this style
+    /// file path
+    pub fn find(&self, name: &str) -> Option<&VoiceEntry> {
+        self.voices()
+            .find(|voice| voice.names().any(|voice_name| voice_name.eq_ignore_ascii_case(name)))
+            .or_else(|| self.voices().find(|voice| self.path_of(voice) == name))
+    }
+}
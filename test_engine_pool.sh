@@ -0,0 +1,112 @@
+#!/bin/bash
+
+# Concurrency test for the TTS engine pool
+#
+# Checks that N requests are synthesized by N engines in parallel: all of them
+# must succeed, the pool must report N engines leased at the same time, and
+# the concurrent batch should finish well before N sequential requests do.
+#
+# Start a fresh server with engine_pool_size >= N first, e.g.
+#   cargo run --release --bin supertts -- --openai --config config.json
+# then run
+#   ./test_engine_pool.sh [N] [base URL]
+#
+# Requires curl and jq. Set API_KEY when the server requires one.
+
+N=${1:-2}
+BASE_URL=${2:-http://localhost:8080}
+TEXT="The quick brown fox jumps over the lazy dog, and then it runs far away into the quiet forest."
+WORK_DIR=$(mktemp -d)
+trap 'rm -rf "$WORK_DIR"' EXIT
+
+AUTH_HEADER=()
+if [ -n "$API_KEY" ]; then
+    AUTH_HEADER=(-H "Authorization: Bearer $API_KEY")
+fi
+
+echo "=== superTTS Engine Pool Concurrency Test ($N requests) ==="
+echo ""
+
+pool_stat() {
+    curl -s "$BASE_URL/health" | jq -r ".pool_stats.$1 // empty"
+}
+
+# Prints the HTTP status of one speech request
+speech_request() {
+    curl -s -o "$WORK_DIR/out_$1.wav" -w "%{http_code}" \
+        -X POST "$BASE_URL/v1/audio/speech" \
+        -H "Content-Type: application/json" \
+        "${AUTH_HEADER[@]}" \
+        -d "{\"input\": \"$TEXT\", \"voice\": \"alloy\"}"
+}
+
+now() {
+    date +%s.%N
+}
+
+if [ -z "$(pool_stat total_engines)" ]; then
+    echo "❌ FAILED - no pool stats at $BASE_URL/health"
+    echo "Start the server with engine_pool_size >= $N (the pool is disabled for a size of 1)"
+    exit 1
+fi
+
+# Warm up, so that engine loading does not count towards the timings
+for i in $(seq 1 "$N"); do
+    speech_request "warmup_$i" > "$WORK_DIR/status_warmup_$i" &
+done
+wait
+
+echo "Sequential: $N requests"
+start=$(now)
+for i in $(seq 1 "$N"); do
+    status=$(speech_request "seq_$i")
+    if [ "$status" != "200" ]; then
+        echo "❌ FAILED - sequential request $i returned HTTP $status"
+        exit 1
+    fi
+done
+sequential=$(echo "$(now) - $start" | bc)
+echo "  ${sequential}s"
+
+echo "Concurrent: $N requests"
+start=$(now)
+for i in $(seq 1 "$N"); do
+    speech_request "par_$i" > "$WORK_DIR/status_par_$i" &
+done
+wait
+concurrent=$(echo "$(now) - $start" | bc)
+echo "  ${concurrent}s"
+
+failed=0
+for i in $(seq 1 "$N"); do
+    status=$(cat "$WORK_DIR/status_par_$i")
+    if [ "$status" != "200" ]; then
+        echo "❌ Concurrent request $i returned HTTP $status"
+        failed=1
+    fi
+done
+[ "$failed" = "0" ] || exit 1
+
+total=$(pool_stat total_engines)
+idle=$(pool_stat idle_engines)
+peak=$(pool_stat peak_leased_engines)
+echo ""
+echo "Engines: $total loaded, $idle idle, at most $peak leased at once"
+
+if [ "$peak" -lt "$N" ]; then
+    echo "❌ FAILED - expected $N engines leased at once, the pool reports $peak"
+    exit 1
+fi
+if [ "$idle" != "$total" ]; then
+    echo "❌ FAILED - $((total - idle)) engine(s) still leased after all requests finished"
+    exit 1
+fi
+
+speedup=$(echo "scale=2; $sequential / $concurrent" | bc)
+echo "Speedup over sequential requests: ${speedup}x"
+if [ "$(echo "$speedup < 1.2" | bc)" = "1" ]; then
+    echo "⚠️  Little speedup; the machine may have too few cores for $N engines"
+fi
+
+echo ""
+echo "✅ SUCCESS"